@@ -0,0 +1,437 @@
+// Static disassembly of PRG ROM into ca65 source that assembles back
+// to the same ROM, and a code/data log of what was found.
+//
+// Code is found by recursive descent: from the vectors, follow jumps,
+// branches and calls until RTS, RTI, BRK or an indirect jump. What is
+// never reached is written as data.
+//
+// Mappers are not known here. The last 16 KB bank is taken to be fixed
+// at $c000 and the others switched in at $8000, as on NROM, UxROM or
+// Bandai boards. Jumps into $8000-$bfff from the fixed bank are only
+// followed when a single bank can be there.
+//
+// Symbols found next to the ROM (see redwhite::symbols) name labels and
+// RAM, and source lines they tell are added as comments.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use redwhite::cdl::{self, Cdl};
+use redwhite::disasm::{self, Labels};
+use redwhite::error::Error;
+use redwhite::ines::Ines;
+use redwhite::opcode::{Instruction, Mode, OPCODES};
+use redwhite::symbols::Symbols;
+
+const BANK_SIZE: usize = 0x4000;
+
+// offsets of the vectors in the fixed bank
+const VECTORS: [(usize, &str); 3] = [(0x3ffa, "nmi"), (0x3ffc, "reset"), (0x3ffe, "irq")];
+
+struct Disassembler<'a> {
+    prg: &'a [u8],
+    banks: usize,
+    cdl: Cdl,
+    // where instructions start, by offset in PRG ROM
+    starts: Vec<bool>,
+    // offsets referred to by code or vectors
+    refs: BTreeSet<usize>,
+    labels: HashMap<usize, String>,
+    symbols: Symbols,
+    // names of RAM and registers, defined at the top of the source
+    equates: HashMap<u16, String>,
+}
+
+// the labels seen from code in a bank
+struct BankLabels<'a> {
+    dis: &'a Disassembler<'a>,
+    bank: usize,
+}
+
+impl<'a> Labels for BankLabels<'a> {
+    fn label(&self, addr: u16) -> Option<&str> {
+        if addr < 0x8000 {
+            return self.dis.equates.get(&addr).map(|s| s.as_str());
+        }
+        let offset = self.dis.resolve(self.bank, addr)?;
+        // a mirror of the bank is not where its labels are
+        if self.dis.addr_of(offset) != addr {
+            return None;
+        }
+        self.dis.labels.get(&offset).map(|s| s.as_str())
+    }
+}
+
+impl<'a> Disassembler<'a> {
+    fn new(rom: &'a Ines, symbols: Symbols) -> Self {
+        Disassembler {
+            prg: &rom.prgrom,
+            banks: rom.prgrom.len() / BANK_SIZE,
+            cdl: Cdl::new(rom.prgrom.len(), rom.chrrom.len()),
+            starts: vec![false; rom.prgrom.len()],
+            refs: BTreeSet::new(),
+            labels: HashMap::new(),
+            symbols,
+            equates: HashMap::new(),
+        }
+    }
+
+    fn fixed_bank(&self) -> usize {
+        self.banks - 1
+    }
+
+    fn base(&self, bank: usize) -> u16 {
+        if bank == self.fixed_bank() { 0xc000 } else { 0x8000 }
+    }
+
+    fn addr_of(&self, offset: usize) -> u16 {
+        self.base(offset / BANK_SIZE) + (offset % BANK_SIZE) as u16
+    }
+
+    // where an address used by code in `bank` is in PRG ROM
+    fn resolve(&self, bank: usize, addr: u16) -> Option<usize> {
+        match addr {
+            0xc000..=0xffff => Some(self.fixed_bank() * BANK_SIZE + (addr as usize - 0xc000)),
+            0x8000..=0xbfff if bank != self.fixed_bank() => Some(bank * BANK_SIZE + (addr as usize - 0x8000)),
+            // NROM, the window has a mirror of the only bank or bank 0
+            0x8000..=0xbfff if self.banks <= 2 => Some(addr as usize - 0x8000),
+            _ => None,
+        }
+    }
+
+    fn mark(&mut self, offset: usize, flag: u8) {
+        let addr = self.addr_of(offset);
+        self.cdl.prg[offset] |= flag | cdl::window(addr);
+    }
+
+    fn vector(&self, offset: usize) -> u16 {
+        self.prg[offset] as u16 | (self.prg[offset + 1] as u16) << 8
+    }
+
+    fn walk_vectors(&mut self) {
+        let fixed = self.fixed_bank();
+        for &(offset, name) in VECTORS.iter() {
+            let offset = fixed * BANK_SIZE + offset;
+            self.mark(offset, cdl::DATA);
+            self.mark(offset + 1, cdl::DATA);
+            let addr = self.vector(offset);
+            if let Some(target) = self.resolve(fixed, addr) {
+                self.labels.entry(target).or_insert_with(|| name.to_string());
+                self.refs.insert(target);
+                self.walk(target);
+            }
+        }
+    }
+
+    fn walk(&mut self, entry: usize) {
+        let mut queue = vec![entry];
+        while let Some(mut offset) = queue.pop() {
+            while !self.starts[offset] {
+                let bank = offset / BANK_SIZE;
+                let addr = self.addr_of(offset);
+                // instructions running off the end of the bank are not code
+                let decoded = match disasm::decode(addr, &self.prg[offset..(bank + 1) * BANK_SIZE]) {
+                    Some(decoded) if decoded.opcode.official => decoded,
+                    _ => break,
+                };
+                self.starts[offset] = true;
+                for i in 0..decoded.size() {
+                    self.mark(offset + i, cdl::CODE);
+                }
+                let op = decoded.opcode;
+                let target = decoded.target().and_then(|t| self.resolve(bank, t));
+                if let Some(target) = target {
+                    self.refs.insert(target);
+                }
+                match (op.instruction, op.mode) {
+                    (Instruction::Jmp, Mode::Indirect) => {
+                        if let Some(target) = target {
+                            self.mark(target, cdl::DATA);
+                        }
+                        break;
+                    }
+                    (Instruction::Jmp, _) => {
+                        queue.extend(target);
+                        break;
+                    }
+                    (Instruction::Rts, _) | (Instruction::Rti, _) | (Instruction::Brk, _) => break,
+                    (Instruction::Jsr, _) | (_, Mode::Relative) => queue.extend(target),
+                    (_, Mode::Absolute) | (_, Mode::AbsoluteX) | (_, Mode::AbsoluteY) => {
+                        if let Some(target) = target {
+                            self.mark(target, cdl::DATA);
+                        }
+                    }
+                    _ => {}
+                }
+                offset += decoded.size();
+                if offset % BANK_SIZE == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Where lines of source start: instructions as they come one after
+    // the other, and every byte of data. Labels only go there.
+    fn line_starts(&self) -> Vec<bool> {
+        let mut lines = vec![false; self.prg.len()];
+        let mut offset = 0;
+        while offset < self.prg.len() {
+            lines[offset] = true;
+            offset += self.instruction_size(offset).unwrap_or(1);
+        }
+        lines
+    }
+
+    // the size of the instruction at `offset`, None if it is data
+    fn instruction_size(&self, offset: usize) -> Option<usize> {
+        if !self.starts[offset] {
+            return None;
+        }
+        disasm::decode(0, &self.prg[offset..]).map(|d| d.size())
+    }
+
+    fn name_labels(&mut self) {
+        let lines = self.line_starts();
+        let fixed = self.fixed_bank();
+        // symbols first, names that can't be labels or are used twice are left out
+        let mut taken = HashSet::new();
+        let mut addrs: Vec<_> = self.symbols.addr_labels().iter().collect();
+        addrs.sort();
+        let mut equates = Vec::new();
+        for (&addr, name) in addrs {
+            if addr < 0x8000 && is_label(name) && taken.insert(name.clone()) {
+                equates.push((addr, name.clone()));
+            }
+        }
+        let mut named = Vec::new();
+        for offset in (0..self.prg.len()).filter(|&offset| lines[offset]) {
+            if let Some(name) = self.symbols.prg_label(offset) {
+                if is_label(name) && taken.insert(name.to_string()) {
+                    named.push((offset, name.to_string()));
+                }
+            }
+        }
+        self.equates.extend(equates);
+        self.labels.retain(|_, name| !taken.contains(name));
+        self.labels.extend(named);
+        for &offset in self.refs.iter().filter(|&&offset| lines[offset]) {
+            let bank = offset / BANK_SIZE;
+            let addr = self.addr_of(offset);
+            let name = if self.banks > 2 && bank != fixed {
+                format!("B{:02x}_{:04x}", bank, addr)
+            }
+            else {
+                format!("L{:04x}", addr)
+            };
+            self.labels.entry(offset).or_insert(name);
+        }
+        self.labels.retain(|offset, _| lines[*offset]);
+    }
+
+    fn source(&self, rom: &Ines, name: &str, chr_file: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "; {}, disassembled by readnesrom", name);
+        let _ = writeln!(out, "; ca65 {0}.s && ld65 -C {0}.cfg -o {0}.nes {0}.o", name);
+        let header: Vec<String> = rom.header.to_bytes().iter().map(|b| format!("${:02x}", b)).collect();
+        if !self.equates.is_empty() {
+            let mut equates: Vec<_> = self.equates.iter().collect();
+            equates.sort();
+            let _ = writeln!(out);
+            for (addr, name) in equates {
+                let _ = writeln!(out, "{} = ${:04x}", name, addr);
+            }
+        }
+        let _ = writeln!(out, "\n.segment \"HEADER\"\n    .byte {}", header.join(", "));
+
+        for bank in 0..self.banks {
+            let _ = writeln!(out, "\n.segment \"PRG{:02x}\"", bank);
+            let labels = BankLabels { dis: self, bank };
+            let end = (bank + 1) * BANK_SIZE;
+            let mut offset = bank * BANK_SIZE;
+            while offset < end {
+                if let Some(label) = self.labels.get(&offset) {
+                    let _ = writeln!(out, "{}:", label);
+                }
+                if let Some(size) = self.instruction_size(offset) {
+                    let decoded = disasm::decode(self.addr_of(offset), &self.prg[offset..]).unwrap();
+                    match self.symbols.source_line(offset) {
+                        Some(line) => {
+                            let _ = writeln!(out, "    {:<24} ; {}", decoded.to_ca65(&labels), line);
+                        }
+                        None => {
+                            let _ = writeln!(out, "    {}", decoded.to_ca65(&labels));
+                        }
+                    }
+                    offset += size;
+                    continue;
+                }
+                if bank == self.fixed_bank() && offset % BANK_SIZE == VECTORS[0].0
+                    && (offset..end).all(|o| !self.starts[o])
+                    && (offset + 1..end).all(|o| !self.labels.contains_key(&o)) {
+                    let vectors: Vec<String> = (0..3).map(|i| {
+                        let addr = self.vector(offset + i * 2);
+                        labels.label(addr).map_or_else(|| format!("${:04x}", addr), |l| l.to_string())
+                    }).collect();
+                    let _ = writeln!(out, "    .word {}", vectors.join(", "));
+                    break;
+                }
+                // a line of data, up to a label or code
+                let mut bytes = Vec::new();
+                while offset < end && bytes.len() < 16 && !self.starts[offset] {
+                    bytes.push(format!("${:02x}", self.prg[offset]));
+                    offset += 1;
+                    if self.labels.contains_key(&offset) {
+                        break;
+                    }
+                    if bank == self.fixed_bank() && offset % BANK_SIZE == VECTORS[0].0 {
+                        break;
+                    }
+                }
+                let _ = writeln!(out, "    .byte {}", bytes.join(", "));
+            }
+        }
+
+        if !rom.chrrom.is_empty() {
+            let _ = writeln!(out, "\n.segment \"CHR\"\n    .incbin \"{}\"", chr_file);
+        }
+        out
+    }
+
+    // ld65 configuration putting the segments back in iNES order
+    fn linker_config(&self, rom: &Ines) -> String {
+        let mut memory = String::from("    HEADER: start = $0000, size = $0010, fill = yes;\n");
+        let mut segments = String::from("    HEADER: load = HEADER, type = ro;\n");
+        for bank in 0..self.banks {
+            let _ = writeln!(memory, "    PRG{:02x}: start = ${:04x}, size = $4000, fill = yes;",
+                             bank, self.base(bank));
+            let _ = writeln!(segments, "    PRG{0:02x}: load = PRG{0:02x}, type = ro;", bank);
+        }
+        if !rom.chrrom.is_empty() {
+            let _ = writeln!(memory, "    CHR: start = $0000, size = ${:04x}, fill = yes;", rom.chrrom.len());
+            segments.push_str("    CHR: load = CHR, type = ro;\n");
+        }
+        format!("MEMORY {{\n{}}}\n\nSEGMENTS {{\n{}}}\n", memory, segments)
+    }
+}
+
+// whether ca65 takes a symbol as a label, cheap locals and the names of
+// registers and instructions are not
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    let first_ok = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    let lower = name.to_ascii_lowercase();
+    first_ok && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !["a", "x", "y"].contains(&lower.as_str())
+        && !OPCODES.iter().any(|op| op.mnemonic() == lower)
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), Error> {
+    File::create(path)
+        .and_then(|mut f| f.write_all(data))
+        .map_err(|e| Error::new(format!("{}: {}", path.display(), e)))
+}
+
+// Write FILE.s, FILE.cfg, FILE.chr and FILE.cdl next to the ROM.
+pub fn disassemble(file: &str) -> Result<(), Error> {
+    let rom = Ines::from_file(file)?;
+    if rom.prgrom.len() < BANK_SIZE {
+        return Err(Error::new("no PRG ROM".to_string()));
+    }
+    let symbols = Symbols::for_rom(file)?;
+    let mut dis = Disassembler::new(&rom, symbols);
+    dis.walk_vectors();
+    dis.name_labels();
+
+    let path = Path::new(file);
+    let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let chr = path.with_extension("chr");
+    let chr_file = chr.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    write_file(&path.with_extension("s"), dis.source(&rom, &name, &chr_file).as_bytes())?;
+    write_file(&path.with_extension("cfg"), dis.linker_config(&rom).as_bytes())?;
+    if !rom.chrrom.is_empty() {
+        write_file(&chr, &rom.chrrom)?;
+    }
+    dis.cdl.save(path.with_extension("cdl"))?;
+
+    let code = dis.cdl.prg.iter().filter(|&&f| f & cdl::CODE != 0).count();
+    let data = dis.cdl.prg.iter().filter(|&&f| f & cdl::DATA != 0).count();
+    println!("{} bytes of PRG ROM: {} code, {} data, {} unknown",
+             rom.prgrom.len(), code, data,
+             dis.cdl.prg.iter().filter(|&&f| f & (cdl::CODE | cdl::DATA) == 0).count());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NROM at $c000: a routine with a table, a subroutine and data
+    // after RTS, and interrupt handlers doing nothing
+    fn rom() -> Ines {
+        let code = [
+            0xa9, 0x00,       // c000 lda #$00
+            0x8d, 0x10, 0x00, // c002 sta a:$0010
+            0xad, 0x11, 0x00, // c005 lda a:$0011
+            0xbd, 0x20, 0xc0, // c008 lda $c020,x
+            0xf0, 0x03,       // c00b beq $c010
+            0x20, 0x13, 0xc0, // c00d jsr $c013
+            0x60,             // c010 rts
+            0xff, 0xff,       // c011 never reached
+            0xe8,             // c013 inx
+            0x60,             // c014 rts
+        ];
+        let mut prg = vec![0; BANK_SIZE];
+        prg[..code.len()].copy_from_slice(&code);
+        prg[0x20] = 0x12;
+        prg[0x21] = 0x34;
+        // c030 rti, c031 rti
+        prg[0x30] = 0x40;
+        prg[0x31] = 0x40;
+        prg[0x3ffa..].copy_from_slice(&[0x30, 0xc0, 0x00, 0xc0, 0x31, 0xc0]);
+        let mut bytes = vec![b'N', b'E', b'S', 0x1a, 1, 1];
+        bytes.resize(16, 0);
+        bytes.extend(prg);
+        bytes.resize(16 + BANK_SIZE + 0x2000, 0);
+        Ines::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn disassemble_nrom() {
+        let rom = rom();
+        let mut symbols = Symbols::new();
+        symbols.add_addr(0x0010, "temp");
+        let mut dis = Disassembler::new(&rom, symbols);
+        dis.walk_vectors();
+        dis.name_labels();
+
+        let window = cdl::window(0xc000);
+        assert_eq!(dis.cdl.prg[0x00], cdl::CODE | window);
+        assert_eq!(dis.cdl.prg[0x10], cdl::CODE | window);
+        assert_eq!(dis.cdl.prg[0x11], 0);
+        assert_eq!(dis.cdl.prg[0x13], cdl::CODE | window);
+        assert_eq!(dis.cdl.prg[0x20], cdl::DATA | window);
+        assert_eq!(dis.cdl.prg[0x31], cdl::CODE | window);
+        assert_eq!(dis.cdl.prg[0x32], 0);
+        assert_eq!(dis.cdl.prg[0x3ffa], cdl::DATA | cdl::window(0xfffa));
+
+        let source = dis.source(&rom, "game", "game.chr");
+        assert!(source.contains("temp = $0010\n"), "{}", source);
+        assert!(source.contains("reset:\n    lda #$00\n    sta a:temp\n    lda a:$0011\n    \
+                                 lda Lc020,x\n    beq Lc010\n    jsr Lc013\n\
+                                 Lc010:\n    rts\n    .byte $ff, $ff\n\
+                                 Lc013:\n    inx\n    rts\n"), "{}", source);
+        assert!(source.contains("Lc020:\n    .byte $12, $34,"), "{}", source);
+        assert!(source.contains("nmi:\n    rti\nirq:\n    rti\n    .byte $00,"), "{}", source);
+        assert!(source.contains("    .word nmi, reset, irq\n"), "{}", source);
+        assert!(source.contains(".segment \"CHR\"\n    .incbin \"game.chr\"\n"), "{}", source);
+
+        let config = dis.linker_config(&rom);
+        assert!(config.contains("    HEADER: start = $0000, size = $0010, fill = yes;\n\
+                                 \x20   PRG00: start = $c000, size = $4000, fill = yes;\n\
+                                 \x20   CHR: start = $0000, size = $2000, fill = yes;\n"), "{}", config);
+        assert!(config.contains("    PRG00: load = PRG00, type = ro;\n"), "{}", config);
+    }
+}
@@ -2,6 +2,8 @@ extern crate clap;
 extern crate redwhite;
 extern crate sdl2;
 
+mod disasm;
+
 use std::process;
 use clap::{App, ArgGroup};
 use redwhite::{Tile, TILE_SIZE_PX, PX_SCALE, PATTAB_TILES};
@@ -159,7 +161,7 @@ fn dump_palette(file: &str) -> Result<(), Error> {
 
     for (r, pal) in pals.iter().enumerate() {
         for (c, color) in pal.iter().enumerate() {
-            canvas.set_draw_color(*color);
+            canvas.set_draw_color(Color::from(*color));
             let x = (c * side) as i32;
             let y = (r * side) as i32;
             canvas.fill_rect(Rect::new(x, y, side as u32, side as u32))
@@ -186,9 +188,10 @@ fn run() -> Result<(), Error> {
                 .about("Read iNES rom files")
                 .args_from_usage("-p, --palette 'Dump palette file'
                                   -r, --rom     'Dump iNES rom file'
+                                  -d, --disasm  'Disassemble iNES rom file to FILE.s and FILE.cdl'
                                   <FILE>        'Target file'")
                 .group(ArgGroup::with_name("action")
-                       .args(&["palette", "rom"])
+                       .args(&["palette", "rom", "disasm"])
                        .required(true))
                 .get_matches();
 
@@ -204,6 +207,11 @@ fn run() -> Result<(), Error> {
         return Ok(());
     }
 
+    if args.is_present("disasm") {
+        disasm::disassemble(file)?;
+        return Ok(());
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,829 @@
+// https://wiki.nesdev.com/w/index.php/APU
+
+use ines::Region;
+use resample::{Filter, Resampler, nes_filters};
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+// CPU clock in Hz
+const CLOCK_RATE_NTSC: f64 = 1_789_773.0;
+const CLOCK_RATE_PAL: f64 = 1_662_607.0;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+    12,  16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0], // 12.5%
+    [0, 1, 1, 0, 0, 0, 0, 0], // 25%
+    [0, 1, 1, 1, 1, 0, 0, 0], // 50%
+    [1, 0, 0, 1, 1, 1, 1, 1], // 25% negated
+];
+
+const TRIANGLE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+     0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15,
+];
+
+// in CPU cycles
+const NOISE_TABLE_NTSC: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const NOISE_TABLE_PAL: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+// in CPU cycles
+const DMC_TABLE_NTSC: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const DMC_TABLE_PAL: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// CPU cycles taken away by each DMC sample fetch
+const DMC_STALL: usize = 4;
+
+// frame counter steps in CPU cycles
+// https://wiki.nesdev.com/w/index.php/APU_Frame_Counter
+const FRAME_STEPS_NTSC: [usize; 5] = [7457, 14913, 22371, 29829, 37281];
+const FRAME_STEPS_PAL: [usize; 5] = [8313, 16627, 24939, 33253, 41565];
+
+// $4015
+const STATUS_PULSE1:    u8 = 0b0000_0001;
+const STATUS_PULSE2:    u8 = 0b0000_0010;
+const STATUS_TRIANGLE:  u8 = 0b0000_0100;
+const STATUS_NOISE:     u8 = 0b0000_1000;
+const STATUS_DMC:       u8 = 0b0001_0000;
+const STATUS_FRAME_IRQ: u8 = 0b0100_0000;
+const STATUS_DMC_IRQ:   u8 = 0b1000_0000;
+
+// https://wiki.nesdev.com/w/index.php/APU_Envelope
+#[derive(Default)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    // also the divider period
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl_state!(Envelope, start, looping, constant, volume, divider, decay);
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.looping = value & 0x20 != 0;
+        self.constant = value & 0x10 != 0;
+        self.volume = value & 0x0f;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        }
+        else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            }
+            else if self.looping {
+                self.decay = 15;
+            }
+        }
+        else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant { self.volume } else { self.decay }
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/APU_Length_Counter
+#[derive(Default)]
+struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    value: u8,
+}
+
+impl_state!(LengthCounter, enabled, halt, value);
+
+impl LengthCounter {
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.value = 0;
+        }
+    }
+
+    fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.value = LENGTH_TABLE[index as usize & 0x1f];
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.halt && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.value > 0
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/APU_Sweep
+#[derive(Default)]
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    reload: bool,
+    divider: u8,
+}
+
+impl_state!(Sweep, enabled, period, negate, shift, reload, divider);
+
+// https://wiki.nesdev.com/w/index.php/APU_Pulse
+#[derive(Default)]
+struct Pulse {
+    // pulse 1 negates with one's complement, pulse 2 with two's complement
+    ones_complement: bool,
+    duty: u8,
+    duty_pos: u8,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+    sweep: Sweep,
+}
+
+impl_state!(Pulse, duty, duty_pos, timer_period, timer, envelope, length, sweep);
+
+impl Pulse {
+    fn new(ones_complement: bool) -> Self {
+        Pulse { ones_complement, ..Default::default() }
+    }
+
+    fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            0 => {
+                self.duty = value >> 6;
+                self.length.halt = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep.enabled = value & 0x80 != 0;
+                self.sweep.period = (value >> 4) & 0x07;
+                self.sweep.negate = value & 0x08 != 0;
+                self.sweep.shift = value & 0x07;
+                self.sweep.reload = true;
+            }
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | value as u16;
+            }
+            _ => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((value as u16 & 0x07) << 8);
+                self.length.load(value >> 3);
+                self.duty_pos = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    // clocked every other CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.duty_pos = (self.duty_pos + 1) & 0x7;
+        }
+        else {
+            self.timer -= 1;
+        }
+    }
+
+    fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+        if self.sweep.negate {
+            let extra = if self.ones_complement { 1 } else { 0 };
+            self.timer_period.saturating_sub(change + extra)
+        }
+        else {
+            self.timer_period + change
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.target_period() > 0x7ff
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.muted() {
+            self.timer_period = self.target_period();
+        }
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        }
+        else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.length.active() || self.muted()
+            || DUTY_TABLE[self.duty as usize][self.duty_pos as usize] == 0 {
+            0
+        }
+        else {
+            self.envelope.output()
+        }
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/APU_Triangle
+#[derive(Default)]
+struct Triangle {
+    timer_period: u16,
+    timer: u16,
+    seq_pos: u8,
+    length: LengthCounter,
+    // also the length counter halt flag
+    control: bool,
+    linear_period: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+}
+
+impl_state!(Triangle, timer_period, timer, seq_pos, length, control,
+            linear_period, linear_counter, linear_reload);
+
+impl Triangle {
+    fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            0 => {
+                self.control = value & 0x80 != 0;
+                self.length.halt = self.control;
+                self.linear_period = value & 0x7f;
+            }
+            1 => {}
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | value as u16;
+            }
+            _ => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((value as u16 & 0x07) << 8);
+                self.length.load(value >> 3);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    // clocked every CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.length.active() && self.linear_counter > 0 {
+                self.seq_pos = (self.seq_pos + 1) & 0x1f;
+            }
+        }
+        else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_period;
+        }
+        else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        TRIANGLE_TABLE[self.seq_pos as usize]
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/APU_Noise
+struct Noise {
+    periods: &'static [u16; 16],
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl_state!(Noise, short_mode, timer_period, timer, shift, envelope, length);
+
+impl Noise {
+    fn new(region: Region) -> Self {
+        let periods = match region {
+            Region::Ntsc => &NOISE_TABLE_NTSC,
+            Region::Pal => &NOISE_TABLE_PAL,
+        };
+        Noise {
+            periods,
+            short_mode: false,
+            timer_period: periods[0],
+            timer: 0,
+            // the shift register is 1 on power-up
+            shift: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            0 => {
+                self.length.halt = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = value & 0x80 != 0;
+                self.timer_period = self.periods[value as usize & 0x0f];
+            }
+            _ => {
+                self.length.load(value >> 3);
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    // clocked every CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        }
+        else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 1 != 0 {
+            0
+        }
+        else {
+            self.envelope.output()
+        }
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/APU_DMC
+struct Dmc {
+    rates: &'static [u16; 16],
+    irq_enabled: bool,
+    irq: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+
+    // memory reader
+    sample_addr: u16,
+    sample_length: u16,
+    current_addr: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+
+    // output unit
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    level: u8,
+}
+
+impl_state!(Dmc, irq_enabled, irq, looping, timer_period, timer, sample_addr,
+            sample_length, current_addr, bytes_remaining, buffer, shift, bits_remaining,
+            silence, level);
+
+impl Dmc {
+    fn new(region: Region) -> Self {
+        let rates = match region {
+            Region::Ntsc => &DMC_TABLE_NTSC,
+            Region::Pal => &DMC_TABLE_PAL,
+        };
+        Dmc {
+            rates,
+            irq_enabled: false,
+            irq: false,
+            looping: false,
+            timer_period: rates[0],
+            timer: 0,
+            sample_addr: 0xc000,
+            sample_length: 1,
+            current_addr: 0xc000,
+            bytes_remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            level: 0,
+        }
+    }
+
+    fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            0 => {
+                self.irq_enabled = value & 0x80 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.looping = value & 0x40 != 0;
+                self.timer_period = self.rates[value as usize & 0x0f];
+            }
+            1 => self.level = value & 0x7f,
+            2 => self.sample_addr = 0xc000 | ((value as u16) << 6),
+            _ => self.sample_length = ((value as u16) << 4) + 1,
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        }
+        else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_addr = self.sample_addr;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    // the address the memory reader wants to fetch next, if any
+    fn fetch_addr(&self) -> Option<u16> {
+        if self.buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_addr)
+        }
+        else {
+            None
+        }
+    }
+
+    fn fill(&mut self, value: u8) {
+        self.buffer = Some(value);
+        // the address wraps around to $8000
+        self.current_addr = if self.current_addr == 0xffff { 0x8000 } else { self.current_addr + 1 };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            }
+            else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    // clocked every CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            }
+            else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(value) => {
+                    self.silence = false;
+                    self.shift = value;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.level
+    }
+}
+
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+
+    frame_steps: &'static [usize; 5],
+    frame_cycle: usize,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    // the pulse and noise timers run at half the CPU clock
+    even_cycle: bool,
+
+    region: Region,
+    sample_rate: u32,
+    // CPU cycles since the last `end_frame`
+    clock: usize,
+    // mixer output at the last cycle
+    level: f32,
+    resampler: Resampler,
+    filters: Vec<Filter>,
+    samples: Vec<f32>,
+    samples_i16: Vec<i16>,
+}
+
+// The resampler is not saved, loading a state may only cause a click.
+impl_state!(Apu, pulse1, pulse2, triangle, noise, dmc, frame_cycle, five_step,
+            irq_inhibit, frame_irq, even_cycle, clock, level);
+
+// https://wiki.nesdev.com/w/index.php/APU_Mixer
+fn mix(levels: [u8; 5]) -> f32 {
+    let [pulse1, pulse2, triangle, noise, dmc] = levels;
+    let pulse = pulse1 as f32 + pulse2 as f32;
+    let pulse_out = if pulse == 0.0 {
+        0.0
+    }
+    else {
+        95.88 / (8128.0 / pulse + 100.0)
+    };
+    let tnd = triangle as f32 / 8227.0 + noise as f32 / 12241.0 + dmc as f32 / 22638.0;
+    let tnd_out = if tnd == 0.0 {
+        0.0
+    }
+    else {
+        159.79 / (1.0 / tnd + 100.0)
+    };
+    pulse_out + tnd_out
+}
+
+impl Apu {
+    pub fn new(region: Region) -> Self {
+        Apu {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::new(region),
+            dmc: Dmc::new(region),
+            frame_steps: match region {
+                Region::Ntsc => &FRAME_STEPS_NTSC,
+                Region::Pal => &FRAME_STEPS_PAL,
+            },
+            frame_cycle: 0,
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            even_cycle: false,
+            region,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            clock: 0,
+            level: 0.0,
+            resampler: Apu::make_resampler(region, DEFAULT_SAMPLE_RATE),
+            filters: nes_filters(DEFAULT_SAMPLE_RATE as f64),
+            samples: Vec::new(),
+            samples_i16: Vec::new(),
+        }
+    }
+
+    fn make_resampler(region: Region, sample_rate: u32) -> Resampler {
+        let clock_rate = match region {
+            Region::Ntsc => CLOCK_RATE_NTSC,
+            Region::Pal => CLOCK_RATE_PAL,
+        };
+        Resampler::new(clock_rate, sample_rate as f64)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // host sample rate, e.g. 44100 or 48000
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.resampler = Apu::make_resampler(self.region, sample_rate);
+        self.filters = nes_filters(sample_rate as f64);
+        self.clock = 0;
+        self.level = 0.0;
+    }
+
+    // Reset silences all channels, as if $4015 was written with 0.
+    // The frame counter keeps its mode.
+    pub fn reset(&mut self) {
+        let five_step = self.five_step;
+        let irq_inhibit = self.irq_inhibit;
+        let sample_rate = self.sample_rate;
+        *self = Apu::new(self.region);
+        self.five_step = five_step;
+        self.irq_inhibit = irq_inhibit;
+        self.set_sample_rate(sample_rate);
+    }
+
+    // $4015
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        if self.pulse1.length.active() {
+            status |= STATUS_PULSE1;
+        }
+        if self.pulse2.length.active() {
+            status |= STATUS_PULSE2;
+        }
+        if self.triangle.length.active() {
+            status |= STATUS_TRIANGLE;
+        }
+        if self.noise.length.active() {
+            status |= STATUS_NOISE;
+        }
+        if self.dmc.bytes_remaining > 0 {
+            status |= STATUS_DMC;
+        }
+        if self.frame_irq {
+            status |= STATUS_FRAME_IRQ;
+        }
+        if self.dmc.irq {
+            status |= STATUS_DMC_IRQ;
+        }
+        // reading clears the frame interrupt flag
+        self.frame_irq = false;
+        status
+    }
+
+    // $4000-$4013, $4015, $4017
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr & 0x3, value),
+            0x4004..=0x4007 => self.pulse2.write(addr & 0x3, value),
+            0x4008..=0x400b => self.triangle.write(addr & 0x3, value),
+            0x400c..=0x400f => self.noise.write(addr & 0x3, value),
+            0x4010..=0x4013 => self.dmc.write(addr & 0x3, value),
+            0x4015 => {
+                self.dmc.set_enabled(value & STATUS_DMC != 0);
+                self.pulse1.length.set_enabled(value & STATUS_PULSE1 != 0);
+                self.pulse2.length.set_enabled(value & STATUS_PULSE2 != 0);
+                self.triangle.length.set_enabled(value & STATUS_TRIANGLE != 0);
+                self.noise.length.set_enabled(value & STATUS_NOISE != 0);
+            }
+            0x4017 => {
+                self.five_step = value & 0x80 != 0;
+                self.irq_inhibit = value & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                // the 5-step mode clocks everything immediately
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.triangle.clock_linear();
+        self.noise.envelope.clock();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        let steps = self.frame_steps;
+        let cycle = self.frame_cycle;
+        if cycle == steps[0] || cycle == steps[2] {
+            self.clock_quarter_frame();
+        }
+        else if cycle == steps[1] {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        }
+        else if cycle == steps[3] && !self.five_step {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+            if !self.irq_inhibit {
+                self.frame_irq = true;
+            }
+            self.frame_cycle = 0;
+        }
+        else if cycle == steps[4] {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+            self.frame_cycle = 0;
+        }
+    }
+
+    // advance one CPU cycle
+    pub fn step(&mut self) {
+        self.even_cycle = !self.even_cycle;
+        if self.even_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        self.clock_frame_counter();
+
+        let level = mix(self.levels());
+        if level != self.level {
+            self.resampler.add_delta(self.clock, level - self.level);
+            self.level = level;
+        }
+        self.clock += 1;
+    }
+
+    // Resample everything produced since the last call and append it
+    // to the sample buffers.
+    pub fn end_frame(&mut self) {
+        let start = self.samples.len();
+        self.resampler.end_frame(self.clock, &mut self.samples);
+        self.clock = 0;
+        for sample in self.samples[start..].iter_mut() {
+            for filter in self.filters.iter_mut() {
+                *sample = filter.process(*sample);
+            }
+            // the cast saturates
+            self.samples_i16.push((*sample * 32767.0) as i16);
+        }
+    }
+
+    // The DMC fetches its samples through the CPU bus, which the APU
+    // has no access to. The bus asks for the address after each step,
+    // feeds the byte back with `dmc_fill` and halts the CPU meanwhile.
+    pub fn dmc_fetch_addr(&self) -> Option<u16> {
+        self.dmc.fetch_addr()
+    }
+
+    // returns the CPU cycles stolen by the fetch
+    pub fn dmc_fill(&mut self, value: u8) -> usize {
+        self.dmc.fill(value);
+        DMC_STALL
+    }
+
+    // current output level of pulse 1, pulse 2, triangle and noise,
+    // 0 to 15, and of the DMC, 0 to 127
+    pub fn levels(&self) -> [u8; 5] {
+        [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ]
+    }
+
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples_i16
+    }
+
+    pub fn samples_f32(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.samples.clear();
+        self.samples_i16.clear();
+    }
+}
@@ -0,0 +1,277 @@
+// Bandai FCG boards, mappers 16 and 159
+// https://wiki.nesdev.com/w/index.php/Bandai_FCG_board
+
+use cartridge::Mapper;
+use eeprom::{Chip, Eeprom};
+use error::Error;
+use ines::{Ines, Mirroring};
+use savestate::{State, StateReader, StateWriter};
+
+pub struct BandaiFcg {
+    prgrom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    // 1 KB CHR banks
+    chr_banks: [u8; 8],
+    // 16 KB PRG bank at $8000, $c000 is fixed to the last bank
+    prg_bank: u8,
+    mirroring: u8,
+    irq_enabled: bool,
+    irq_counter: u16,
+    irq_latch: u16,
+    irq: bool,
+    // The FCG-1/2 chips are mapped at $6000 and write the IRQ counter
+    // directly, the LZ93D50 is mapped at $8000 and reloads it from a
+    // latch. Boards not telling which behave as both.
+    fcg: bool,
+    lz93d50: bool,
+    eeprom: Option<Eeprom>,
+}
+
+impl BandaiFcg {
+    pub fn new(rom: Ines) -> Self {
+        let submapper = rom.header.submapper();
+        let (fcg, lz93d50, eeprom) = match (rom.header.mapper(), submapper) {
+            (159, _) => (false, true, Some(Eeprom::new(Chip::X24C01))),
+            (_, 4) => (true, false, None),
+            (_, 5) => (false, true, Some(Eeprom::new(Chip::X24C02))),
+            _ => (true, true, Some(Eeprom::new(Chip::X24C02))),
+        };
+        let chr_is_ram = rom.chrrom.is_empty();
+        BandaiFcg {
+            prgrom: rom.prgrom,
+            chr: if chr_is_ram { vec![0; 0x2000] } else { rom.chrrom },
+            chr_is_ram,
+            chr_banks: [0; 8],
+            prg_bank: 0,
+            mirroring: 0,
+            irq_enabled: false,
+            irq_counter: 0,
+            irq_latch: 0,
+            irq: false,
+            fcg,
+            lz93d50,
+            eeprom,
+        }
+    }
+
+    fn write_register(&mut self, reg: u16, value: u8) {
+        match reg {
+            0x0..=0x7 => self.chr_banks[reg as usize] = value,
+            0x8 => self.prg_bank = value & 0x0f,
+            0x9 => self.mirroring = value & 0x03,
+            0xa => {
+                self.irq_enabled = value & 0x01 != 0;
+                self.irq = false;
+                if self.lz93d50 {
+                    self.irq_counter = self.irq_latch;
+                }
+            }
+            0xb | 0xc => {
+                let shift = if reg == 0xb { 0 } else { 8 };
+                let mask = !(0xff << shift);
+                if self.lz93d50 {
+                    self.irq_latch = (self.irq_latch & mask) | (value as u16) << shift;
+                }
+                if self.fcg {
+                    self.irq_counter = (self.irq_counter & mask) | (value as u16) << shift;
+                }
+            }
+            0xd => {
+                if let Some(ref mut eeprom) = self.eeprom {
+                    eeprom.write(value & 0x20 != 0, value & 0x40 != 0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl State for BandaiFcg {
+    fn save(&self, w: &mut StateWriter) {
+        if self.chr_is_ram {
+            self.chr.save(w);
+        }
+        self.chr_banks.save(w);
+        self.prg_bank.save(w);
+        self.mirroring.save(w);
+        self.irq_enabled.save(w);
+        self.irq_counter.save(w);
+        self.irq_latch.save(w);
+        self.irq.save(w);
+        if let Some(ref eeprom) = self.eeprom {
+            eeprom.save(w);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        if self.chr_is_ram {
+            self.chr.load(r)?;
+        }
+        self.chr_banks.load(r)?;
+        self.prg_bank.load(r)?;
+        self.mirroring.load(r)?;
+        self.irq_enabled.load(r)?;
+        self.irq_counter.load(r)?;
+        self.irq_latch.load(r)?;
+        self.irq.load(r)?;
+        if let Some(ref mut eeprom) = self.eeprom {
+            eeprom.load(r)?;
+        }
+        Ok(())
+    }
+}
+
+impl Mapper for BandaiFcg {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        self.peek_prg(addr)
+    }
+
+    fn peek_prg(&self, addr: u16) -> u8 {
+        match addr {
+            // bit 4 is the EEPROM data line, the rest is open bus
+            0x6000..=0x7fff => match self.eeprom {
+                Some(ref eeprom) => (eeprom.read() as u8) << 4,
+                None => 0,
+            },
+            _ => match self.prg_offset(addr) {
+                Some(offset) => self.prgrom[offset],
+                None => 0,
+            },
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xbfff => {
+                let offset = self.prg_bank as usize * 0x4000 + (addr as usize & 0x3fff);
+                Some(offset % self.prgrom.len())
+            }
+            0xc000..=0xffff => Some(self.prgrom.len() - 0x4000 + (addr as usize & 0x3fff)),
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7fff if self.fcg => self.write_register(addr & 0x0f, value),
+            0x8000..=0xffff if self.lz93d50 => self.write_register(addr & 0x0f, value),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        match self.chr_offset(addr) {
+            Some(offset) => self.chr[offset],
+            None => self.chr[addr as usize & 0x1fff],
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram {
+            return None;
+        }
+        let bank = self.chr_banks[(addr as usize >> 10) & 7] as usize;
+        let offset = bank * 0x400 + (addr as usize & 0x3ff);
+        Some(offset % self.chr.len())
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_is_ram {
+            self.chr[addr as usize & 0x1fff] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.mirroring {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleLower,
+            _ => Mirroring::SingleUpper,
+        }
+    }
+
+    // https://wiki.nesdev.com/w/index.php/Bandai_FCG_board#IRQ_Control_($800A)
+    fn clock(&mut self) {
+        if self.irq_enabled {
+            if self.irq_counter == 0 {
+                self.irq = true;
+            }
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+
+    fn battery(&self) -> Option<&[u8]> {
+        self.eeprom.as_ref().map(|e| e.data())
+    }
+
+    fn battery_mut(&mut self) -> Option<&mut [u8]> {
+        self.eeprom.as_mut().map(|e| e.data_mut())
+    }
+
+    fn power_on(&mut self) {
+        if self.chr_is_ram {
+            self.chr.fill(0);
+        }
+        self.chr_banks = [0; 8];
+        self.prg_bank = 0;
+        self.mirroring = 0;
+        self.irq_enabled = false;
+        self.irq_counter = 0;
+        self.irq_latch = 0;
+        self.irq = false;
+        if let Some(ref mut eeprom) = self.eeprom {
+            eeprom.power_on();
+        }
+    }
+
+    // a blank EEPROM reads all ones
+    fn clear_battery(&mut self) {
+        if let Some(ref mut eeprom) = self.eeprom {
+            eeprom.data_mut().fill(0xff);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use savestate::StateWriter;
+
+    fn fcg() -> BandaiFcg {
+        let mut rom = vec![b'N', b'E', b'S', 0x1a, 2, 1, 0x00, 0x10];
+        rom.resize(16 + 0x8000 + 0x2000, 0);
+        BandaiFcg::new(Ines::from_bytes(&rom).unwrap())
+    }
+
+    fn state(mapper: &BandaiFcg) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        mapper.save(&mut w);
+        w.into_bytes()
+    }
+
+    #[test]
+    fn power_on_keeps_only_the_eeprom_data() {
+        let mut mapper = fcg();
+        for &(reg, value) in [(0x0, 3), (0x8, 1), (0x9, 1), (0xb, 0x34), (0xc, 0x12), (0xa, 1)].iter() {
+            mapper.write_prg(0x8000 | reg, value);
+        }
+        mapper.clock();
+        // in the middle of an EEPROM transfer
+        mapper.write_prg(0x800d, 0x60);
+        mapper.write_prg(0x800d, 0x20);
+        mapper.eeprom.as_mut().unwrap().data_mut()[0] = 0x12;
+        mapper.power_on();
+
+        let mut expected = fcg();
+        expected.eeprom.as_mut().unwrap().data_mut()[0] = 0x12;
+        assert_eq!(state(&mapper), state(&expected));
+
+        mapper.clear_battery();
+        assert!(mapper.battery().unwrap().iter().all(|&b| b == 0xff));
+    }
+}
@@ -0,0 +1,294 @@
+// Settings of the player, read from $XDG_CONFIG_HOME/redwhite/config.toml.
+//
+//     scale = 3
+//     sample_rate = 48000
+//     palette = "/home/me/nes/smooth.pal"
+//     rom_dir = "/home/me/nes/roms"
+//     save_dir = "/home/me/nes/saves"
+//     # keep a state every 2 frames for rewinding, in at most 32 MB
+//     rewind_interval = 2
+//     rewind_memory = 32
+//     controller_db = "/home/me/nes/gamecontrollerdb.txt"
+//     mappings = ["<SDL GameController mapping string>"]
+//
+//     [player1]
+//     a = "X"
+//     start = "Return"
+//
+//     [pad]
+//     a = "b"
+//     b = "a"
+//
+//     # per game settings, keyed by the CRC-32 of the ROM
+//     [games.3fe272fb]
+//     scale = 4
+//     expansion = "zapper"
+//
+// Keys are SDL key names, gamepad buttons are SDL GameController
+// button names. Binding one takes it from the button it drives by
+// default, binding one to two buttons is an error.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use redwhite::PX_SCALE;
+use redwhite::controller::Buttons;
+use redwhite::error::{Error, ResultContext};
+use redwhite::input::Expansion;
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+use toml;
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+const DEFAULT_REWIND_INTERVAL: u32 = 2;
+// in MB
+const DEFAULT_REWIND_MEMORY: u32 = 32;
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+struct Settings {
+    scale: Option<u32>,
+    sample_rate: Option<u32>,
+    palette: Option<PathBuf>,
+    rom_dir: Option<PathBuf>,
+    save_dir: Option<PathBuf>,
+    expansion: Option<String>,
+    rewind_interval: Option<u32>,
+    rewind_memory: Option<u32>,
+    player1: HashMap<String, String>,
+    player2: HashMap<String, String>,
+    pad: HashMap<String, String>,
+}
+
+// The bindings of `other` replace those of the same buttons in `map`,
+// and those to a key or gamepad button bound in any of `bound`.
+fn merge_bindings(map: &HashMap<String, String>, other: &HashMap<String, String>,
+                  bound: &[&HashMap<String, String>]) -> HashMap<String, String> {
+    let is_bound = |name: &String| bound.iter().any(|b| b.values().any(|v| v.eq_ignore_ascii_case(name)));
+    let mut merged: HashMap<String, String> = map.iter()
+        .filter(|&(_, name)| !is_bound(name))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    merged.extend(other.iter().map(|(k, v)| (k.clone(), v.clone())));
+    merged
+}
+
+impl Settings {
+    // `other` takes precedence
+    fn merge(&self, other: &Settings) -> Settings {
+        let keys = [&other.player1, &other.player2];
+        Settings {
+            scale: other.scale.or(self.scale),
+            sample_rate: other.sample_rate.or(self.sample_rate),
+            palette: other.palette.clone().or_else(|| self.palette.clone()),
+            rom_dir: other.rom_dir.clone().or_else(|| self.rom_dir.clone()),
+            save_dir: other.save_dir.clone().or_else(|| self.save_dir.clone()),
+            expansion: other.expansion.clone().or_else(|| self.expansion.clone()),
+            rewind_interval: other.rewind_interval.or(self.rewind_interval),
+            rewind_memory: other.rewind_memory.or(self.rewind_memory),
+            player1: merge_bindings(&self.player1, &other.player1, &keys),
+            player2: merge_bindings(&self.player2, &other.player2, &keys),
+            pad: merge_bindings(&self.pad, &other.pad, &[&other.pad]),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ConfigFile {
+    #[serde(flatten)]
+    settings: Settings,
+    controller_db: Option<PathBuf>,
+    mappings: Vec<String>,
+    games: HashMap<String, Settings>,
+}
+
+pub struct Config {
+    file: ConfigFile,
+    settings: Settings,
+    pub scale: u32,
+    pub sample_rate: u32,
+    pub palette: Option<PathBuf>,
+    pub rom_dir: Option<PathBuf>,
+    pub save_dir: PathBuf,
+    pub expansion: Option<Expansion>,
+    // in frames
+    pub rewind_interval: usize,
+    // in bytes
+    pub rewind_memory: usize,
+    // key to player and button
+    pub keys: HashMap<Keycode, (usize, Buttons)>,
+    pub pad: HashMap<Button, Buttons>,
+}
+
+fn button_from_name(name: &str) -> Option<Buttons> {
+    match name.to_lowercase().as_str() {
+        "a" => Some(Buttons::A),
+        "b" => Some(Buttons::B),
+        "select" => Some(Buttons::SELECT),
+        "start" => Some(Buttons::START),
+        "up" => Some(Buttons::UP),
+        "down" => Some(Buttons::DOWN),
+        "left" => Some(Buttons::LEFT),
+        "right" => Some(Buttons::RIGHT),
+        _ => None,
+    }
+}
+
+fn default_keys() -> HashMap<String, String> {
+    [("a", "X"), ("b", "Z"), ("select", "Right Shift"), ("start", "Return"),
+     ("up", "Up"), ("down", "Down"), ("left", "Left"), ("right", "Right")]
+        .iter().map(|&(b, k)| (b.to_string(), k.to_string())).collect()
+}
+
+fn default_pad() -> HashMap<String, String> {
+    [("a", "a"), ("b", "b"), ("select", "back"), ("start", "start"),
+     ("up", "dpup"), ("down", "dpdown"), ("left", "dpleft"), ("right", "dpright")]
+        .iter().map(|&(b, p)| (b.to_string(), p.to_string())).collect()
+}
+
+// $XDG_CONFIG_HOME or $XDG_DATA_HOME, falling back to `fallback` in $HOME
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    match env::var_os(var) {
+        Some(ref dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = env::var_os("HOME").unwrap_or_default();
+            Path::new(&home).join(fallback)
+        }
+    }
+}
+
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config").join("redwhite")
+}
+
+fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share").join("redwhite")
+}
+
+impl Config {
+    // A missing file is not an error, every setting has a default.
+    pub fn load() -> Result<Self, Error> {
+        let path = config_dir().join("config.toml");
+        let file = match File::open(&path) {
+            Ok(mut f) => {
+                let mut text = String::new();
+                f.read_to_string(&mut text).context(path.display().to_string())?;
+                toml::from_str(&text).context(path.display().to_string())?
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => ConfigFile::default(),
+            Err(e) => return Err(e).context(path.display().to_string()),
+        };
+        let defaults = Settings {
+            player1: default_keys(),
+            pad: default_pad(),
+            ..Default::default()
+        };
+        let settings = defaults.merge(&file.settings);
+        let mut config = Config {
+            file,
+            settings: Settings::default(),
+            scale: 0,
+            sample_rate: 0,
+            palette: None,
+            rom_dir: None,
+            save_dir: PathBuf::new(),
+            expansion: None,
+            rewind_interval: 0,
+            rewind_memory: 0,
+            keys: HashMap::new(),
+            pad: HashMap::new(),
+        };
+        config.apply(settings)?;
+        Ok(config)
+    }
+
+    // apply the settings of a game on top of the global ones
+    pub fn select_game(&mut self, crc: u32) -> Result<(), Error> {
+        let key = format!("{:08x}", crc);
+        let game = self.file.games.iter()
+                       .find(|&(k, _)| k.to_lowercase() == key)
+                       .map(|(_, v)| v.clone());
+        if let Some(game) = game {
+            let settings = self.settings.merge(&game);
+            self.apply(settings)?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, settings: Settings) -> Result<(), Error> {
+        self.scale = settings.scale.unwrap_or(PX_SCALE as u32).max(1);
+        self.sample_rate = settings.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+        self.palette = settings.palette.clone();
+        self.rom_dir = settings.rom_dir.clone();
+        self.save_dir = settings.save_dir.clone().unwrap_or_else(data_dir);
+        self.expansion = match settings.expansion {
+            Some(ref name) => Some(Expansion::from_name(name).ok_or_else(|| {
+                Error::new(format!("unknown expansion device {}", name))
+            })?),
+            None => None,
+        };
+        self.rewind_interval = settings.rewind_interval.unwrap_or(DEFAULT_REWIND_INTERVAL) as usize;
+        self.rewind_memory = (settings.rewind_memory.unwrap_or(DEFAULT_REWIND_MEMORY) as usize) << 20;
+
+        // a key or gamepad button drives a single button, the names are
+        // kept for telling which ones are bound twice
+        self.keys.clear();
+        let mut key_names = HashMap::new();
+        for (player, map) in [&settings.player1, &settings.player2].iter().enumerate() {
+            for (button, key) in map.iter() {
+                let b = button_from_name(button)
+                        .ok_or_else(|| Error::new(format!("unknown button {}", button)))?;
+                let k = Keycode::from_name(key)
+                        .ok_or_else(|| Error::new(format!("unknown key {}", key)))?;
+                if let Some((other_player, other)) = key_names.insert(k, (player, button)) {
+                    return Err(Error::new(format!("key {} is bound to both player{}.{} and player{}.{}",
+                                                  key, other_player + 1, other, player + 1, button)));
+                }
+                self.keys.insert(k, (player, b));
+            }
+        }
+
+        self.pad.clear();
+        let mut pad_names = HashMap::new();
+        for (button, pad_button) in settings.pad.iter() {
+            let b = button_from_name(button)
+                    .ok_or_else(|| Error::new(format!("unknown button {}", button)))?;
+            let p = Button::from_string(pad_button)
+                    .ok_or_else(|| Error::new(format!("unknown gamepad button {}", pad_button)))?;
+            if let Some(other) = pad_names.insert(p, button) {
+                return Err(Error::new(format!("gamepad button {} is bound to both pad.{} and pad.{}",
+                                              pad_button, other, button)));
+            }
+            self.pad.insert(p, b);
+        }
+
+        self.settings = settings;
+        Ok(())
+    }
+
+    // SDL GameController mappings, from a gamecontrollerdb.txt file
+    // and from the config itself
+    pub fn controller_db(&self) -> Option<&Path> {
+        self.file.controller_db.as_deref()
+    }
+
+    pub fn mappings(&self) -> &[String] {
+        &self.file.mappings
+    }
+
+    // A relative ROM path is looked up in the ROM directory if it
+    // is not found from the working directory.
+    pub fn find_rom(&self, path: &str) -> PathBuf {
+        let path = PathBuf::from(path);
+        if path.exists() || path.is_absolute() {
+            return path;
+        }
+        match self.rom_dir {
+            Some(ref dir) => dir.join(path),
+            None => path,
+        }
+    }
+}
@@ -0,0 +1,525 @@
+extern crate clap;
+extern crate redwhite;
+extern crate sdl2;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+
+mod config;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+use clap::App;
+use config::Config;
+use redwhite::cdl::Cdl;
+use redwhite::cheat::{Cheat, Cheats};
+use redwhite::controller::Buttons;
+use redwhite::debugger::Debugger;
+use redwhite::error::{Error, ResultContext};
+use redwhite::gdb::{GdbStub, Session};
+use redwhite::ines::Region;
+use redwhite::input::Zapper;
+use redwhite::iolog::IoLog;
+use redwhite::movie::{Frame, Movie};
+use redwhite::nes::Nes;
+use redwhite::palette::{self, PaletteSet, DEFAULT_PALETTE};
+use redwhite::rewind::Rewind;
+use redwhite::symbols::Symbols;
+use redwhite::ppu::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use sdl2::mouse::MouseButton;
+use sdl2::keyboard::{Keycode, LCTRLMOD, RCTRLMOD, LSHIFTMOD, RSHIFTMOD};
+use sdl2::pixels::PixelFormatEnum;
+
+const NTSC_FPS: f64 = 60.0988;
+const PAL_FPS: f64 = 50.007;
+
+// default for --gdb
+const GDB_PORT: u16 = 6502;
+
+// how often the battery memory is written out if it changed
+const BATTERY_SAVE_FRAMES: u64 = 600;
+
+// drop queued audio beyond this to keep the latency bounded
+const MAX_QUEUED_SECS: f64 = 0.1;
+
+// F1-F10 load a save state slot, with Shift they save it
+const SLOT_KEYS: [Keycode; 10] = [
+    Keycode::F1, Keycode::F2, Keycode::F3, Keycode::F4, Keycode::F5,
+    Keycode::F6, Keycode::F7, Keycode::F8, Keycode::F9, Keycode::F10,
+];
+
+// files next to each other in the save directory, named after the
+// ROM, e.g. ~/.local/share/redwhite/smb.st1 and smb.sav
+fn save_path(save_dir: &Path, rom: &Path, extension: &str) -> PathBuf {
+    let name = rom.file_stem().unwrap_or_default().to_string_lossy();
+    save_dir.join(format!("{}.{}", name, extension))
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(dir.display().to_string())?;
+    }
+    fs::write(path, data).context(path.display().to_string())
+}
+
+fn save_state(nes: &Nes, path: &Path) -> Result<(), Error> {
+    write_file(path, &nes.save_state())
+}
+
+// write the battery memory if it changed since the last time
+fn save_battery(nes: &Nes, path: &Path, saved: &mut Option<Vec<u8>>) {
+    if let Some(ram) = nes.battery() {
+        if saved.as_ref().map(|s| &s[..]) != Some(ram) {
+            match write_file(path, ram) {
+                Ok(()) => *saved = Some(ram.to_vec()),
+                Err(e) => eprintln!("battery save: {}", e),
+            }
+        }
+    }
+}
+
+fn load_state(nes: &mut Nes, path: &Path) -> Result<(), Error> {
+    let data = fs::read(path).context(path.display().to_string())?;
+    nes.load_state(&data)
+}
+
+enum MovieMode {
+    Off,
+    // saved on exit
+    Recording(Movie, PathBuf),
+    // the next frame to play
+    Playing(Movie, usize),
+}
+
+// player of the gamepad with the given instance id
+fn pad_player(pads: &[GameController], which: i32) -> Option<usize> {
+    pads.iter().position(|pad| pad.instance_id() == which)
+}
+
+fn render(nes: &Nes, pal: &PaletteSet, pixels: &mut [u8]) {
+    for (i, &index) in nes.framebuffer().iter().enumerate() {
+        let color = palette::lookup(pal, index);
+        pixels[i * 3] = color.r;
+        pixels[i * 3 + 1] = color.g;
+        pixels[i * 3 + 2] = color.b;
+    }
+}
+
+fn play(nes: &Nes, audio: &AudioQueue<i16>) {
+    let bytes_per_sec = audio.spec().freq as f64 * 2.0;
+    if audio.size() as f64 > bytes_per_sec * MAX_QUEUED_SECS {
+        audio.clear();
+    }
+    audio.queue(nes.audio_samples());
+}
+
+// the accesses and counts of the frame just run
+fn write_io_log<W: Write>(nes: &mut Nes, out: &mut W) -> io::Result<()> {
+    let frame = nes.bus().ppu.frame();
+    if let Some(ref mut log) = nes.bus_mut().io_log {
+        for access in log.take_accesses() {
+            writeln!(out, "{}", access)?;
+        }
+        write!(out, "frame {}: {}", frame, log.end_frame())?;
+    }
+    Ok(())
+}
+
+// a debugger knowing the symbols found next to the ROM
+fn new_debugger(rom: &Path) -> Debugger {
+    let mut debugger = Debugger::new();
+    match Symbols::for_rom(rom) {
+        Ok(symbols) => debugger.symbols = symbols,
+        Err(e) => eprintln!("symbols: {}", e),
+    }
+    debugger
+}
+
+// Run a frame under the debugger, breaking into its command line on
+// stdin first or when it stops. Returns false to quit.
+fn debug_frame(debugger: &mut Debugger, nes: &mut Nes, break_in: bool) -> bool {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    if break_in && !debugger.repl(nes, &mut stdin.lock(), &mut stdout.lock()) {
+        return false;
+    }
+    while let Some(stop) = debugger.run_frame(nes) {
+        println!("{}", stop);
+        if !debugger.repl(nes, &mut stdin.lock(), &mut stdout.lock()) {
+            return false;
+        }
+    }
+    true
+}
+
+fn run() -> Result<(), Error> {
+    let args = App::new("redwhite")
+                .version("0.1")
+                .about("NES emulator")
+                .args_from_usage("-p, --palette [PALETTE] 'Palette file'
+                                  --record [MOVIE]        'Record a movie from power-on, .fm2 or .bk2'
+                                  --play [MOVIE]          'Play a movie, .fm2 or .bk2'
+                                  -c, --cheat [CODE]...   'Add a Game Genie or address:value cheat'
+                                  -d, --debug             'Start in the debugger, F12 breaks into it'
+                                  --cycle-accurate        'Run the CPU a bus cycle at a time, dummy accesses included'
+                                  --gdb [PORT]            'Wait for a GDB remote protocol client, on port 6502 by default'
+                                  --cdl                   'Log code and data to a .cdl file, added to earlier logs'
+                                  --io-stats [FILE]       'Write counts of hardware register accesses per frame to FILE'
+                                  --io-log [FILE]         'Write every hardware register access to FILE, with the counts'
+                                  <ROM>                   'iNES rom file'")
+                .get_matches();
+
+    let mut config = Config::load()?;
+    let rom = config.find_rom(args.value_of("ROM").unwrap());
+    let mut nes = Nes::from_file(&rom)?;
+    nes.set_cycle_accurate(args.is_present("cycle-accurate"));
+    config.select_game(nes.rom_crc())?;
+    if let Some(expansion) = config.expansion {
+        nes.set_expansion(expansion);
+    }
+
+    // battery-backed memory, saved on exit and from time to time
+    let sav = save_path(&config.save_dir, &rom, "sav");
+    if nes.battery().is_some() && sav.exists() {
+        let data = fs::read(&sav).context(sav.display().to_string())?;
+        nes.load_battery(&data)
+           .map_err(|e| Error::new(format!("{}: {}", sav.display(), e)))?;
+    }
+    let mut saved_battery = nes.battery().map(|ram| ram.to_vec());
+    // frames shown, the PPU's count goes back with rewinds, save states
+    // and movies
+    let mut frames: u64 = 0;
+
+    // cheats of the game, the ones given on the command line are added
+    let cht = save_path(&config.save_dir, &rom, "cht");
+    let mut cheats = if cht.exists() {
+        Cheats::from_file(&cht).map_err(|e| Error::new(format!("{}: {}", cht.display(), e)))?
+    }
+    else {
+        Cheats::new()
+    };
+    if let Some(codes) = args.values_of("cheat") {
+        for code in codes {
+            cheats.add(Cheat::new(code)?);
+        }
+        write_file(&cht, cheats.to_text().as_bytes())?;
+    }
+    nes.bus_mut().cheats = cheats;
+
+    // code/data log in the FCEUX format, merged with earlier sessions
+    let cdl = save_path(&config.save_dir, &rom, "cdl");
+    if args.is_present("cdl") {
+        let mut log = nes.new_cdl();
+        if cdl.exists() {
+            let old = Cdl::from_file(&cdl, log.prg.len(), log.chr.len())
+                          .map_err(|e| Error::new(format!("{}: {}", cdl.display(), e)))?;
+            log.merge(&old)?;
+        }
+        nes.set_cdl(Some(log))?;
+    }
+
+    // hardware register accesses, written out after every frame
+    let mut io_log = match args.value_of("io-log").or_else(|| args.value_of("io-stats")) {
+        Some(path) => {
+            nes.bus_mut().io_log = Some(IoLog::new(args.is_present("io-log")));
+            Some(BufWriter::new(File::create(path).context(path.to_string())?))
+        }
+        None => None,
+    };
+
+    let mut movie = if let Some(path) = args.value_of("record") {
+        let name = rom.file_name().unwrap_or_default().to_string_lossy();
+        MovieMode::Recording(Movie::record(&mut nes, &name, false), PathBuf::from(path))
+    }
+    else if let Some(path) = args.value_of("play") {
+        let movie = Movie::from_file(path)
+                          .and_then(|movie| movie.rewind(&mut nes).map(|_| movie))
+                          .map_err(|e| Error::new(format!("{}: {}", path, e)))?;
+        if movie.is_empty() {
+            return Err(Error::new(format!("{}: no frames", path)));
+        }
+        MovieMode::Playing(movie, 0)
+    }
+    else {
+        MovieMode::Off
+    };
+    // movies run from a blank battery, which is not to replace the save
+    let sav = if matches!(movie, MovieMode::Off) { Some(sav) } else { None };
+
+    let pal = match args.value_of("palette").map(PathBuf::from).or_else(|| config.palette.clone()) {
+        Some(file) => palette::palette_from_file(&file)?,
+        None => DEFAULT_PALETTE,
+    };
+    if let Some(zapper) = nes.device_mut::<Zapper>(1) {
+        zapper.set_palette(pal);
+    }
+
+    let sdl_ctx = sdl2::init().map_err(Error::new)?;
+    let video = sdl_ctx.video().map_err(Error::new)?;
+    let audio = sdl_ctx.audio().map_err(Error::new)?;
+    let game_controller = sdl_ctx.game_controller().map_err(Error::new)?;
+    if let Some(db) = config.controller_db() {
+        game_controller.load_mappings(db).context(db.display().to_string())?;
+    }
+    for mapping in config.mappings() {
+        game_controller.add_mapping(mapping).context(mapping.clone())?;
+    }
+
+    let scale = config.scale as usize;
+    let width = (SCREEN_WIDTH * scale) as u32;
+    let height = (SCREEN_HEIGHT * scale) as u32;
+    let window = video.window("redwhite", width, height).position_centered().build()?;
+    let mut canvas = window.into_canvas().build()?;
+    let creator = canvas.texture_creator();
+    let mut texture = creator.create_texture_streaming(PixelFormatEnum::RGB24,
+                                                       SCREEN_WIDTH as u32,
+                                                       SCREEN_HEIGHT as u32)?;
+    let mut pixels = vec![0u8; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+
+    let spec = AudioSpecDesired {
+        freq: Some(config.sample_rate as i32),
+        channels: Some(1),
+        samples: Some(1024),
+    };
+    let queue: AudioQueue<i16> = audio.open_queue(None, &spec).map_err(Error::new)?;
+    nes.set_sample_rate(queue.spec().freq as u32);
+    queue.resume();
+
+    // hold Backspace to rewind
+    let mut rewind = Rewind::new(config.rewind_interval, config.rewind_memory);
+    let mut rewinding = false;
+    // Ctrl+R, done with the next frame
+    let mut reset = false;
+    // commands are read from the terminal, type help
+    let mut debugger = if args.is_present("debug") { Some(new_debugger(&rom)) } else { None };
+    let mut break_in = debugger.is_some();
+    // the GDB client takes over from the debugger
+    let gdb_port = match args.value_of("gdb") {
+        Some(port) => Some(port.parse::<u16>().map_err(|_| Error::new(format!("bad port {}", port)))?),
+        None if args.is_present("gdb") => Some(GDB_PORT),
+        None => None,
+    };
+    let mut gdb = match gdb_port {
+        Some(port) => {
+            println!("waiting for a GDB client on localhost:{}", port);
+            Some(GdbStub::accept(("127.0.0.1", port), new_debugger(&rom))?)
+        }
+        None => None,
+    };
+
+    let mut pads = Vec::new();
+    let mut buttons = [Buttons::empty(); 4];
+
+    let fps = match nes.region() {
+        Region::Ntsc => NTSC_FPS,
+        Region::Pal => PAL_FPS,
+    };
+    let frame_time = Duration::from_nanos((1e9 / fps) as u64);
+    let mut next_frame = Instant::now();
+
+    let mut event_pump = sdl_ctx.event_pump().map_err(Error::new)?;
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit {..} |
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+                Event::KeyDown { keycode: Some(Keycode::R), keymod, .. }
+                    if keymod.intersects(LCTRLMOD | RCTRLMOD) => reset = true,
+                Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
+                    if debugger.is_none() {
+                        debugger = Some(new_debugger(&rom));
+                    }
+                    break_in = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => rewinding = true,
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => rewinding = false,
+                Event::KeyDown { keycode: Some(key), keymod, repeat: false, .. }
+                    if SLOT_KEYS.contains(&key) => {
+                    let slot = SLOT_KEYS.iter().position(|&k| k == key).unwrap();
+                    let path = save_path(&config.save_dir, &rom, &format!("st{}", slot + 1));
+                    let result = if keymod.intersects(LSHIFTMOD | RSHIFTMOD) {
+                        save_state(&nes, &path).map(|_| "saved")
+                    }
+                    else if matches!(movie, MovieMode::Off) {
+                        load_state(&mut nes, &path).map(|_| "loaded")
+                    }
+                    else {
+                        Err(Error::new("cannot load a state during a movie".to_string()))
+                    };
+                    match result {
+                        Ok(action) => println!("{} state {}", action, slot + 1),
+                        Err(e) => eprintln!("state {}: {}", slot + 1, e),
+                    }
+                }
+                Event::KeyDown { keycode: Some(key), .. } => {
+                    if let Some(&(p, b)) = config.keys.get(&key) {
+                        buttons[p].set(b, true);
+                    }
+                }
+                Event::KeyUp { keycode: Some(key), .. } => {
+                    if let Some(&(p, b)) = config.keys.get(&key) {
+                        buttons[p].set(b, false);
+                    }
+                }
+                Event::MouseMotion { x, y, .. } => {
+                    if let Some(zapper) = nes.device_mut::<Zapper>(1) {
+                        zapper.aim = Some((x as usize / scale, y as usize / scale));
+                    }
+                }
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, .. } => {
+                    if let Some(zapper) = nes.device_mut::<Zapper>(1) {
+                        zapper.trigger = true;
+                    }
+                }
+                Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } => {
+                    if let Some(zapper) = nes.device_mut::<Zapper>(1) {
+                        zapper.trigger = false;
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Ok(pad) = game_controller.open(which) {
+                        println!("gamepad {}: {}", pads.len() + 1, pad.name());
+                        pads.push(pad);
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    pads.retain(|pad| pad.instance_id() != which);
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    if let (Some(p), Some(&b)) = (pad_player(&pads, which), config.pad.get(&button)) {
+                        buttons[p.min(3)].set(b, true);
+                    }
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    if let (Some(p), Some(&b)) = (pad_player(&pads, which), config.pad.get(&button)) {
+                        buttons[p.min(3)].set(b, false);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // rewinding would throw a movie out of sync
+        if rewinding && matches!(movie, MovieMode::Off) {
+            // stay on the oldest state when the buffer runs out
+            if rewind.step_back(&mut nes) {
+                nes.run_frame();
+            }
+        }
+        else {
+            rewind.record(&nes);
+            let frame = Frame { buttons, reset, power: false };
+            reset = false;
+            let finished = match movie {
+                MovieMode::Off if gdb.is_some() => {
+                    frame.apply(&mut nes);
+                    match gdb.as_mut().unwrap().run_frame(&mut nes) {
+                        Ok(Session::Attached) => {}
+                        Ok(Session::Detached) => gdb = None,
+                        Ok(Session::Killed) => break 'running,
+                        Err(e) => {
+                            eprintln!("gdb: {}", e);
+                            gdb = None;
+                        }
+                    }
+                    false
+                }
+                MovieMode::Off => match debugger {
+                    Some(ref mut debugger) => {
+                        frame.apply(&mut nes);
+                        if !debug_frame(debugger, &mut nes, break_in) {
+                            break 'running;
+                        }
+                        break_in = false;
+                        false
+                    }
+                    None => {
+                        frame.run(&mut nes);
+                        false
+                    }
+                },
+                MovieMode::Recording(ref mut m, _) => {
+                    m.record_frame(&mut nes, frame);
+                    false
+                }
+                MovieMode::Playing(ref m, ref mut index) => {
+                    let result = m.play_frame(&mut nes, *index);
+                    *index += 1;
+                    match result {
+                        Ok(()) if *index < m.len() => false,
+                        Ok(()) => {
+                            println!("movie finished");
+                            true
+                        }
+                        Err(e) => {
+                            eprintln!("movie: {}", e);
+                            true
+                        }
+                    }
+                }
+            };
+            if finished {
+                movie = MovieMode::Off;
+            }
+            play(&nes, &queue);
+        }
+
+        if let Some(ref mut out) = io_log {
+            write_io_log(&mut nes, out)?;
+        }
+
+        render(&nes, &pal, &mut pixels);
+        texture.update(None, &pixels, SCREEN_WIDTH * 3)?;
+        canvas.copy(&texture, None, None).map_err(Error::new)?;
+        canvas.present();
+
+        frames += 1;
+        if frames.is_multiple_of(BATTERY_SAVE_FRAMES) {
+            if let Some(ref sav) = sav {
+                save_battery(&nes, sav, &mut saved_battery);
+            }
+        }
+
+        next_frame += frame_time;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        }
+        else {
+            // too slow to keep up, don't try to catch up
+            next_frame = now;
+        }
+    }
+
+    if let Some(ref sav) = sav {
+        save_battery(&nes, sav, &mut saved_battery);
+    }
+    if let Some(log) = nes.cdl() {
+        write_file(&cdl, &log.to_bytes())?;
+    }
+    if let (Some(ref mut out), Some(ref log)) = (io_log, &nes.bus().io_log) {
+        write!(out, "total: {}", log.total())?;
+    }
+    if let MovieMode::Recording(ref m, ref path) = movie {
+        m.save(path).map_err(|e| Error::new(format!("{}: {}", path.display(), e)))?;
+    }
+    Ok(())
+}
+
+fn main() {
+    match run() {
+        Ok(()) => (),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+// CPU memory map
+// https://wiki.nesdev.com/w/index.php/CPU_memory_map
+
+use apu::Apu;
+use cartridge::Cartridge;
+use cdl;
+use cheat::Cheats;
+use debugger::{Watchpoint, WatchHit};
+use ines::Region;
+use input::{Expansion, InputDevice};
+use iolog::IoLog;
+use mem::{Access, Ram, ReadKind};
+use ppu::Ppu;
+
+pub struct Bus {
+    ram: Ram,
+    pub ppu: Ppu,
+    pub apu: Apu,
+    pub cartridge: Cartridge,
+    pub ports: [Box<dyn InputDevice>; 2],
+    pub cheats: Cheats,
+    // checked on every access, see `Debugger`
+    pub watchpoints: Vec<Watchpoint>,
+    watch_hit: Option<WatchHit>,
+    // accesses to hardware registers, see `IoLog`
+    pub io_log: Option<IoLog>,
+    region: Region,
+    // PAL runs 3.2 PPU dots per CPU cycle, count in fifths of a dot
+    ppu_fraction: usize,
+    dma_stall: usize,
+    // an OAM DMA was started since `take_dma_stall`
+    oam_dma: bool,
+    // the last value on the data bus, read back from unmapped addresses
+    open_bus: u8,
+}
+
+// The devices in the controller ports are not saved, their buttons are
+// set again before every frame.
+impl_state!(Bus, ram, ppu, apu, cartridge, ppu_fraction, dma_stall, open_bus);
+
+impl Bus {
+    pub fn new(cartridge: Cartridge, region: Region) -> Self {
+        Bus {
+            ram: Ram::new(),
+            ppu: Ppu::new(region),
+            apu: Apu::new(region),
+            cartridge,
+            ports: Expansion::Standard.devices(),
+            cheats: Cheats::new(),
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            io_log: None,
+            region,
+            ppu_fraction: 0,
+            dma_stall: 0,
+            oam_dma: false,
+            open_bus: 0,
+        }
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.ram[..]
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram[..]
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+        self.dma_stall = 0;
+        self.oam_dma = false;
+    }
+
+    pub fn power_cycle(&mut self) {
+        self.ram = Ram::new();
+        self.ppu = Ppu::new(self.region);
+        self.apu = Apu::new(self.region);
+        self.cartridge.power_on();
+        self.ppu_fraction = 0;
+        self.dma_stall = 0;
+        self.oam_dma = false;
+        self.open_bus = 0;
+    }
+
+    // the first watched access since the last call
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    fn watch(&mut self, addr: u16, value: u8, write: bool) {
+        if self.watch_hit.is_none() && self.watchpoints.iter().any(|w| w.matches(addr, write)) {
+            self.watch_hit = Some(WatchHit { addr, value, write });
+        }
+    }
+
+    // What a read would return, without side effects such as clearing
+    // flags in PPU or APU registers. Those registers are not read.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1fff => self.ram[addr as usize & 0x7ff],
+            0x2000..=0x401f => self.open_bus,
+            _ => self.cartridge.peek_prg(addr),
+        }
+    }
+
+    // Cycles the CPU has to be halted for DMA since the last call, from
+    // `cycle` on. OAM DMA waits one more to start on an odd cycle.
+    pub fn take_dma_stall(&mut self, cycle: usize) -> usize {
+        let mut stall = self.dma_stall;
+        if self.oam_dma && cycle % 2 == 1 {
+            stall += 1;
+        }
+        self.dma_stall = 0;
+        self.oam_dma = false;
+        stall
+    }
+
+    // https://wiki.nesdev.com/w/index.php/PPU_registers#OAMDMA
+    fn oam_dma(&mut self, page: u8) {
+        let base = (page as u16) << 8;
+        for i in 0..256 {
+            let value = self.read_for(base | i, ReadKind::Data);
+            self.ppu.write_oam(value);
+        }
+        self.dma_stall += 513;
+        self.oam_dma = true;
+    }
+
+    // a read as the devices see it, without cheats, watchpoints or logs
+    fn read_devices(&mut self, addr: u16) -> u8 {
+        let value = match addr {
+            0x0000..=0x1fff => self.ram.read(addr),
+            0x2000..=0x3fff => self.ppu.read_register(addr, &mut self.cartridge),
+            // bit 5 is not driven by the APU
+            0x4015 => self.apu.read_status() | (self.open_bus & 0x20),
+            // input devices only drive the lowest bits
+            0x4016 => self.ports[0].read(&self.ppu) | (self.open_bus & 0xe0),
+            0x4017 => self.ports[1].read(&self.ppu) | (self.open_bus & 0xe0),
+            0x4000..=0x401f => self.open_bus,
+            _ => self.cartridge.read_prg(addr),
+        };
+        self.open_bus = value;
+        value
+    }
+}
+
+impl Access for Bus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.read_devices(addr);
+        let value = if self.cheats.is_empty() { value } else { self.cheats.apply(addr, value) };
+        self.open_bus = value;
+        if !self.watchpoints.is_empty() {
+            self.watch(addr, value, false);
+        }
+        if let Some(ref mut log) = self.io_log {
+            log.access(addr, value, false);
+        }
+        value
+    }
+
+    // dummy reads clear flags and clock shift registers as any other,
+    // but the program does not see what they read
+    fn read_for(&mut self, addr: u16, kind: ReadKind) -> u8 {
+        if kind == ReadKind::Dummy {
+            return self.read_devices(addr);
+        }
+        let value = self.read(addr);
+        if addr >= 0x8000 {
+            self.cartridge.log_prg(addr, cdl::prg_flags(kind));
+        }
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.open_bus = value;
+        if !self.watchpoints.is_empty() {
+            self.watch(addr, value, true);
+        }
+        if let Some(ref mut log) = self.io_log {
+            log.access(addr, value, true);
+        }
+        match addr {
+            0x0000..=0x1fff => self.ram.write(addr, value),
+            0x2000..=0x3fff => self.ppu.write_register(addr, value, &mut self.cartridge),
+            0x4014 => self.oam_dma(value),
+            0x4016 => {
+                self.ports[0].write(value);
+                self.ports[1].write(value);
+            }
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.write_register(addr, value),
+            0x4018..=0x401f => {}
+            _ => self.cartridge.write_prg(addr, value),
+        }
+    }
+
+    // advance the rest of the system by one CPU cycle
+    fn tick(&mut self) {
+        if let Some(ref mut log) = self.io_log {
+            log.tick();
+        }
+        self.ppu_fraction += match self.region {
+            Region::Ntsc => 15,
+            Region::Pal => 16,
+        };
+        while self.ppu_fraction >= 5 {
+            self.ppu_fraction -= 5;
+            self.ppu.step(&mut self.cartridge);
+        }
+        self.apu.step();
+        self.cartridge.clock();
+        if let Some(addr) = self.apu.dmc_fetch_addr() {
+            let value = self.read(addr);
+            self.cartridge.log_prg(addr, cdl::DATA | cdl::PCM_AUDIO);
+            self.dma_stall += self.apu.dmc_fill(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cheat::Cheat;
+    use nes::nes_running;
+
+    #[test]
+    fn dummy_reads_are_not_seen() {
+        let mut nes = nes_running(&[]);
+        let cdl = nes.new_cdl();
+        nes.set_cdl(Some(cdl)).unwrap();
+        let bus = nes.bus_mut();
+        bus.ram_mut()[0x10] = 0x55;
+        bus.cheats.add(Cheat::new("0010:99").unwrap());
+        bus.watchpoints.push(Watchpoint { start: 0x0000, end: 0xffff, read: true, write: true });
+        bus.io_log = Some(IoLog::new(true));
+
+        assert_eq!(bus.read_for(0x0010, ReadKind::Dummy), 0x55);
+        // but it is on the data bus
+        assert_eq!(bus.peek(0x4000), 0x55);
+        bus.read_for(0x2002, ReadKind::Dummy);
+        bus.read_for(0xc001, ReadKind::Dummy);
+        assert_eq!(bus.take_watch_hit(), None);
+        assert!(bus.io_log.as_mut().unwrap().take_accesses().is_empty());
+        assert!(bus.cartridge.cdl().unwrap().prg.iter().all(|&flags| flags == 0));
+
+        assert_eq!(bus.read_for(0x0010, ReadKind::Data), 0x99);
+        assert!(bus.take_watch_hit().is_some());
+        bus.read_for(0x2002, ReadKind::Data);
+        assert_eq!(bus.io_log.as_mut().unwrap().take_accesses().len(), 1);
+        bus.read_for(0xc001, ReadKind::Data);
+        assert_eq!(bus.cartridge.cdl().unwrap().prg[1] & cdl::DATA, cdl::DATA);
+    }
+
+    #[test]
+    fn oam_dma_on_odd_cycles() {
+        for &accurate in &[false, true] {
+            // sta $4014 is left on an odd cycle, lda $00 first makes it even
+            for &(code, stall) in &[(&[0x8d, 0x14, 0x40][..], 514),
+                                    (&[0xa5, 0x00, 0x8d, 0x14, 0x40][..], 513)] {
+                let mut nes = nes_running(code);
+                nes.set_cycle_accurate(accurate);
+                if code.len() > 3 {
+                    nes.step();
+                }
+                let start = nes.cpu().cycles();
+                nes.step();
+                assert_eq!(nes.cpu().cycles(), start + 4 + stall);
+            }
+        }
+    }
+}
@@ -0,0 +1,304 @@
+// https://wiki.nesdev.com/w/index.php/Mapper
+
+use bandai::BandaiFcg;
+use cdl::{self, Cdl};
+use error::Error;
+use ines::{Ines, Mirroring};
+use savestate::{State, StateReader, StateWriter};
+
+// The state of a mapper is its registers and RAM, the ROM is not saved.
+pub trait Mapper: State {
+    // CPU side, $4020-$ffff
+    fn read_prg(&mut self, addr: u16) -> u8;
+    fn write_prg(&mut self, addr: u16, value: u8);
+
+    // PPU side, $0000-$1fff
+    fn read_chr(&mut self, addr: u16) -> u8;
+    fn write_chr(&mut self, addr: u16, value: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    // Where a CPU address is in PRG ROM, None if it is not mapped
+    // to PRG ROM. Debuggers use it to tell banks apart.
+    fn prg_offset(&self, addr: u16) -> Option<usize>;
+
+    // what `read_prg` would return, without its side effects
+    fn peek_prg(&self, addr: u16) -> u8;
+
+    // where a PPU address is in CHR ROM, None for CHR RAM
+    fn chr_offset(&self, addr: u16) -> Option<usize>;
+
+    // called every CPU cycle, for mappers counting cycles
+    fn clock(&mut self) {}
+
+    // the level of the IRQ line
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // Memory kept by a battery, PRG RAM or an EEPROM, that is saved
+    // to a .sav file. None if the board has none.
+    fn battery(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn battery_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    // Registers and RAM as they are at power-on, the memory kept by the
+    // battery stays.
+    fn power_on(&mut self);
+
+    // blank the memory kept by the battery, see `Movie::record`
+    fn clear_battery(&mut self) {
+        if let Some(ram) = self.battery_mut() {
+            ram.fill(0);
+        }
+    }
+}
+
+// Mapper 0
+// https://wiki.nesdev.com/w/index.php/NROM
+struct Nrom {
+    prgrom: Vec<u8>,
+    prgram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+    has_battery: bool,
+}
+
+impl Nrom {
+    fn new(rom: Ines) -> Self {
+        let chr_is_ram = rom.chrrom.is_empty();
+        let chr = if chr_is_ram { vec![0; 0x2000] } else { rom.chrrom };
+        Nrom {
+            mirroring: rom.header.mirroring(),
+            has_battery: rom.header.has_battery(),
+            prgrom: rom.prgrom,
+            prgram: vec![0; 0x2000],
+            chr,
+            chr_is_ram,
+        }
+    }
+}
+
+impl State for Nrom {
+    fn save(&self, w: &mut StateWriter) {
+        self.prgram.save(w);
+        if self.chr_is_ram {
+            self.chr.save(w);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        self.prgram.load(r)?;
+        if self.chr_is_ram {
+            self.chr.load(r)?;
+        }
+        Ok(())
+    }
+}
+
+impl Mapper for Nrom {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        self.peek_prg(addr)
+    }
+
+    fn peek_prg(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7fff => self.prgram[addr as usize - 0x6000],
+            // 16 KB images are mirrored into $c000-$ffff
+            0x8000..=0xffff => self.prgrom[(addr as usize - 0x8000) % self.prgrom.len()],
+            _ => 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xffff => Some((addr as usize - 0x8000) % self.prgrom.len()),
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if let 0x6000..=0x7fff = addr {
+            self.prgram[addr as usize - 0x6000] = value;
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr[addr as usize & 0x1fff]
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(addr as usize & 0x1fff) }
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if self.chr_is_ram {
+            self.chr[addr as usize & 0x1fff] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn battery(&self) -> Option<&[u8]> {
+        if self.has_battery { Some(&self.prgram) } else { None }
+    }
+
+    fn battery_mut(&mut self) -> Option<&mut [u8]> {
+        if self.has_battery { Some(&mut self.prgram) } else { None }
+    }
+
+    fn power_on(&mut self) {
+        if !self.has_battery {
+            self.prgram.fill(0);
+        }
+        if self.chr_is_ram {
+            self.chr.fill(0);
+        }
+    }
+}
+
+pub struct Cartridge {
+    mapper: Box<dyn Mapper>,
+    prg_len: usize,
+    chr_len: usize,
+    // code/data log, when logging
+    cdl: Option<Cdl>,
+}
+
+impl State for Cartridge {
+    fn save(&self, w: &mut StateWriter) {
+        self.mapper.save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        self.mapper.load(r)
+    }
+}
+
+impl Cartridge {
+    pub fn new(rom: Ines) -> Result<Self, Error> {
+        if rom.prgrom.is_empty() {
+            return Err(Error::new("no PRG ROM".to_string()));
+        }
+        let (prg_len, chr_len) = (rom.prgrom.len(), rom.chrrom.len());
+        let mapper: Box<dyn Mapper> = match rom.header.mapper() {
+            0 => Box::new(Nrom::new(rom)),
+            16 | 159 => Box::new(BandaiFcg::new(rom)),
+            n => return Err(Error::new(format!("unsupported mapper {}", n))),
+        };
+        Ok(Cartridge { mapper, prg_len, chr_len, cdl: None })
+    }
+
+    pub fn read_prg(&mut self, addr: u16) -> u8 {
+        self.mapper.read_prg(addr)
+    }
+
+    pub fn write_prg(&mut self, addr: u16, value: u8) {
+        self.mapper.write_prg(addr, value)
+    }
+
+    // read by the PPU to render
+    pub fn read_chr(&mut self, addr: u16) -> u8 {
+        self.log_chr(addr, cdl::DRAWN);
+        self.mapper.read_chr(addr)
+    }
+
+    // read by the CPU through $2007
+    pub fn read_chr_data(&mut self, addr: u16) -> u8 {
+        self.log_chr(addr, cdl::READ);
+        self.mapper.read_chr(addr)
+    }
+
+    pub fn write_chr(&mut self, addr: u16, value: u8) {
+        self.mapper.write_chr(addr, value)
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+
+    pub fn prg_offset(&self, addr: u16) -> Option<usize> {
+        self.mapper.prg_offset(addr)
+    }
+
+    pub fn peek_prg(&self, addr: u16) -> u8 {
+        self.mapper.peek_prg(addr)
+    }
+
+    // an empty code/data log for this ROM
+    pub fn new_cdl(&self) -> Cdl {
+        Cdl::new(self.prg_len, self.chr_len)
+    }
+
+    pub fn cdl(&self) -> Option<&Cdl> {
+        self.cdl.as_ref()
+    }
+
+    // start logging on top of `cdl`, or stop with None
+    pub fn set_cdl(&mut self, cdl: Option<Cdl>) -> Result<(), Error> {
+        if let Some(ref cdl) = cdl {
+            if cdl.prg.len() != self.prg_len || cdl.chr.len() != self.chr_len {
+                return Err(Error::new("the code/data log is for another ROM".to_string()));
+            }
+        }
+        self.cdl = cdl;
+        Ok(())
+    }
+
+    // mark a byte of PRG ROM read by the CPU
+    pub fn log_prg(&mut self, addr: u16, flags: u8) {
+        if let Some(ref mut log) = self.cdl {
+            if let Some(offset) = self.mapper.prg_offset(addr) {
+                log.prg[offset] |= flags | cdl::window(addr);
+            }
+        }
+    }
+
+    fn log_chr(&mut self, addr: u16, flags: u8) {
+        if let Some(ref mut log) = self.cdl {
+            if let Some(offset) = self.mapper.chr_offset(addr) {
+                log.chr[offset] |= flags;
+            }
+        }
+    }
+
+    pub fn clock(&mut self) {
+        self.mapper.clock()
+    }
+
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    pub fn battery(&self) -> Option<&[u8]> {
+        self.mapper.battery()
+    }
+
+    pub fn power_on(&mut self) {
+        self.mapper.power_on()
+    }
+
+    pub fn clear_battery(&mut self) {
+        self.mapper.clear_battery()
+    }
+
+    // restore the memory kept by the battery, e.g. from a .sav file
+    pub fn load_battery(&mut self, data: &[u8]) -> Result<(), Error> {
+        match self.mapper.battery_mut() {
+            Some(ref mut ram) if ram.len() == data.len() => {
+                ram.copy_from_slice(data);
+                Ok(())
+            }
+            Some(ref ram) => Err(Error::new(format!("battery save has {} bytes where {} are expected",
+                                                    data.len(), ram.len()))),
+            None => Err(Error::new("the cartridge has no battery".to_string())),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// Code/data logs in the FCEUX format: a byte of flags for every byte
+// of PRG ROM, followed by one for every byte of CHR ROM.
+// http://fceux.com/web/help/CodeDataLogger.html
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use error::Error;
+use mem::ReadKind;
+
+// PRG ROM flags
+pub const CODE: u8 = 0x01;
+pub const DATA: u8 = 0x02;
+// bits 2-3 are the 8 KB window the byte was seen through, see `window`
+pub const INDIRECT_CODE: u8 = 0x10;
+pub const INDIRECT_DATA: u8 = 0x20;
+pub const PCM_AUDIO: u8 = 0x40;
+
+// CHR ROM flags
+pub const DRAWN: u8 = 0x01;
+pub const READ: u8 = 0x02;
+
+// the window bits of a CPU address, $8000 is 0, $a000 1...
+pub fn window(addr: u16) -> u8 {
+    ((addr >> 13) as u8 & 0x03) << 2
+}
+
+// the flags for a CPU read
+pub fn prg_flags(kind: ReadKind) -> u8 {
+    match kind {
+        ReadKind::Opcode | ReadKind::Operand => CODE,
+        ReadKind::Data => DATA,
+        ReadKind::IndirectOpcode => CODE | INDIRECT_CODE,
+        ReadKind::IndirectData => DATA | INDIRECT_DATA,
+        ReadKind::Dummy => 0,
+    }
+}
+
+pub struct Cdl {
+    pub prg: Vec<u8>,
+    pub chr: Vec<u8>,
+}
+
+impl Cdl {
+    pub fn new(prg_len: usize, chr_len: usize) -> Self {
+        Cdl { prg: vec![0; prg_len], chr: vec![0; chr_len] }
+    }
+
+    pub fn from_bytes(bytes: &[u8], prg_len: usize, chr_len: usize) -> Result<Self, Error> {
+        if bytes.len() != prg_len + chr_len {
+            return Err(Error::new(format!("code/data log has {} bytes where {} are expected",
+                                          bytes.len(), prg_len + chr_len)));
+        }
+        Ok(Cdl { prg: bytes[..prg_len].to_vec(), chr: bytes[prg_len..].to_vec() })
+    }
+
+    // add what another log, e.g. of another session, has seen
+    pub fn merge(&mut self, other: &Cdl) -> Result<(), Error> {
+        if self.prg.len() != other.prg.len() || self.chr.len() != other.chr.len() {
+            return Err(Error::new("code/data logs of different ROMs".to_string()));
+        }
+        for (flags, other) in self.prg.iter_mut().zip(&other.prg) {
+            *flags |= other;
+        }
+        for (flags, other) in self.chr.iter_mut().zip(&other.chr) {
+            *flags |= other;
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.prg.clone();
+        bytes.extend_from_slice(&self.chr);
+        bytes
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P, prg_len: usize, chr_len: usize) -> Result<Self, Error> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        Cdl::from_bytes(&bytes, prg_len, chr_len)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        File::create(path)?.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nes::nes_running;
+
+    #[test]
+    fn logged_while_running() {
+        let mut code = vec![
+            0xad, 0x00, 0xc1, // lda $c100
+            0xad, 0x01, 0x81, // lda $8101, the same bank through $8000
+            0xb1, 0x10,       // lda ($10),y
+            0xa9, 0x0c,       // lda #$0c
+            0x8d, 0x12, 0x40, // sta $4012, a DMC sample at $c300
+            0xa9, 0x10,       // lda #$10
+            0x8d, 0x15, 0x40, // sta $4015
+            0xa9, 0x00,       // lda #$00
+            0x8d, 0x06, 0x20, // sta $2006
+            0xa9, 0x10,       // lda #$10
+            0x8d, 0x06, 0x20, // sta $2006
+            0xad, 0x07, 0x20, // lda $2007, CHR $0010
+            0xa9, 0x08,       // lda #$08
+            0x8d, 0x01, 0x20, // sta $2001, background on
+            0x6c, 0x10, 0xc1, // jmp ($c110)
+        ];
+        code.resize(0x200, 0);
+        code[0x110] = 0x00;
+        code[0x111] = 0xc2;
+        // c200: jmp $c200
+        code.extend_from_slice(&[0x4c, 0x00, 0xc2]);
+
+        for &accurate in &[false, true] {
+            let mut nes = nes_running(&code);
+            nes.set_cycle_accurate(accurate);
+            let cdl = nes.new_cdl();
+            nes.set_cdl(Some(cdl)).unwrap();
+            // for the reset vector to be read again
+            nes.reset();
+            // the pointer of lda ($10),y
+            nes.bus_mut().ram_mut()[0x10..0x12].copy_from_slice(&[0x02, 0xc1]);
+            nes.run_frame();
+            nes.run_frame();
+
+            let cdl = nes.cdl().unwrap();
+            let high = window(0xc000);
+            assert_eq!(high, 0x08);
+            assert_eq!(window(0x8000), 0x00);
+            assert_eq!(window(0xa000), 0x04);
+            assert_eq!(window(0xe000), 0x0c);
+            assert_eq!(cdl.prg[0x000], CODE | high);
+            assert_eq!(cdl.prg[0x002], CODE | high);
+            assert_eq!(cdl.prg[0x100], DATA | high);
+            assert_eq!(cdl.prg[0x101], DATA);
+            assert_eq!(cdl.prg[0x102], DATA | INDIRECT_DATA | high);
+            assert_eq!(cdl.prg[0x110], DATA | high);
+            assert_eq!(cdl.prg[0x200], CODE | INDIRECT_CODE | high);
+            // only the opcode is reached through the pointer
+            assert_eq!(cdl.prg[0x201], CODE | high);
+            assert_eq!(cdl.prg[0x300], DATA | PCM_AUDIO | high);
+            assert_eq!(cdl.prg[0x301], 0);
+            assert_eq!(cdl.prg[0x3ffc], DATA | window(0xfffc));
+
+            // the background is tile 0
+            assert_eq!(cdl.chr[0x000], DRAWN);
+            assert_eq!(cdl.chr[0x010], READ);
+            assert_eq!(cdl.chr[0x020], 0);
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut cdl = Cdl::from_bytes(&[CODE, 0, DATA, DRAWN], 3, 1).unwrap();
+        let other = Cdl::from_bytes(&[DATA, 0, DATA | PCM_AUDIO, READ], 3, 1).unwrap();
+        cdl.merge(&other).unwrap();
+        assert_eq!(cdl.to_bytes(), [CODE | DATA, 0, DATA | PCM_AUDIO, DRAWN | READ]);
+
+        assert!(cdl.merge(&Cdl::new(3, 2)).is_err());
+        assert!(cdl.merge(&Cdl::new(4, 0)).is_err());
+    }
+
+    #[test]
+    fn from_bytes() {
+        let cdl = Cdl::from_bytes(&[1, 2, 3], 2, 1).unwrap();
+        assert_eq!(cdl.prg, [1, 2]);
+        assert_eq!(cdl.chr, [3]);
+        for bytes in [&[1, 2][..], &[1, 2, 3, 4][..]].iter() {
+            match Cdl::from_bytes(bytes, 2, 1) {
+                Err(e) => assert!(e.to_string().contains("where 3 are expected"), "{}", e),
+                Ok(_) => panic!("{} bytes taken", bytes.len()),
+            }
+        }
+    }
+}
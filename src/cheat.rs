@@ -0,0 +1,223 @@
+// Cheats patch what the CPU reads from the bus: Game Genie codes
+// patch the ROM, raw codes work anywhere, e.g. to freeze a RAM value.
+//
+// Codes are either
+//
+//     SXIOPO              6-letter Game Genie
+//     YEUZUGAA            8-letter Game Genie, with a compare value
+//     0075:09             address:value, in hex
+//     e0f4:a5:ea          address:compare:value, in hex
+//
+// Cheat lists are text files with a code per line, optionally followed
+// by a description. Lines starting with '#' are comments, a code
+// starting with '-' is disabled.
+// https://wiki.nesdev.com/w/index.php/Game_Genie
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use error::Error;
+
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    // as entered, written back to cheat lists
+    pub code: String,
+    pub description: String,
+    pub enabled: bool,
+    pub addr: u16,
+    pub value: u8,
+    // only patch when the original value is this one
+    pub compare: Option<u8>,
+}
+
+impl Cheat {
+    pub fn new(code: &str) -> Result<Self, Error> {
+        let code = code.trim();
+        let (addr, value, compare) = if code.contains(':') {
+            decode_raw(code)?
+        }
+        else {
+            decode_game_genie(code)?
+        };
+        Ok(Cheat {
+            code: code.to_string(),
+            description: String::new(),
+            enabled: true,
+            addr,
+            value,
+            compare,
+        })
+    }
+
+    // the value read from `addr`, with the cheat applied
+    pub fn apply(&self, addr: u16, value: u8) -> u8 {
+        if !self.enabled || addr != self.addr {
+            return value;
+        }
+        match self.compare {
+            Some(compare) if compare != value => value,
+            _ => self.value,
+        }
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/Game_Genie#Decoding
+fn decode_game_genie(code: &str) -> Result<(u16, u8, Option<u8>), Error> {
+    let n: Vec<u16> = code.chars()
+                          .map(|c| GAME_GENIE_LETTERS.find(c.to_ascii_uppercase()).map(|i| i as u16))
+                          .collect::<Option<_>>()
+                          .ok_or_else(|| Error::new(format!("bad Game Genie code {}", code)))?;
+    if n.len() != 6 && n.len() != 8 {
+        return Err(Error::new(format!("Game Genie codes have 6 or 8 letters: {}", code)));
+    }
+    let addr = 0x8000
+        | (n[3] & 7) << 12
+        | (n[5] & 7) << 8 | (n[4] & 8) << 8
+        | (n[2] & 7) << 4 | (n[1] & 8) << 4
+        | (n[4] & 7) | (n[3] & 8);
+    let value = (n[1] & 7) << 4 | (n[0] & 8) << 4 | (n[0] & 7);
+    if n.len() == 6 {
+        Ok((addr, (value | (n[5] & 8)) as u8, None))
+    }
+    else {
+        let compare = (n[7] & 7) << 4 | (n[6] & 8) << 4 | (n[6] & 7) | (n[5] & 8);
+        Ok((addr, (value | (n[7] & 8)) as u8, Some(compare as u8)))
+    }
+}
+
+fn decode_raw(code: &str) -> Result<(u16, u8, Option<u8>), Error> {
+    let bad = || Error::new(format!("bad cheat {}, expected address:value or address:compare:value",
+                                    code));
+    let fields: Vec<&str> = code.split(':').collect();
+    let addr = u16::from_str_radix(fields[0], 16).map_err(|_| bad())?;
+    let byte = |s: &str| u8::from_str_radix(s, 16).map_err(|_| bad());
+    match fields.len() {
+        2 => Ok((addr, byte(fields[1])?, None)),
+        3 => Ok((addr, byte(fields[2])?, Some(byte(fields[1])?))),
+        _ => Err(bad()),
+    }
+}
+
+#[derive(Default)]
+pub struct Cheats {
+    list: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Cheats { list: Vec::new() }
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.list
+    }
+
+    pub fn list_mut(&mut self) -> &mut Vec<Cheat> {
+        &mut self.list
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.list.push(cheat);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    // called on every CPU read
+    pub fn apply(&self, addr: u16, value: u8) -> u8 {
+        self.list.iter().fold(value, |value, cheat| cheat.apply(addr, value))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut cheats = Cheats::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let code = parts.next().unwrap_or("");
+            let (code, enabled) = match code.strip_prefix('-') {
+                Some(code) => (code, false),
+                None => (code, true),
+            };
+            let mut cheat = Cheat::new(code)
+                                  .map_err(|e| Error::new(format!("line {}: {}", n + 1, e)))?;
+            cheat.enabled = enabled;
+            cheat.description = parts.next().unwrap_or("").trim().to_string();
+            cheats.add(cheat);
+        }
+        Ok(cheats)
+    }
+
+    pub fn to_text(&self) -> String {
+        self.list.iter().map(|cheat| {
+            let prefix = if cheat.enabled { "" } else { "-" };
+            if cheat.description.is_empty() {
+                format!("{}{}\n", prefix, cheat.code)
+            }
+            else {
+                format!("{}{} {}\n", prefix, cheat.code, cheat.description)
+            }
+        }).collect()
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        Cheats::parse(&text)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        File::create(path)?.write_all(self.to_text().as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // examples from https://wiki.nesdev.com/w/index.php/Game_Genie
+    #[test]
+    fn game_genie() {
+        assert_eq!(decode_game_genie("GOSSIP").unwrap(), (0xd1dd, 0x14, None));
+        assert_eq!(decode_game_genie("YEUZUGAA").unwrap(), (0xacb3, 0x07, Some(0x00)));
+        // infinite lives in Super Mario Bros.
+        assert_eq!(decode_game_genie("sxiopo").unwrap(), (0x91d9, 0xad, None));
+        assert!(decode_game_genie("SXIOP").is_err());
+        assert!(decode_game_genie("SXIOPQ").is_err());
+    }
+
+    #[test]
+    fn raw() {
+        assert_eq!(decode_raw("0075:09").unwrap(), (0x0075, 0x09, None));
+        assert_eq!(decode_raw("e0f4:a5:ea").unwrap(), (0xe0f4, 0xea, Some(0xa5)));
+        assert!(decode_raw("e0f4").is_err());
+        assert!(decode_raw("e0f4:1:2:3").is_err());
+        assert!(decode_raw("e0f4:100").is_err());
+    }
+
+    #[test]
+    fn compare_value() {
+        let cheat = Cheat::new("e0f4:a5:ea").unwrap();
+        assert_eq!(cheat.apply(0xe0f4, 0xa5), 0xea);
+        assert_eq!(cheat.apply(0xe0f4, 0x00), 0x00);
+        assert_eq!(cheat.apply(0xe0f5, 0xa5), 0xa5);
+    }
+
+    #[test]
+    fn cheat_list() {
+        let text = "# lives\nSXIOPO infinite lives\n-0075:09\n";
+        let cheats = Cheats::parse(text).unwrap();
+        assert_eq!(cheats.list().len(), 2);
+        assert_eq!(cheats.list()[0].description, "infinite lives");
+        assert!(!cheats.list()[1].enabled);
+        assert_eq!(cheats.apply(0x0075, 0x01), 0x01);
+        assert_eq!(cheats.to_text(), "SXIOPO infinite lives\n-0075:09\n");
+        assert!(Cheats::parse("0075\n").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+// https://wiki.nesdev.com/w/index.php/Standard_controller
+
+use std::ops::{BitOr, BitOrAssign};
+
+// Button state of a standard controller, in the order they are
+// shifted out: A, B, Select, Start, Up, Down, Left, Right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Buttons(pub u8);
+
+impl Buttons {
+    pub const A:      Buttons = Buttons(0b0000_0001);
+    pub const B:      Buttons = Buttons(0b0000_0010);
+    pub const SELECT: Buttons = Buttons(0b0000_0100);
+    pub const START:  Buttons = Buttons(0b0000_1000);
+    pub const UP:     Buttons = Buttons(0b0001_0000);
+    pub const DOWN:   Buttons = Buttons(0b0010_0000);
+    pub const LEFT:   Buttons = Buttons(0b0100_0000);
+    pub const RIGHT:  Buttons = Buttons(0b1000_0000);
+
+    pub fn empty() -> Self {
+        Buttons(0)
+    }
+
+    pub fn contains(self, other: Buttons) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn set(&mut self, other: Buttons, pressed: bool) {
+        if pressed {
+            self.0 |= other.0;
+        }
+        else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+impl BitOr for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, other: Buttons) -> Buttons {
+        Buttons(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Buttons {
+    fn bitor_assign(&mut self, other: Buttons) {
+        self.0 |= other.0;
+    }
+}
+
+#[derive(Default)]
+pub struct Controller {
+    buttons: Buttons,
+    shift: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    pub fn new() -> Self {
+        Controller::default()
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+    }
+
+    // $4016 write, only bit 0 matters
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift = self.buttons.0;
+        }
+    }
+
+    // $4016/$4017 read, returns bit 0 only, the upper bits come from
+    // the open bus
+    pub fn read(&mut self) -> u8 {
+        // while strobe is high the shift register keeps reloading
+        if self.strobe {
+            return self.buttons.0 & 1;
+        }
+        let bit = self.shift & 1;
+        // official controllers shift in 1s, so reads after the
+        // 8th one all return 1
+        self.shift = (self.shift >> 1) | 0x80;
+        bit
+    }
+}
@@ -1,44 +1,7 @@
-use mem::{Memory, Access};
-
-const CYCLES: [usize;256] = [
-    //       0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
-    /* 0 */  7, 6, 0, 0, 0, 3, 5, 0, 3, 2, 2, 0, 0, 4, 6, 0,
-    /* 1 */  2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
-    /* 2 */  6, 6, 0, 0, 3, 3, 5, 0, 4, 2, 2, 0, 4, 4, 6, 0,
-    /* 3 */  2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
-    /* 4 */  6, 6, 0, 0, 0, 3, 5, 0, 3, 2, 2, 0, 3, 4, 6, 0,
-    /* 5 */  2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
-    /* 6 */  6, 6, 0, 0, 0, 3, 5, 0, 4, 2, 2, 0, 5, 4, 6, 0,
-    /* 7 */  2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
-    /* 8 */  0, 6, 0, 0, 3, 3, 3, 0, 2, 0, 2, 0, 4, 4, 4, 0,
-    /* 9 */  2, 6, 0, 0, 4, 4, 4, 0, 2, 5, 2, 0, 0, 5, 0, 0,
-    /* a */  2, 6, 2, 0, 3, 3, 3, 0, 2, 2, 2, 0, 4, 4, 4, 0,
-    /* b */  2, 5, 0, 0, 4, 4, 4, 0, 2, 4, 2, 0, 4, 4, 4, 0,
-    /* c */  2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, 0, 4, 4, 6, 0,
-    /* d */  2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
-    /* e */  2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, 0, 4, 4, 6, 0,
-    /* f */  2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0,
-];
-
-const XPAGE_CYCLES: [usize;256] = [
-    //       0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
-    /* 0 */  0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    /* 1 */  1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0,
-    /* 2 */  0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    /* 3 */  1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0,
-    /* 4 */  0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    /* 5 */  1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0,
-    /* 6 */  0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    /* 7 */  1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0,
-    /* 8 */  0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    /* 9 */  1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    /* a */  0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    /* b */  1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 1, 0,
-    /* c */  0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    /* d */  1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0,
-    /* e */  0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    /* f */  1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0,
-];
+use error::Error;
+use mem::{Access, ReadKind};
+use opcode::{Instruction, Mode, Opcode, OPCODES, OPCODES_65C02};
+use savestate::{State, StateReader, StateWriter};
 
 // status flags
 const NEGATIVE:  u8 = 0b1000_0000;
@@ -50,61 +13,154 @@ const INTERRUPT: u8 = 0b0000_0100;
 const ZERO:      u8 = 0b0000_0010;
 const CARRY:     u8 = 0b0000_0001;
 
-pub struct Cpu {
+// interrupt vectors
+const NMI_VECTOR:   u16 = 0xfffa;
+const RESET_VECTOR: u16 = 0xfffc;
+const IRQ_VECTOR:   u16 = 0xfffe;
+
+// the registers as seen by a program, for debuggers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Registers {
+    pub a:  u8,
+    pub x:  u8,
+    pub y:  u8,
+    pub sp: u8,
+    pub p:  u8,
+    pub pc: u16,
+}
+
+// the CPU emulated, for reusing it outside of the NES
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    // the NES CPU, an NMOS 6502 without decimal mode
+    Ricoh2A03,
+    // ADC and SBC honor the D flag
+    Nmos6502,
+    // the CMOS 65C02, see `OPCODES_65C02`, with decimal mode
+    Cmos65C02,
+}
+
+pub struct Cpu<M: Access> {
     a:  u8,
     x:  u8,
     y:  u8,
     sp: u8,
     p:  u8,
     pc: u16,
-    mem: Memory,
+    mem: M,
     cycles: usize,
     check_xpage: bool,
+    nmi_pending: bool,
+    irq_line: bool,
+    // the last instruction was JMP ($nnnn)
+    indirect_jump: bool,
+    variant: Variant,
+    // every cycle is a bus access, see `set_cycle_accurate`
+    cycle_accurate: bool,
+    // stores and read-modify-write instructions spend the cycle fixing
+    // the high byte of an indexed address even if the page is the same
+    always_fix: bool,
 }
 
-impl Access for Cpu {
-    fn read(&self, addr: u16) -> u8 {
-        self.mem.read(addr)
+impl<M: Access> Access for Cpu<M> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.read_kind(addr, ReadKind::Data)
     }
 
     fn write(&mut self, addr: u16, value: u8) {
+        self.bus_cycle();
         self.mem.write(addr, value)
     }
 }
 
+// the registers, followed by the state of the memory
+impl<M: Access + State> State for Cpu<M> {
+    fn save(&self, w: &mut StateWriter) {
+        self.a.save(w);
+        self.x.save(w);
+        self.y.save(w);
+        self.sp.save(w);
+        self.p.save(w);
+        self.pc.save(w);
+        self.cycles.save(w);
+        self.nmi_pending.save(w);
+        self.irq_line.save(w);
+        self.mem.save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        self.a.load(r)?;
+        self.x.load(r)?;
+        self.y.load(r)?;
+        self.sp.load(r)?;
+        self.p.load(r)?;
+        self.pc.load(r)?;
+        self.cycles.load(r)?;
+        self.nmi_pending.load(r)?;
+        self.irq_line.load(r)?;
+        self.mem.load(r)
+    }
+}
+
 trait Addressing {
-    fn address(&self, cpu: &mut Cpu) -> u8;
-    fn writeback(&self, _cpu: &mut Cpu, _value: u8) {}
+    fn address<M: Access>(&self, cpu: &mut Cpu<M>) -> u8;
+    fn writeback<M: Access>(&self, _cpu: &mut Cpu<M>, _value: u8) {}
+
+    // the write of a read-modify-write instruction
+    fn modify<M: Access>(&self, cpu: &mut Cpu<M>, _old: u8, new: u8) {
+        self.writeback(cpu, new);
+    }
 }
 
 struct Immediate;
 struct Accumulator;
 struct FromMemory { addr: u16 }
+// through a pointer, ($nn,x) and ($nn),y
+struct FromPointer { addr: u16 }
 
 impl Addressing for Immediate {
-    fn address(&self, cpu: &mut Cpu) -> u8 {
+    fn address<M: Access>(&self, cpu: &mut Cpu<M>) -> u8 {
         cpu.read_at_pc()
     }
 }
 
 impl Addressing for Accumulator {
-    fn address(&self, cpu: &mut Cpu) -> u8 {
+    fn address<M: Access>(&self, cpu: &mut Cpu<M>) -> u8 {
         cpu.a
     }
 
-    fn writeback(&self, cpu: &mut Cpu, value: u8) {
+    fn writeback<M: Access>(&self, cpu: &mut Cpu<M>, value: u8) {
         cpu.a = value;
     }
 }
 
 impl Addressing for FromMemory {
-    fn address(&self, cpu: &mut Cpu) -> u8 {
+    fn address<M: Access>(&self, cpu: &mut Cpu<M>) -> u8 {
         cpu.read(self.addr)
     }
 
-    fn writeback(&self, cpu: &mut Cpu, value: u8) {
+    fn writeback<M: Access>(&self, cpu: &mut Cpu<M>, value: u8) {
         cpu.write(self.addr, value);
     }
+
+    fn modify<M: Access>(&self, cpu: &mut Cpu<M>, old: u8, new: u8) {
+        cpu.modify(self.addr, old, new);
+    }
+}
+
+impl Addressing for FromPointer {
+    fn address<M: Access>(&self, cpu: &mut Cpu<M>) -> u8 {
+        cpu.read_kind(self.addr, ReadKind::IndirectData)
+    }
+
+    fn writeback<M: Access>(&self, cpu: &mut Cpu<M>, value: u8) {
+        cpu.write(self.addr, value);
+    }
+
+    // only the unofficial read-modify-write instructions use pointers
+    fn modify<M: Access>(&self, cpu: &mut Cpu<M>, old: u8, new: u8) {
+        cpu.modify(self.addr, old, new);
+    }
 }
 
 macro_rules! inst {
@@ -114,8 +170,57 @@ macro_rules! inst {
     }}
 }
 
-impl Cpu {
-    pub fn new() -> Self {
+// an instruction taking an operand, in the addressing mode of the opcode
+macro_rules! operand_inst {
+    ($cpu:ident, $inst_fn:ident, $mode:expr) => {
+        match $mode {
+            Mode::Accumulator => inst!($cpu, $inst_fn, accumulator),
+            Mode::Immediate => inst!($cpu, $inst_fn, immediate),
+            Mode::ZeroPage => inst!($cpu, $inst_fn, zeropage),
+            Mode::ZeroPageX => inst!($cpu, $inst_fn, zeropage_x),
+            Mode::ZeroPageY => inst!($cpu, $inst_fn, zeropage_y),
+            Mode::Absolute => inst!($cpu, $inst_fn, absolute),
+            Mode::AbsoluteX => inst!($cpu, $inst_fn, absolute_x),
+            Mode::AbsoluteY => inst!($cpu, $inst_fn, absolute_y),
+            Mode::IndirectX => inst!($cpu, $inst_fn, indexed_indirect),
+            Mode::IndirectY => inst!($cpu, $inst_fn, indirect_indexed),
+            Mode::ZeroPageIndirect => inst!($cpu, $inst_fn, zeropage_indirect),
+            Mode::Implied | Mode::Indirect | Mode::Relative | Mode::AbsoluteIndirectX => unreachable!(),
+        }
+    }
+}
+
+// Decimal SBC on an NMOS 6502, the flags are those of the binary
+// difference.
+// http://www.6502.org/tutorials/decimal_mode.html#A
+fn sbc_decimal(a: u8, operand: u8, borrow: u8) -> u8 {
+    let mut low = (a & 0x0f) as i16 - (operand & 0x0f) as i16 - borrow as i16;
+    if low < 0 {
+        low = ((low - 0x06) & 0x0f) - 0x10;
+    }
+    let mut diff = (a & 0xf0) as i16 - (operand & 0xf0) as i16 + low;
+    if diff < 0 {
+        diff -= 0x60;
+    }
+    diff as u8
+}
+
+// Decimal SBC on a 65C02, N and Z are those of the result.
+// http://www.6502.org/tutorials/decimal_mode.html#A
+fn sbc_decimal_65c02(a: u8, operand: u8, borrow: u8) -> u8 {
+    let low = (a & 0x0f) as i16 - (operand & 0x0f) as i16 - borrow as i16;
+    let mut diff = a as i16 - operand as i16 - borrow as i16;
+    if diff < 0 {
+        diff -= 0x60;
+    }
+    if low < 0 {
+        diff -= 0x06;
+    }
+    diff as u8
+}
+
+impl<M: Access> Cpu<M> {
+    pub fn new(mem: M) -> Self {
         // Set power-up state
         // Ref: https://wiki.nesdev.com/w/index.php/CPU_power_up_state
         Cpu {
@@ -125,9 +230,151 @@ impl Cpu {
             sp: 0xfd,
             pc: 0,
             p:  0x34,
-            mem: Memory::new(),
+            mem,
             cycles: 0,
             check_xpage: false,
+            nmi_pending: false,
+            irq_line: false,
+            indirect_jump: false,
+            variant: Variant::Ricoh2A03,
+            cycle_accurate: false,
+            always_fix: false,
+        }
+    }
+
+    pub fn mem(&self) -> &M {
+        &self.mem
+    }
+
+    pub fn mem_mut(&mut self) -> &mut M {
+        &mut self.mem
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    // Break instructions into their bus cycles, dummy reads and the
+    // double writes of read-modify-write instructions included, and
+    // tick the memory before each (see `Access::tick`). Otherwise the
+    // memory is only accessed for what instructions do, and `step`
+    // leaves ticking to the caller.
+    // http://nesdev.com/6502_cpu.txt
+    pub fn set_cycle_accurate(&mut self, on: bool) {
+        self.cycle_accurate = on;
+    }
+
+    pub fn cycle_accurate(&self) -> bool {
+        self.cycle_accurate
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers { a: self.a, x: self.x, y: self.y, sp: self.sp, p: self.p, pc: self.pc }
+    }
+
+    pub fn set_registers(&mut self, regs: Registers) {
+        self.a = regs.a;
+        self.x = regs.x;
+        self.y = regs.y;
+        self.sp = regs.sp;
+        self.p = regs.p;
+        self.pc = regs.pc;
+    }
+
+    // Put the registers back to their power-up state and jump
+    // through the reset vector.
+    pub fn power_on(&mut self) {
+        self.a = 0;
+        self.x = 0;
+        self.y = 0;
+        self.sp = 0xfd;
+        self.p = 0x34;
+        self.cycles = 0;
+        self.nmi_pending = false;
+        self.irq_line = false;
+        self.reset_cycles();
+        self.pc = self.read16(RESET_VECTOR);
+    }
+
+    // The reset line only decrements SP and sets the I flag.
+    pub fn reset(&mut self) {
+        self.reset_cycles();
+        self.sp = self.sp.wrapping_sub(3);
+        self.set_flag(INTERRUPT);
+        self.nmi_pending = false;
+        self.pc = self.read16(RESET_VECTOR);
+    }
+
+    // Reset is an interrupt with the pushes turned into reads, the vector
+    // is read after these 5 cycles.
+    fn reset_cycles(&mut self) {
+        if !self.cycle_accurate {
+            self.cycles += 7;
+            return;
+        }
+        let pc = self.pc;
+        self.dummy_read(pc);
+        self.dummy_read(pc);
+        for i in 0..3 {
+            let addr = 0x0100 + self.sp.wrapping_sub(i) as u16;
+            self.dummy_read(addr);
+        }
+    }
+
+    // NMI is edge triggered, it is serviced before the next instruction.
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    // IRQ is level triggered, it stays asserted as long as a device holds it.
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    // Halt the CPU for some cycles, e.g. during DMA.
+    pub fn stall(&mut self, cycles: usize) {
+        self.cycles += cycles;
+    }
+
+    // Execute one instruction, or service a pending interrupt,
+    // and return the number of cycles it took.
+    pub fn step(&mut self) -> usize {
+        let start = self.cycles;
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.interrupt(NMI_VECTOR);
+        }
+        else if self.irq_line && !self.flag_on(INTERRUPT) {
+            self.interrupt(IRQ_VECTOR);
+        }
+        else {
+            self.dispatch();
+        }
+        self.cycles - start
+    }
+
+    fn interrupt(&mut self, vector: u16) {
+        self.indirect_jump = false;
+        let pc = self.pc;
+        // the opcode fetched is thrown away, and read again
+        self.dummy_read(pc);
+        self.dummy_read(pc);
+        self.push16(pc);
+        let p = self.p;
+        self.push(p & !BREAK | UNKNOWN);
+        self.set_flag(INTERRUPT);
+        self.clear_decimal();
+        self.pc = self.read16(vector);
+        if !self.cycle_accurate {
+            self.cycles += 7;
         }
     }
 
@@ -143,7 +390,12 @@ impl Cpu {
 
     #[inline(always)]
     fn update_flag(&mut self, flag: u8, cond: bool) {
-        self.set_flag(if cond { flag } else { !flag });
+        if cond {
+            self.set_flag(flag);
+        }
+        else {
+            self.clear_flag(flag);
+        }
     }
 
     #[inline(always)]
@@ -157,22 +409,78 @@ impl Cpu {
         self.p & flag != 0
     }
 
+    fn opcodes(&self) -> &'static [Opcode; 256] {
+        match self.variant {
+            Variant::Cmos65C02 => &OPCODES_65C02,
+            _ => &OPCODES,
+        }
+    }
+
+    // the 65C02 leaves decimal mode on interrupts
+    fn clear_decimal(&mut self) {
+        if self.variant == Variant::Cmos65C02 {
+            self.clear_flag(DECIMAL);
+        }
+    }
+
+    // a cycle of the bus, the rest of the system catches up before it
+    // in cycle-accurate mode
+    fn bus_cycle(&mut self) {
+        if self.cycle_accurate {
+            self.mem.tick();
+            self.cycles += 1;
+        }
+    }
+
+    fn read_kind(&mut self, addr: u16, kind: ReadKind) -> u8 {
+        self.bus_cycle();
+        self.mem.read_for(addr, kind)
+    }
+
+    // a read only made in cycle-accurate mode, for its side effects
+    fn dummy_read(&mut self, addr: u16) {
+        if self.cycle_accurate {
+            self.read_kind(addr, ReadKind::Dummy);
+        }
+    }
+
+    // The value read is written back while the new one is computed, the
+    // 65C02 reads it again instead.
+    fn modify(&mut self, addr: u16, old: u8, new: u8) {
+        if self.variant == Variant::Cmos65C02 {
+            self.dummy_read(addr);
+        }
+        else if self.cycle_accurate {
+            self.write(addr, old);
+        }
+        self.write(addr, new);
+    }
+
+    fn fetch_opcode(&mut self) -> u8 {
+        let kind = if self.indirect_jump { ReadKind::IndirectOpcode } else { ReadKind::Opcode };
+        self.indirect_jump = false;
+        let pc = self.pc;
+        let value = self.read_kind(pc, kind);
+        self.pc = pc.wrapping_add(1);
+        value
+    }
+
     fn read_at_pc(&mut self) -> u8 {
-        let value = self.read(self.pc);
-        self.pc += 1;
+        let pc = self.pc;
+        let value = self.read_kind(pc, ReadKind::Operand);
+        self.pc = pc.wrapping_add(1);
         value
     }
 
     fn read16_at_pc(&mut self) -> u16 {
-        let value = self.read16(self.pc);
-        self.pc += 2;
-        value
+        let value = self.read_at_pc() as u16;
+        value | (self.read_at_pc() as u16) << 8
     }
 
     fn push(&mut self, value: u8) {
         let addr = self.sp as u16 + 0x0100;
         self.write(addr, value);
-        self.sp -= 1;
+        self.sp = self.sp.wrapping_sub(1);
     }
 
     fn push16(&mut self, value: u16) {
@@ -181,14 +489,14 @@ impl Cpu {
     }
 
     fn pop(&mut self) -> u8 {
-        self.sp += 1;
-        self.read(self.sp as u16 + 0x0100)
+        self.sp = self.sp.wrapping_add(1);
+        let addr = self.sp as u16 + 0x0100;
+        self.read(addr)
     }
 
     fn pop16(&mut self) -> u16 {
         let value = self.pop() as u16;
-        let value = value | (self.pop() as u16) << 8;
-        value
+        value | (self.pop() as u16) << 8
     }
 
     fn page_crossed(&self, addr1: u16, addr2: u16) -> bool {
@@ -197,16 +505,40 @@ impl Cpu {
 
     fn jump(&mut self, addr: u16, condition: bool) {
         if condition {
-            // +1 cycle if branch is taken
-            self.cycles += 1;
-            // +1 cycle if branching across page boundary
-            if self.page_crossed(self.pc, addr) {
+            // +1 cycle if branch is taken, reading the next opcode
+            let pc = self.pc;
+            self.dummy_read(pc);
+            // +1 cycle if branching across page boundary, reading where
+            // the high byte is not fixed yet
+            if self.page_crossed(pc, addr) {
+                self.dummy_read(pc & 0xff00 | addr & 0x00ff);
+                if !self.cycle_accurate {
+                    self.cycles += 1;
+                }
+            }
+            if !self.cycle_accurate {
                 self.cycles += 1;
             }
             self.pc = addr;
         }
     }
 
+    // Indexing adds to the low byte of the address first. The read from
+    // there is thrown away if the page is wrong, and always is for
+    // stores and read-modify-write instructions. The 65C02 reads the last
+    // byte of the instruction instead.
+    fn fix_high_byte(&mut self, base: u16, addr: u16) {
+        if self.cycle_accurate && (self.always_fix || self.page_crossed(base, addr)) {
+            let unfixed = if self.variant == Variant::Cmos65C02 {
+                self.pc.wrapping_sub(1)
+            }
+            else {
+                base & 0xff00 | addr & 0x00ff
+            };
+            self.dummy_read(unfixed);
+        }
+    }
+
     // addressing modes: http://obelisk.me.uk/6502/addressing.html
     fn immediate(&self) -> Immediate {
         Immediate {}
@@ -220,12 +552,17 @@ impl Cpu {
         FromMemory { addr: self.read_at_pc() as u16 }
     }
 
+    // the base address is read before the index is added
     fn zeropage_x(&mut self) -> FromMemory {
-        FromMemory { addr: self.read_at_pc().wrapping_add(self.x) as u16 }
+        let base = self.read_at_pc();
+        self.dummy_read(base as u16);
+        FromMemory { addr: base.wrapping_add(self.x) as u16 }
     }
 
     fn zeropage_y(&mut self) -> FromMemory {
-        FromMemory { addr: self.read_at_pc().wrapping_add(self.y) as u16 }
+        let base = self.read_at_pc();
+        self.dummy_read(base as u16);
+        FromMemory { addr: base.wrapping_add(self.y) as u16 }
     }
 
     fn absolute(&mut self) -> FromMemory {
@@ -233,55 +570,131 @@ impl Cpu {
     }
 
     fn absolute_x(&mut self) -> FromMemory {
-        let pc = self.pc;
-        let addr = self.read16_at_pc().wrapping_add(self.x as u16);
-        self.check_xpage = self.page_crossed(pc, addr);
+        let base = self.read16_at_pc();
+        let addr = base.wrapping_add(self.x as u16);
+        self.check_xpage = self.page_crossed(base, addr);
+        self.fix_high_byte(base, addr);
         FromMemory { addr }
     }
 
     fn absolute_y(&mut self) -> FromMemory {
-        let pc = self.pc;
-        let addr = self.read16_at_pc().wrapping_add(self.y as u16);
-        self.check_xpage = self.page_crossed(pc, addr);
+        let base = self.read16_at_pc();
+        let addr = base.wrapping_add(self.y as u16);
+        self.check_xpage = self.page_crossed(base, addr);
+        self.fix_high_byte(base, addr);
         FromMemory { addr }
     }
 
+    // the 65C02 reads a pointer at $xxff across the page, taking a cycle
+    // more than the 6502
     fn indirect(&mut self) -> FromMemory {
         let a = self.read16_at_pc();
+        if self.variant == Variant::Cmos65C02 {
+            let pc = self.pc.wrapping_sub(1);
+            self.dummy_read(pc);
+            return FromMemory { addr: self.read16(a) };
+        }
         FromMemory { addr: self.read16_wrapped(a) }
     }
 
-    fn indexed_indirect(&mut self) -> FromMemory {
-        let a = self.read_at_pc().wrapping_add(self.x);
-        FromMemory { addr: self.read16_wrapped(a as u16) }
+    fn absolute_indirect_x(&mut self) -> FromMemory {
+        let base = self.read16_at_pc();
+        let pc = self.pc.wrapping_sub(1);
+        self.dummy_read(pc);
+        FromMemory { addr: self.read16(base.wrapping_add(self.x as u16)) }
+    }
+
+    fn zeropage_indirect(&mut self) -> FromPointer {
+        let a = self.read_at_pc();
+        FromPointer { addr: self.read16_wrapped(a as u16) }
     }
 
-    fn indirect_indexed(&mut self) -> FromMemory {
+    fn indexed_indirect(&mut self) -> FromPointer {
+        let base = self.read_at_pc();
+        self.dummy_read(base as u16);
+        let a = base.wrapping_add(self.x);
+        FromPointer { addr: self.read16_wrapped(a as u16) }
+    }
+
+    fn indirect_indexed(&mut self) -> FromPointer {
         let a = self.read_at_pc();
         let v = self.read16_wrapped(a as u16);
         let addr = v.wrapping_add(self.y as u16);
         self.check_xpage = self.page_crossed(v, addr);
-        FromMemory { addr }
+        self.fix_high_byte(v, addr);
+        FromPointer { addr }
     }
 
     fn relative(&mut self) -> FromMemory {
-        let offset = self.read_at_pc();
-        FromMemory { addr: ((self.pc as i16) + (offset as i16)) as u16 }
+        let offset = self.read_at_pc() as i8;
+        FromMemory { addr: self.pc.wrapping_add(offset as u16) }
     }
 
     // instructions
+    fn decimal_mode(&self) -> bool {
+        self.variant != Variant::Ricoh2A03 && self.flag_on(DECIMAL)
+    }
+
     fn adc<T: Addressing>(&mut self, mode: T) {
         let operand = mode.address(self);
-        let mut sum = operand as u16 +
+        self.add(operand);
+    }
+
+    // ADC, also the second half of RRA
+    fn add(&mut self, operand: u8) {
+        let carry = if self.flag_on(CARRY) { 1 } else { 0 };
+        let sum = operand as u16 +
                       self.a as u16 +
-                      if self.flag_on(CARRY) { 1 } else { 0 };
+                      carry as u16;
         self.update_flag(CARRY, sum > 0xff);
         let result = sum as u8;
         self.update_zero_negative(result);
         let a = self.a;
         let cond = (a ^ operand) & 0x80 == 0 && (a ^ result) & 0x80 != 0;
         self.update_flag(OVERFLOW, cond);
-        self.a = result;
+        if self.decimal_mode() {
+            self.adc_decimal(a, operand, carry);
+        }
+        else {
+            self.a = result;
+        }
+    }
+
+    // The 65C02 sets N and Z from the decimal result, taking a cycle
+    // more to read the last byte of the instruction again.
+    fn after_decimal(&mut self) {
+        if self.variant != Variant::Cmos65C02 {
+            return;
+        }
+        let a = self.a;
+        self.update_zero_negative(a);
+        if self.cycle_accurate {
+            let pc = self.pc.wrapping_sub(1);
+            self.dummy_read(pc);
+        }
+        else {
+            self.cycles += 1;
+        }
+    }
+
+    // Decimal ADC on an NMOS 6502. Z is left as the binary sum sets it,
+    // N and V come from the sum before the high digit is adjusted.
+    // http://www.6502.org/tutorials/decimal_mode.html#A
+    fn adc_decimal(&mut self, a: u8, operand: u8, carry: u8) {
+        let mut low = (a & 0x0f) + (operand & 0x0f) + carry;
+        if low >= 0x0a {
+            low = ((low + 0x06) & 0x0f) + 0x10;
+        }
+        let signed = (a & 0xf0) as i8 as i16 + (operand & 0xf0) as i8 as i16 + low as i16;
+        self.update_flag(NEGATIVE, signed & 0x80 != 0);
+        self.update_flag(OVERFLOW, !(-128..=127).contains(&signed));
+        let mut sum = (a & 0xf0) as u16 + (operand & 0xf0) as u16 + low as u16;
+        if sum >= 0xa0 {
+            sum += 0x60;
+        }
+        self.update_flag(CARRY, sum >= 0x100);
+        self.a = sum as u8;
+        self.after_decimal();
     }
 
     fn and<T: Addressing>(&mut self, mode: T) {
@@ -296,7 +709,7 @@ impl Cpu {
         self.update_flag(CARRY, operand & 0x80 != 0);
         let result = operand << 1;
         self.update_zero_negative(result);
-        mode.writeback(self, result);
+        mode.modify(self, operand, result);
     }
 
     fn bcc(&mut self, mode: FromMemory) {
@@ -347,32 +760,46 @@ impl Cpu {
         self.update_flag(ZERO, cond);
     }
 
+    // BIT #$nn on the 65C02 only sets Z
+    fn bit_immediate(&mut self) {
+        let operand = self.read_at_pc();
+        let cond = operand & self.a == 0;
+        self.update_flag(ZERO, cond);
+    }
+
+    fn bra(&mut self, mode: FromMemory) {
+        self.jump(mode.addr, true);
+    }
+
+    fn compare(&mut self, register: u8, operand: u8) {
+        let result = register.wrapping_sub(operand);
+        self.update_flag(CARRY, register >= operand);
+        self.update_zero_negative(result);
+    }
+
     fn cmp<T: Addressing>(&mut self, mode: T) {
         let operand = mode.address(self);
-        let result = self.a as i8 - operand as i8;
-        self.update_flag(CARRY, result >= 0);
-        self.update_zero_negative(result as u8);
+        let a = self.a;
+        self.compare(a, operand);
     }
 
     fn cpx<T: Addressing>(&mut self, mode: T) {
         let operand = mode.address(self);
-        let result = self.x as i8 - operand as i8;
-        self.update_flag(CARRY, result >= 0);
-        self.update_zero_negative(result as u8);
+        let x = self.x;
+        self.compare(x, operand);
     }
 
     fn cpy<T: Addressing>(&mut self, mode: T) {
         let operand = mode.address(self);
-        let result = self.y as i8 - operand as i8;
-        self.update_flag(CARRY, result >= 0);
-        self.update_zero_negative(result as u8);
+        let y = self.y;
+        self.compare(y, operand);
     }
 
     fn dec<T: Addressing>(&mut self, mode: T) {
         let operand = mode.address(self);
         let result = operand.wrapping_sub(1);
         self.update_zero_negative(result);
-        mode.writeback(self, result);
+        mode.modify(self, operand, result);
     }
 
     fn dex(&mut self) {
@@ -398,7 +825,7 @@ impl Cpu {
         let operand = mode.address(self);
         let result = operand.wrapping_add(1);
         self.update_zero_negative(result);
-        mode.writeback(self, result);
+        mode.modify(self, operand, result);
     }
 
     fn inx(&mut self) {
@@ -418,10 +845,21 @@ impl Cpu {
         self.pc = mode.addr;
     }
 
-    fn jsr(&mut self, mode: FromMemory) {
-        let ret = self.pc - 1;
+    // the return address is pushed between reading the two bytes of the
+    // target, it is the address of the second
+    fn jsr(&mut self) {
+        let low = self.read_at_pc() as u16;
+        let sp = 0x0100 + self.sp as u16;
+        self.dummy_read(sp);
+        let ret = self.pc;
         self.push16(ret);
-        self.pc = mode.addr;
+        self.pc = low | (self.read_at_pc() as u16) << 8;
+    }
+
+    // a pull increments SP first, on a cycle of its own
+    fn before_pull(&mut self) {
+        let sp = 0x0100 + self.sp as u16;
+        self.dummy_read(sp);
     }
 
     fn lda<T: Addressing>(&mut self, mode: T) {
@@ -447,7 +885,7 @@ impl Cpu {
         self.update_flag(CARRY, operand & 0x1 != 0);
         let result = operand >> 1;
         self.update_zero_negative(result);
-        mode.writeback(self, result);
+        mode.modify(self, operand, result);
     }
 
     fn ora<T: Addressing>(&mut self, mode: T) {
@@ -466,7 +904,7 @@ impl Cpu {
         self.update_flag(CARRY, shift > 0xff);
         let result = shift as u8;
         self.update_zero_negative(result);
-        mode.writeback(self, result);
+        mode.modify(self, operand, result);
     }
 
     fn ror<T: Addressing>(&mut self, mode: T) {
@@ -478,25 +916,96 @@ impl Cpu {
         self.update_flag(CARRY, shift & 0x1 != 0);
         let result = (shift >> 1) as u8;
         self.update_zero_negative(result);
-        mode.writeback(self, result);
+        mode.modify(self, operand, result);
     }
 
     fn rts(&mut self) {
-        self.pc = self.pop16() + 1;
+        self.before_pull();
+        let ret = self.pop16();
+        self.dummy_read(ret);
+        self.pc = ret.wrapping_add(1);
+    }
+
+    fn rti(&mut self) {
+        self.before_pull();
+        // the Break flag does not exist in the register
+        self.p = self.pop() & !BREAK | UNKNOWN;
+        self.pc = self.pop16();
+    }
+
+    fn brk(&mut self) {
+        // BRK skips a padding byte after the opcode
+        let ret = self.pc.wrapping_add(1);
+        self.push16(ret);
+        let p = self.p;
+        self.push(p | BREAK | UNKNOWN);
+        self.set_flag(INTERRUPT);
+        self.clear_decimal();
+        self.pc = self.read16(IRQ_VECTOR);
+    }
+
+    // The NOPs of the 65C02 skip their operand and spend their cycles.
+    // `start` is the cycle count before the opcode was fetched.
+    fn nop(&mut self, op: &Opcode, start: usize) {
+        for _ in 0..op.mode.operand_len() {
+            self.read_at_pc();
+        }
+        while self.cycle_accurate && self.cycles - start < op.cycles as usize {
+            let pc = self.pc;
+            self.dummy_read(pc);
+        }
     }
 
     fn sbc<T: Addressing>(&mut self, mode: T) {
         let operand = mode.address(self);
-        let diff = self.a as u16 -
-                   operand as u16 -
-                   if self.flag_on(CARRY) { 0 } else { 1 };
+        self.subtract(operand);
+    }
+
+    // SBC, also the second half of ISC
+    fn subtract(&mut self, operand: u8) {
+        let borrow = if self.flag_on(CARRY) { 0 } else { 1 };
+        let diff = (self.a as u16)
+                   .wrapping_sub(operand as u16)
+                   .wrapping_sub(borrow as u16);
         self.update_flag(CARRY, diff < 0x100);
         let result = diff as u8;
         self.update_zero_negative(result);
         let a = self.a;
         let cond = (a ^ result) & 0x80 != 0 && (a ^ operand) & 0x80 != 0;
         self.update_flag(OVERFLOW, cond);
-        self.a = result;
+        if !self.decimal_mode() {
+            self.a = result;
+        }
+        else if self.variant == Variant::Cmos65C02 {
+            self.a = sbc_decimal_65c02(a, operand, borrow);
+            self.after_decimal();
+        }
+        else {
+            self.a = sbc_decimal(a, operand, borrow);
+        }
+    }
+
+    #[inline(always)]
+    fn stz<T: Addressing>(&mut self, mode: T) {
+        mode.writeback(self, 0);
+    }
+
+    // Z tells if any bit of A was set in memory, then they are reset
+    fn trb<T: Addressing>(&mut self, mode: T) {
+        let operand = mode.address(self);
+        let cond = operand & self.a == 0;
+        self.update_flag(ZERO, cond);
+        let result = operand & !self.a;
+        mode.modify(self, operand, result);
+    }
+
+    // or set
+    fn tsb<T: Addressing>(&mut self, mode: T) {
+        let operand = mode.address(self);
+        let cond = operand & self.a == 0;
+        self.update_flag(ZERO, cond);
+        let result = operand | self.a;
+        mode.modify(self, operand, result);
     }
 
     #[inline(always)]
@@ -535,12 +1044,6 @@ impl Cpu {
         self.x = sp;
     }
 
-    fn tsy(&mut self) {
-        let sp = self.sp;
-        self.update_zero_negative(sp);
-        self.y = sp;
-    }
-
     fn txa(&mut self) {
         let x = self.x;
         self.update_zero_negative(x);
@@ -548,9 +1051,7 @@ impl Cpu {
     }
 
     fn txs(&mut self) {
-        let x = self.x;
-        self.update_zero_negative(x);
-        self.sp = x;
+        self.sp = self.x;
     }
 
     fn tya(&mut self) {
@@ -559,212 +1060,663 @@ impl Cpu {
         self.a = y;
     }
 
-    /*
-    fn rti(&mut self) {
-        cpu.p = cpu.pop();
-        cpu.pc = cpu.pop_word() as u16;
+    // unofficial instructions of the NMOS 6502
+    // https://wiki.nesdev.com/w/index.php/CPU_unofficial_opcodes
+    // http://www.oxyron.de/html/opcodes02.html
+
+    // the AND immediate of ALR, ANC and ARR
+    fn and_immediate(&mut self) -> u8 {
+        let result = self.read_at_pc() & self.a;
+        self.update_zero_negative(result);
+        self.a = result;
+        result
+    }
+
+    fn alr(&mut self) {
+        self.and_immediate();
+        let mode = self.accumulator();
+        self.lsr(mode);
+    }
+
+    // C is copied from N
+    fn anc(&mut self) {
+        let result = self.and_immediate();
+        self.update_flag(CARRY, result & 0x80 != 0);
+    }
+
+    // The ROR gets C from bit 6 and V from bits 6 and 5 of the result.
+    // Decimal mode is not emulated.
+    fn arr(&mut self) {
+        self.and_immediate();
+        let mode = self.accumulator();
+        self.ror(mode);
+        let a = self.a;
+        self.update_flag(CARRY, a & 0x40 != 0);
+        self.update_flag(OVERFLOW, (a ^ (a << 1)) & 0x40 != 0);
+    }
+
+    // X = A & X - operand, without borrow, C and flags as CMP sets them
+    fn axs(&mut self) {
+        let operand = self.read_at_pc();
+        let and = self.a & self.x;
+        self.compare(and, operand);
+        self.x = and.wrapping_sub(operand);
+    }
+
+    fn dcp<T: Addressing>(&mut self, mode: T) {
+        let operand = mode.address(self);
+        let result = operand.wrapping_sub(1);
+        mode.modify(self, operand, result);
+        let a = self.a;
+        self.compare(a, result);
+    }
+
+    fn isc<T: Addressing>(&mut self, mode: T) {
+        let operand = mode.address(self);
+        let result = operand.wrapping_add(1);
+        mode.modify(self, operand, result);
+        self.subtract(result);
+    }
+
+    // KIL stops the CPU until a reset, it is left fetching the same
+    // opcode. Interrupts still get through, unlike on a real 6502.
+    fn kil(&mut self) {
+        self.pc = self.pc.wrapping_sub(1);
+        if self.cycle_accurate {
+            self.dummy_read(0xffff);
+        }
+        else {
+            self.cycles += 2;
+        }
+    }
+
+    fn las<T: Addressing>(&mut self, mode: T) {
+        let result = mode.address(self) & self.sp;
+        self.update_zero_negative(result);
+        self.a = result;
+        self.x = result;
+        self.sp = result;
+    }
+
+    fn lax<T: Addressing>(&mut self, mode: T) {
+        let operand = mode.address(self);
+        self.update_zero_negative(operand);
+        self.a = operand;
+        self.x = operand;
+    }
+
+    // XAA mixes in some bits of A depending on the chip, $ee is what most
+    // do
+    fn xaa(&mut self) {
+        let result = (self.a | 0xee) & self.x & self.read_at_pc();
+        self.update_zero_negative(result);
+        self.a = result;
+    }
+
+    // NOPs with an operand read it like any other instruction
+    fn ign<T: Addressing>(&mut self, mode: T) {
+        mode.address(self);
+    }
+
+    fn rla<T: Addressing>(&mut self, mode: T) {
+        let operand = mode.address(self);
+        let result = operand << 1 | if self.flag_on(CARRY) { 1 } else { 0 };
+        self.update_flag(CARRY, operand & 0x80 != 0);
+        mode.modify(self, operand, result);
+        let a = self.a & result;
+        self.update_zero_negative(a);
+        self.a = a;
+    }
+
+    fn rra<T: Addressing>(&mut self, mode: T) {
+        let operand = mode.address(self);
+        let result = operand >> 1 | if self.flag_on(CARRY) { 0x80 } else { 0 };
+        self.update_flag(CARRY, operand & 0x01 != 0);
+        mode.modify(self, operand, result);
+        self.add(result);
+    }
+
+    fn sax<T: Addressing>(&mut self, mode: T) {
+        let result = self.a & self.x;
+        mode.writeback(self, result);
+    }
+
+    fn slo<T: Addressing>(&mut self, mode: T) {
+        let operand = mode.address(self);
+        let result = operand << 1;
+        self.update_flag(CARRY, operand & 0x80 != 0);
+        mode.modify(self, operand, result);
+        let a = self.a | result;
+        self.update_zero_negative(a);
+        self.a = a;
+    }
+
+    fn sre<T: Addressing>(&mut self, mode: T) {
+        let operand = mode.address(self);
+        let result = operand >> 1;
+        self.update_flag(CARRY, operand & 0x01 != 0);
+        mode.modify(self, operand, result);
+        let a = self.a ^ result;
+        self.update_zero_negative(a);
+        self.a = a;
+    }
+
+    // AHX, SHX, SHY and TAS store a value ANDed with the high byte of the
+    // base address plus one. When indexing crosses a page, the value is
+    // the high byte of the address too.
+    fn store_high(&mut self, addr: u16, index: u8, value: u8) {
+        let base = addr.wrapping_sub(index as u16);
+        let value = value & ((base >> 8) as u8).wrapping_add(1);
+        let addr = if self.page_crossed(base, addr) {
+            (value as u16) << 8 | addr & 0x00ff
+        }
+        else {
+            addr
+        };
+        self.write(addr, value);
     }
-    */
 
     fn dispatch(&mut self) {
-        let opcode = self.read_at_pc();
-        match opcode {
-            0x69 => inst!(self, adc, immediate),
-            0x65 => inst!(self, adc, zeropage),
-            0x75 => inst!(self, adc, zeropage_x),
-            0x6d => inst!(self, adc, absolute),
-            0x7d => inst!(self, adc, absolute_x),
-            0x79 => inst!(self, adc, absolute_y),
-            0x61 => inst!(self, adc, indexed_indirect),
-            0x71 => inst!(self, adc, indirect_indexed),
-
-            0x29 => inst!(self, and, immediate),
-            0x25 => inst!(self, and, zeropage),
-            0x35 => inst!(self, and, zeropage_x),
-            0x2d => inst!(self, and, absolute),
-            0x3d => inst!(self, and, absolute_x),
-            0x39 => inst!(self, and, absolute_y),
-            0x21 => inst!(self, and, indexed_indirect),
-            0x31 => inst!(self, and, indirect_indexed),
-
-            0x0a => inst!(self, asl, accumulator),
-            0x06 => inst!(self, asl, zeropage),
-            0x16 => inst!(self, asl, zeropage_x),
-            0x0e => inst!(self, asl, absolute),
-            0x1e => inst!(self, asl, absolute_x),
-
-            0x90 => inst!(self, bcc, relative),
-            0xb0 => inst!(self, bcs, relative),
-            0xf0 => inst!(self, beq, relative),
-            0x30 => inst!(self, bmi, relative),
-            0xd0 => inst!(self, bne, relative),
-            0x10 => inst!(self, bpl, relative),
-            0x50 => inst!(self, bvc, relative),
-            0x70 => inst!(self, bvs, relative),
-
-            0x24 => inst!(self, bit, zeropage),
-            0x2c => inst!(self, bit, absolute),
-
-            0x18 => self.clear_flag(CARRY),     // clc
-            0xd8 => self.clear_flag(DECIMAL),   // cld
-            0x58 => self.clear_flag(INTERRUPT), // cli
-            0xb8 => self.clear_flag(OVERFLOW),  // clv
-
-            0xc9 => inst!(self, cmp, immediate),
-            0xc5 => inst!(self, cmp, zeropage),
-            0xd5 => inst!(self, cmp, zeropage_x),
-            0xcd => inst!(self, cmp, absolute),
-            0xdd => inst!(self, cmp, absolute_x),
-            0xd9 => inst!(self, cmp, absolute_y),
-            0xc1 => inst!(self, cmp, indexed_indirect),
-            0xd1 => inst!(self, cmp, indirect_indexed),
-
-            0xe0 => inst!(self, cpx, immediate),
-            0xe4 => inst!(self, cpx, zeropage),
-            0xec => inst!(self, cpx, absolute),
-
-            0xc0 => inst!(self, cpy, immediate),
-            0xc4 => inst!(self, cpy, zeropage),
-            0xcc => inst!(self, cpy, absolute),
-
-            0xc6 => inst!(self, dec, zeropage),
-            0xd6 => inst!(self, dec, zeropage_x),
-            0xce => inst!(self, dec, absolute),
-            0xde => inst!(self, dec, absolute_x),
-
-            0xca => self.dex(),
-            0x88 => self.dey(),
-
-            0x49 => inst!(self, eor, immediate),
-            0x45 => inst!(self, eor, zeropage),
-            0x55 => inst!(self, eor, zeropage_x),
-            0x4d => inst!(self, eor, absolute),
-            0x5d => inst!(self, eor, absolute_x),
-            0x59 => inst!(self, eor, absolute_y),
-            0x41 => inst!(self, eor, indexed_indirect),
-            0x51 => inst!(self, eor, indirect_indexed),
-
-            0xe6 => inst!(self, inc, zeropage),
-            0xf6 => inst!(self, inc, zeropage_x),
-            0xee => inst!(self, inc, absolute),
-            0xfe => inst!(self, inc, absolute_x),
-
-            0xe8 => self.inx(),
-            0xc8 => self.iny(),
-
-            0x4c => inst!(self, jmp, absolute),
-            0x6c => inst!(self, jmp, indirect),
-    
-            0x20 => inst!(self, jsr, absolute),
-    
-            0xa9 => inst!(self, lda, immediate),
-            0xa5 => inst!(self, lda, zeropage),
-            0xb5 => inst!(self, lda, zeropage_x),
-            0xad => inst!(self, lda, absolute),
-            0xbd => inst!(self, lda, absolute_x),
-            0xb9 => inst!(self, lda, absolute_y),
-            0xa1 => inst!(self, lda, indexed_indirect),
-            0xb1 => inst!(self, lda, indirect_indexed),
-
-            0xa2 => inst!(self, ldx, immediate),
-            0xa6 => inst!(self, ldx, zeropage),
-            0xb6 => inst!(self, ldx, zeropage_y),
-            0xae => inst!(self, ldx, absolute),
-            0xbe => inst!(self, ldx, absolute_y),
-
-            0xa0 => inst!(self, ldy, immediate),
-            0xa4 => inst!(self, ldy, zeropage),
-            0xb4 => inst!(self, ldy, zeropage_x),
-            0xac => inst!(self, ldy, absolute),
-            0xbc => inst!(self, ldy, absolute_x),
-
-            0x4a => inst!(self, lsr, accumulator),
-            0x46 => inst!(self, lsr, zeropage),
-            0x56 => inst!(self, lsr, zeropage_x),
-            0x4e => inst!(self, lsr, absolute),
-            0x5e => inst!(self, lsr, absolute_x),
-
-            0xea => (), // nop
-
-            0x09 => inst!(self, ora, immediate),
-            0x05 => inst!(self, ora, zeropage),
-            0x15 => inst!(self, ora, zeropage_x),
-            0x0d => inst!(self, ora, absolute),
-            0x1d => inst!(self, ora, absolute_x),
-            0x19 => inst!(self, ora, absolute_y),
-            0x01 => inst!(self, ora, indexed_indirect),
-            0x11 => inst!(self, ora, indirect_indexed),
-
-            0x48 => { // pha
+        let start = self.cycles;
+        let opcode = self.fetch_opcode();
+        let op: &Opcode = &self.opcodes()[opcode as usize];
+        self.check_xpage = false;
+        self.always_fix = op.xpage_cycles == 0;
+        // instructions without an operand read the byte after the opcode,
+        // but for the one-cycle NOPs of the 65C02
+        if (op.mode == Mode::Implied || op.mode == Mode::Accumulator) && op.cycles > 1 {
+            let pc = self.pc;
+            self.dummy_read(pc);
+        }
+        match op.instruction {
+            Instruction::Adc => operand_inst!(self, adc, op.mode),
+            Instruction::And => operand_inst!(self, and, op.mode),
+            Instruction::Asl => operand_inst!(self, asl, op.mode),
+
+            Instruction::Bcc => inst!(self, bcc, relative),
+            Instruction::Bcs => inst!(self, bcs, relative),
+            Instruction::Beq => inst!(self, beq, relative),
+            Instruction::Bmi => inst!(self, bmi, relative),
+            Instruction::Bne => inst!(self, bne, relative),
+            Instruction::Bpl => inst!(self, bpl, relative),
+            Instruction::Bvc => inst!(self, bvc, relative),
+            Instruction::Bvs => inst!(self, bvs, relative),
+
+            Instruction::Bit if op.mode == Mode::Immediate => self.bit_immediate(),
+            Instruction::Bit => operand_inst!(self, bit, op.mode),
+            Instruction::Bra => inst!(self, bra, relative),
+            Instruction::Brk => self.brk(),
+
+            Instruction::Clc => self.clear_flag(CARRY),
+            Instruction::Cld => self.clear_flag(DECIMAL),
+            Instruction::Cli => self.clear_flag(INTERRUPT),
+            Instruction::Clv => self.clear_flag(OVERFLOW),
+
+            Instruction::Cmp => operand_inst!(self, cmp, op.mode),
+            Instruction::Cpx => operand_inst!(self, cpx, op.mode),
+            Instruction::Cpy => operand_inst!(self, cpy, op.mode),
+
+            Instruction::Dec => operand_inst!(self, dec, op.mode),
+            Instruction::Dex => self.dex(),
+            Instruction::Dey => self.dey(),
+
+            Instruction::Eor => operand_inst!(self, eor, op.mode),
+
+            Instruction::Inc => operand_inst!(self, inc, op.mode),
+            Instruction::Inx => self.inx(),
+            Instruction::Iny => self.iny(),
+
+            Instruction::Jmp if op.mode == Mode::Indirect => {
+                inst!(self, jmp, indirect);
+                self.indirect_jump = true;
+            }
+            Instruction::Jmp if op.mode == Mode::AbsoluteIndirectX => {
+                inst!(self, jmp, absolute_indirect_x);
+                self.indirect_jump = true;
+            }
+            Instruction::Jmp => inst!(self, jmp, absolute),
+            Instruction::Jsr => self.jsr(),
+
+            Instruction::Lda => operand_inst!(self, lda, op.mode),
+            Instruction::Ldx => operand_inst!(self, ldx, op.mode),
+            Instruction::Ldy => operand_inst!(self, ldy, op.mode),
+            Instruction::Lsr => operand_inst!(self, lsr, op.mode),
+
+            Instruction::Nop if self.variant == Variant::Cmos65C02 => self.nop(op, start),
+            Instruction::Nop if op.mode == Mode::Implied => (),
+            Instruction::Nop => operand_inst!(self, ign, op.mode),
+
+            Instruction::Ora => operand_inst!(self, ora, op.mode),
+
+            Instruction::Pha => {
                 let a = self.a;
                 self.push(a);
             }
 
-            0x08 => { // php
+            Instruction::Php => {
                 let p = self.p;
                 // PHP always pushes Break flag as 1
-                self.push(p | BREAK);
+                self.push(p | BREAK | UNKNOWN);
+            }
+
+            Instruction::Phx => {
+                let x = self.x;
+                self.push(x);
+            }
+
+            Instruction::Phy => {
+                let y = self.y;
+                self.push(y);
+            }
+
+            Instruction::Pla => {
+                self.before_pull();
+                let a = self.pop();
+                self.update_zero_negative(a);
+                self.a = a;
+            }
+
+            Instruction::Plp => {
+                self.before_pull();
+                // the Break flag does not exist in the register
+                self.p = self.pop() & !BREAK | UNKNOWN;
+            }
+
+            Instruction::Plx => {
+                self.before_pull();
+                let x = self.pop();
+                self.update_zero_negative(x);
+                self.x = x;
+            }
+
+            Instruction::Ply => {
+                self.before_pull();
+                let y = self.pop();
+                self.update_zero_negative(y);
+                self.y = y;
+            }
+
+            Instruction::Rol => operand_inst!(self, rol, op.mode),
+            Instruction::Ror => operand_inst!(self, ror, op.mode),
+
+            Instruction::Rti => self.rti(),
+            Instruction::Rts => self.rts(),
+
+            Instruction::Sbc => operand_inst!(self, sbc, op.mode),
+
+            Instruction::Sec => self.set_flag(CARRY),
+            Instruction::Sed => self.set_flag(DECIMAL),
+            Instruction::Sei => self.set_flag(INTERRUPT),
+
+            Instruction::Sta => operand_inst!(self, sta, op.mode),
+            Instruction::Stx => operand_inst!(self, stx, op.mode),
+            Instruction::Sty => operand_inst!(self, sty, op.mode),
+            Instruction::Stz => operand_inst!(self, stz, op.mode),
+
+            Instruction::Tax => self.tax(),
+            Instruction::Tay => self.tay(),
+            Instruction::Trb => operand_inst!(self, trb, op.mode),
+            Instruction::Tsb => operand_inst!(self, tsb, op.mode),
+            Instruction::Tsx => self.tsx(),
+            Instruction::Txa => self.txa(),
+            Instruction::Txs => self.txs(),
+            Instruction::Tya => self.tya(),
+
+            Instruction::Ahx => {
+                let addr = if op.mode == Mode::IndirectY {
+                    self.indirect_indexed().addr
+                }
+                else {
+                    self.absolute_y().addr
+                };
+                let (y, value) = (self.y, self.a & self.x);
+                self.store_high(addr, y, value);
+            }
+            Instruction::Alr => self.alr(),
+            Instruction::Anc => self.anc(),
+            Instruction::Arr => self.arr(),
+            Instruction::Axs => self.axs(),
+            Instruction::Dcp => operand_inst!(self, dcp, op.mode),
+            Instruction::Isc => operand_inst!(self, isc, op.mode),
+            Instruction::Kil => self.kil(),
+            Instruction::Las => operand_inst!(self, las, op.mode),
+            Instruction::Lax => operand_inst!(self, lax, op.mode),
+            Instruction::Rla => operand_inst!(self, rla, op.mode),
+            Instruction::Rra => operand_inst!(self, rra, op.mode),
+            Instruction::Sax => operand_inst!(self, sax, op.mode),
+            Instruction::Shx => {
+                let addr = self.absolute_y().addr;
+                let (y, x) = (self.y, self.x);
+                self.store_high(addr, y, x);
+            }
+            Instruction::Shy => {
+                let addr = self.absolute_x().addr;
+                let (x, y) = (self.x, self.y);
+                self.store_high(addr, x, y);
+            }
+            Instruction::Slo => operand_inst!(self, slo, op.mode),
+            Instruction::Sre => operand_inst!(self, sre, op.mode),
+            Instruction::Tas => {
+                let addr = self.absolute_y().addr;
+                self.sp = self.a & self.x;
+                let (y, sp) = (self.y, self.sp);
+                self.store_high(addr, y, sp);
             }
+            Instruction::Xaa => self.xaa(),
+        }
+
+        // counted as they went in cycle-accurate mode
+        if !self.cycle_accurate {
+            self.cycles += op.cycles as usize;
+            if self.check_xpage {
+                self.cycles += op.xpage_cycles as usize;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 64K of RAM
+    struct Ram(Vec<u8>);
+
+    impl Access for Ram {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.0[addr as usize]
+        }
+
+        fn write(&mut self, addr: u16, value: u8) {
+            self.0[addr as usize] = value;
+        }
+    }
+
+    // a CPU about to run `program` at $0200
+    fn cpu_with(variant: Variant, program: &[u8]) -> Cpu<Ram> {
+        let mut ram = vec![0; 0x10000];
+        ram[0x0200..0x0200 + program.len()].copy_from_slice(program);
+        let mut cpu = Cpu::new(Ram(ram));
+        cpu.set_variant(variant);
+        cpu.set_registers(Registers { a: 0, x: 0, y: 0, sp: 0xfd, p: 0x24, pc: 0x0200 });
+        cpu
+    }
+
+    #[test]
+    fn unofficial_opcodes() {
+        let mut cpu = cpu_with(Variant::Ricoh2A03, &[
+            0xa7, 0x10,       // lax $10
+            0x87, 0x11,       // sax $11
+            0xc7, 0x12,       // dcp $12
+            0xe7, 0x13,       // isc $13
+            0xcb, 0x01,       // axs #$01
+        ]);
+        cpu.mem_mut().0[0x10] = 0x8f;
+        cpu.mem_mut().0[0x12] = 0x90;
+        cpu.mem_mut().0[0x13] = 0x0f;
+        assert_eq!(cpu.step(), 3);
+        assert_eq!((cpu.a, cpu.x, cpu.p & NEGATIVE), (0x8f, 0x8f, NEGATIVE));
+        cpu.set_registers(Registers { x: 0xf0, ..cpu.registers() });
+        cpu.step();
+        assert_eq!(cpu.mem().0[0x11], 0x80);
+        // $8f compared to $8f
+        assert_eq!(cpu.step(), 5);
+        assert_eq!(cpu.mem().0[0x12], 0x8f);
+        assert_eq!(cpu.p & (ZERO | CARRY), ZERO | CARRY);
+        // $8f - $10 with carry set
+        cpu.step();
+        assert_eq!((cpu.mem().0[0x13], cpu.a), (0x10, 0x7f));
+        // ($7f & $f0) - 1
+        cpu.step();
+        assert_eq!(cpu.x, 0x6f);
+    }
+
+    // steps through a program, checking the cycles each instruction takes
+    fn run(cpu: &mut Cpu<Ram>, cycles: &[usize]) {
+        for (i, &expected) in cycles.iter().enumerate() {
+            let pc = cpu.pc;
+            assert_eq!(cpu.step(), expected, "instruction {} at {:04x}", i, pc);
+        }
+    }
+
+    #[test]
+    fn cmos_branch_and_stack() {
+        for &accurate in &[false, true] {
+            let mut cpu = cpu_with(Variant::Cmos65C02, &[
+                0x80, 0x02,       // bra $0204
+                0xea, 0xea,
+                0xa2, 0x12,       // ldx #$12
+                0xa0, 0x84,       // ldy #$84
+                0xda,             // phx
+                0x5a,             // phy
+                0xa2, 0x00,       // ldx #$00
+                0xa0, 0x00,       // ldy #$00
+                0xfa,             // plx
+                0x7a,             // ply
+                0x80, 0x80,       // bra $0192
+            ]);
+            cpu.set_cycle_accurate(accurate);
+            run(&mut cpu, &[3, 2, 2, 3, 3, 2, 2]);
+            assert_eq!(cpu.sp, 0xfb);
+            assert_eq!(&cpu.mem().0[0x01fc..0x01fe], &[0x84, 0x12]);
+            assert_eq!(cpu.p & ZERO, ZERO);
+            run(&mut cpu, &[4]);
+            assert_eq!((cpu.x, cpu.p & (NEGATIVE | ZERO)), (0x84, NEGATIVE));
+            run(&mut cpu, &[4]);
+            assert_eq!((cpu.y, cpu.p & (NEGATIVE | ZERO)), (0x12, 0));
+            assert_eq!(cpu.sp, 0xfd);
+            // crossing a page takes a cycle more
+            run(&mut cpu, &[4]);
+            assert_eq!(cpu.pc, 0x0192);
+        }
+    }
+
+    #[test]
+    fn cmos_memory_instructions() {
+        for &accurate in &[false, true] {
+            let mut cpu = cpu_with(Variant::Cmos65C02, &[
+                0x64, 0x10,       // stz $10
+                0x9c, 0x01, 0x03, // stz $0301
+                0x14, 0x11,       // trb $11
+                0x04, 0x12,       // tsb $12
+                0xb2, 0x20,       // lda ($20)
+                0x1a,             // inc a
+                0x92, 0x20,       // sta ($20)
+                0x3a,             // dec a
+                0x89, 0x00,       // bit #$00
+            ]);
+            cpu.set_cycle_accurate(accurate);
+            {
+                let ram = &mut cpu.mem_mut().0;
+                ram[0x10] = 0xff;
+                ram[0x11] = 0x34;
+                ram[0x12] = 0x30;
+                ram[0x20] = 0x00;
+                ram[0x21] = 0x03;
+                ram[0x0300] = 0xff;
+                ram[0x0301] = 0xaa;
+            }
+            cpu.set_registers(Registers { a: 0x0c, p: UNKNOWN | INTERRUPT | NEGATIVE | OVERFLOW | ZERO,
+                                          ..cpu.registers() });
+            run(&mut cpu, &[3, 4]);
+            assert_eq!((cpu.mem().0[0x10], cpu.mem().0[0x0301]), (0x00, 0x00));
+            // A and $34 have bit 2 in common, only Z is changed
+            run(&mut cpu, &[5]);
+            assert_eq!(cpu.mem().0[0x11], 0x30);
+            assert_eq!(cpu.p & (NEGATIVE | OVERFLOW | ZERO), NEGATIVE | OVERFLOW);
+            run(&mut cpu, &[5]);
+            assert_eq!(cpu.mem().0[0x12], 0x3c);
+            assert_eq!(cpu.p & (NEGATIVE | OVERFLOW | ZERO), NEGATIVE | OVERFLOW | ZERO);
+            assert_eq!(cpu.a, 0x0c);
+
+            run(&mut cpu, &[5]);
+            assert_eq!((cpu.a, cpu.p & (NEGATIVE | ZERO)), (0xff, NEGATIVE));
+            run(&mut cpu, &[2]);
+            assert_eq!((cpu.a, cpu.p & (NEGATIVE | ZERO)), (0x00, ZERO));
+            run(&mut cpu, &[5]);
+            assert_eq!(cpu.mem().0[0x0300], 0x00);
+            run(&mut cpu, &[2]);
+            assert_eq!((cpu.a, cpu.p & (NEGATIVE | ZERO)), (0xff, NEGATIVE));
+            // N and V are not taken from the operand
+            run(&mut cpu, &[2]);
+            assert_eq!(cpu.p & (NEGATIVE | OVERFLOW | ZERO), NEGATIVE | OVERFLOW | ZERO);
+            assert_eq!(cpu.pc, 0x0211);
+        }
+    }
+
+    #[test]
+    fn cmos_jmp_indirect_and_interrupts() {
+        for &(variant, target, cycles, decimal) in &[(Variant::Nmos6502, 0x6c00, 5, DECIMAL),
+                                                     (Variant::Cmos65C02, 0x0400, 6, 0)] {
+            for &accurate in &[false, true] {
+                // jmp ($02ff)
+                let mut cpu = cpu_with(variant, &[0x6c, 0xff, 0x02]);
+                cpu.set_cycle_accurate(accurate);
+                {
+                    let ram = &mut cpu.mem_mut().0;
+                    ram[0x02ff] = 0x00;
+                    ram[0x0300] = 0x04;
+                    // sed, brk
+                    ram[0x0400..0x0403].copy_from_slice(&[0xf8, 0x00, 0x00]);
+                    ram[0x6c00..0x6c03].copy_from_slice(&[0xf8, 0x00, 0x00]);
+                    // cli, sed
+                    ram[0x0500..0x0502].copy_from_slice(&[0x58, 0xf8]);
+                    ram[0xfffe] = 0x00;
+                    ram[0xffff] = 0x05;
+                }
+                run(&mut cpu, &[cycles]);
+                assert_eq!(cpu.pc, target);
+
+                run(&mut cpu, &[2, 7]);
+                assert_eq!(cpu.pc, 0x0500);
+                assert_eq!(cpu.p & (DECIMAL | INTERRUPT), decimal | INTERRUPT);
+                assert_eq!(cpu.mem().0[0x01fb], UNKNOWN | BREAK | DECIMAL | INTERRUPT);
+
+                run(&mut cpu, &[2, 2]);
+                cpu.set_irq(true);
+                run(&mut cpu, &[7]);
+                assert_eq!(cpu.pc, 0x0500);
+                assert_eq!(cpu.p & (DECIMAL | INTERRUPT), decimal | INTERRUPT);
+                assert_eq!(cpu.mem().0[0x01f8], UNKNOWN | DECIMAL);
+            }
+        }
+    }
+
+    #[test]
+    fn cmos_cycles() {
+        let program = [
+            0x1e, 0x00, 0x03, // asl $0300,x
+            0x1e, 0xff, 0x03, // asl $03ff,x
+            0x5c, 0x00, 0x00, // nop, 3 bytes on the 65C02
+        ];
+        for &(variant, cycles, next) in &[(Variant::Nmos6502, [7, 7, 4], 0x0209),
+                                          (Variant::Cmos65C02, [6, 7, 8], 0x0209)] {
+            for &accurate in &[false, true] {
+                let mut cpu = cpu_with(variant, &program);
+                cpu.set_cycle_accurate(accurate);
+                cpu.set_registers(Registers { x: 0x01, ..cpu.registers() });
+                cpu.mem_mut().0[0x0301] = 0x01;
+                cpu.mem_mut().0[0x0400] = 0x40;
+                run(&mut cpu, &cycles);
+                assert_eq!(cpu.mem().0[0x0301], 0x02);
+                assert_eq!(cpu.mem().0[0x0400], 0x80);
+                assert_eq!(cpu.pc, next);
+            }
+        }
+    }
+
+    // Bruce Clark's decimal mode, appendix A: A, then N V Z C, after
+    // ADC and SBC of `b` to `a` with carry `c`
+    // http://www.6502.org/tutorials/decimal_mode.html#A
+    fn clark_adc(variant: Variant, a: u8, b: u8, c: u8) -> (u8, u8) {
+        let (a, b, c) = (a as i32, b as i32, c as i32);
+        // sequence 1, A and C
+        let mut low = (a & 0x0f) + (b & 0x0f) + c;
+        if low >= 0x0a {
+            low = ((low + 0x06) & 0x0f) + 0x10;
+        }
+        let mut sum = (a & 0xf0) + (b & 0xf0) + low;
+        if sum >= 0xa0 {
+            sum += 0x60;
+        }
+        let result = sum as u8;
+        // sequence 2, N and V from the sum of signed high digits
+        let signed = (a & 0xf0) as u8 as i8 as i32 + (b & 0xf0) as u8 as i8 as i32 + low;
+        let (n, z) = match variant {
+            Variant::Cmos65C02 => (result & 0x80 != 0, result == 0),
+            _ => (signed & 0x80 != 0, (a + b + c) as u8 == 0),
+        };
+        (result, flags(n, !(-128..=127).contains(&signed), z, sum >= 0x100))
+    }
+
+    fn clark_sbc(variant: Variant, a: u8, b: u8, c: u8) -> (u8, u8) {
+        let (a, b, c) = (a as i32, b as i32, c as i32);
+        let mut low = (a & 0x0f) - (b & 0x0f) + c - 1;
+        let result = match variant {
+            // sequence 4
+            Variant::Cmos65C02 => {
+                let mut diff = a - b + c - 1;
+                if diff < 0 {
+                    diff -= 0x60;
+                }
+                if low < 0 {
+                    diff -= 0x06;
+                }
+                diff as u8
+            }
+            // sequence 3
+            _ => {
+                if low < 0 {
+                    low = ((low - 0x06) & 0x0f) - 0x10;
+                }
+                let mut diff = (a & 0xf0) - (b & 0xf0) + low;
+                if diff < 0 {
+                    diff -= 0x60;
+                }
+                diff as u8
+            }
+        };
+        // C and V as in binary mode, and N and Z too on the NMOS
+        let binary = a - b + c - 1;
+        let nz = if variant == Variant::Cmos65C02 { result } else { binary as u8 };
+        let v = (a ^ binary) & (a ^ b) & 0x80 != 0;
+        (result, flags(nz & 0x80 != 0, v, nz == 0, binary >= 0))
+    }
+
+    fn flags(n: bool, v: bool, z: bool, c: bool) -> u8 {
+        (if n { NEGATIVE } else { 0 }) | (if v { OVERFLOW } else { 0 }) |
+        (if z { ZERO } else { 0 }) | (if c { CARRY } else { 0 })
+    }
+
+    #[test]
+    fn decimal_mode() {
+        for &variant in &[Variant::Nmos6502, Variant::Cmos65C02] {
+            for &(opcode, reference) in &[(0x69, clark_adc as fn(Variant, u8, u8, u8) -> (u8, u8)),
+                                          (0xe9, clark_sbc)] {
+                let mut cpu = cpu_with(variant, &[opcode, 0x00]);
+                for a in 0..=0xff {
+                    for b in 0..=0xff {
+                        for c in 0..2 {
+                            cpu.mem_mut().0[0x0201] = b;
+                            cpu.set_registers(Registers { a, x: 0, y: 0, sp: 0xfd, p: UNKNOWN | DECIMAL | c, pc: 0x0200 });
+                            cpu.step();
+                            let p = cpu.p & (NEGATIVE | OVERFLOW | ZERO | CARRY);
+                            assert!((cpu.a, p) == reference(variant, a, b, c),
+                                    "{:?} {:02x} with a={:02x} operand={:02x} c={}: a={:02x} p={:02x}, expected {:02x?}",
+                                    variant, opcode, a, b, c, cpu.a, p, reference(variant, a, b, c));
+                        }
+                    }
+                }
+            }
+        }
+    }
 
-            0x68 => self.a = self.pop(), // pla
-            0x28 => self.p = self.pop(), // plp
-
-            0x2a => inst!(self, rol, accumulator),
-            0x26 => inst!(self, rol, zeropage),
-            0x36 => inst!(self, rol, zeropage_x),
-            0x2e => inst!(self, rol, absolute),
-            0x3e => inst!(self, rol, absolute_x),
-
-            0x6a => inst!(self, ror, accumulator),
-            0x66 => inst!(self, ror, zeropage),
-            0x76 => inst!(self, ror, zeropage_x),
-            0x6e => inst!(self, ror, absolute),
-            0x7e => inst!(self, ror, absolute_x),
-
-            0x60 => self.rts(),
-
-            0xe9 => inst!(self, sbc, immediate),
-            0xe5 => inst!(self, sbc, zeropage),
-            0xf5 => inst!(self, sbc, zeropage_x),
-            0xed => inst!(self, sbc, absolute),
-            0xfd => inst!(self, sbc, absolute_x),
-            0xf9 => inst!(self, sbc, absolute_y),
-            0xe1 => inst!(self, sbc, indexed_indirect),
-            0xf1 => inst!(self, sbc, indirect_indexed),
-
-            0x38 => self.set_flag(CARRY),     // sec
-            0xf8 => self.set_flag(DECIMAL),   // sed
-            0x78 => self.set_flag(INTERRUPT), // sei
-
-            0x85 => inst!(self, sta, zeropage),
-            0x95 => inst!(self, sta, zeropage_x),
-            0x8d => inst!(self, sta, absolute),
-            0x9d => inst!(self, sta, absolute_x),
-            0x99 => inst!(self, sta, absolute_y),
-            0x81 => inst!(self, sta, indexed_indirect),
-            0x91 => inst!(self, sta, indirect_indexed),
-
-            0x86 => inst!(self, stx, zeropage),
-            0x96 => inst!(self, stx, zeropage_y),
-            0x8e => inst!(self, stx, absolute),
-
-            0x84 => inst!(self, sty, zeropage),
-            0x94 => inst!(self, sty, zeropage_x),
-            0x8c => inst!(self, sty, absolute),
-
-            0xaa => self.tax(),
-            0xa8 => self.tay(),
-            0xba => self.tsx(),
-            0x8a => self.txa(),
-            0x9a => self.txs(),
-            0x98 => self.tya(),
-
-            _ => panic!("unknown opcode {} pc={:x}", opcode, self.pc)
-        }
-
-        self.cycles += CYCLES[opcode as usize];
-        if self.check_xpage {
-            self.cycles += XPAGE_CYCLES[opcode as usize];
+    #[test]
+    fn kil_jams() {
+        let mut cpu = cpu_with(Variant::Ricoh2A03, &[0x02]);
+        for _ in 0..3 {
+            assert!(cpu.step() > 0);
+            assert_eq!(cpu.pc, 0x0200);
         }
     }
 }
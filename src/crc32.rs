@@ -0,0 +1,18 @@
+// CRC-32 as used by zip and by ROM databases to identify games.
+
+const POLY: u32 = 0xedb8_8320;
+
+pub fn update(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ POLY } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    update(0, bytes)
+}
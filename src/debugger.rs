@@ -0,0 +1,644 @@
+// A CPU debugger: breakpoints, watchpoints and stepping, driven by
+// commands typed in a terminal.
+//
+// Breakpoints stop before the instruction at an address executes,
+// optionally only in a PRG bank (16 KB, as in FCEUX) or when a
+// condition on the registers holds. Watchpoints stop after an
+// instruction reads or writes a range of addresses; the bus checks
+// them, see `Bus::watchpoints`.
+
+use std::fmt;
+use std::io::{BufRead, Write};
+use cpu::Registers;
+use disasm::{self, Decoded};
+use error::Error;
+use nes::Nes;
+use symbols::Symbols;
+
+const JSR: u8 = 0x20;
+const RTI: u8 = 0x40;
+const RTS: u8 = 0x60;
+
+// step over and step out give up after this many instructions
+const STEP_LIMIT: usize = 1_000_000;
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+const HELP: &str = "\
+c, continue                 run until a breakpoint or a watchpoint
+s, step [N]                 execute N instructions
+n, next                     step over a JSR
+f, finish                   run until the current subroutine returns
+b, break ADDR [if COND]     stop at ADDR, or BANK:ADDR
+b, break if COND            stop anywhere COND holds, e.g. a == 10 && x >= 2
+w, watch [r|w|rw] ADDR[-END]  stop on accesses to a range
+l, list                     list breakpoints and watchpoints
+d, delete N                 delete breakpoint N
+u, unwatch N                delete watchpoint N
+r, regs                     show the registers
+set REG VALUE               change a register
+m, mem ADDR [LEN]           dump memory
+x, disasm [ADDR] [N]        disassemble N instructions, from PC by default
+sym FILE                    load symbols, .dbg, .nl or .mlb
+q, quit                     quit
+Numbers are hex, with or without $ or 0x. Symbols can be used for addresses.
+An empty line repeats the last command.
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, addr: u16, write: bool) -> bool {
+        addr >= self.start && addr <= self.end && if write { self.write } else { self.read }
+    }
+}
+
+// the access that hit a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    A,
+    X,
+    Y,
+    Sp,
+    P,
+    Pc,
+}
+
+impl Register {
+    fn from_name(name: &str) -> Option<Register> {
+        match name.to_lowercase().as_str() {
+            "a" => Some(Register::A),
+            "x" => Some(Register::X),
+            "y" => Some(Register::Y),
+            "sp" | "s" => Some(Register::Sp),
+            "p" => Some(Register::P),
+            "pc" => Some(Register::Pc),
+            _ => None,
+        }
+    }
+
+    fn get(self, regs: &Registers) -> u16 {
+        match self {
+            Register::A => regs.a as u16,
+            Register::X => regs.x as u16,
+            Register::Y => regs.y as u16,
+            Register::Sp => regs.sp as u16,
+            Register::P => regs.p as u16,
+            Register::Pc => regs.pc,
+        }
+    }
+
+    fn set(self, regs: &mut Registers, value: u16) {
+        match self {
+            Register::A => regs.a = value as u8,
+            Register::X => regs.x = value as u8,
+            Register::Y => regs.y = value as u8,
+            Register::Sp => regs.sp = value as u8,
+            Register::P => regs.p = value as u8,
+            Register::Pc => regs.pc = value,
+        }
+    }
+}
+
+const OPERATORS: [&str; 6] = ["==", "!=", "<=", ">=", "<", ">"];
+
+// registers compared to values, all of which must hold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    text: String,
+    terms: Vec<(Register, &'static str, u16)>,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut terms = Vec::new();
+        for term in text.split("&&") {
+            let term = term.trim();
+            let op = OPERATORS.iter()
+                              .find(|op| term.contains(*op))
+                              .ok_or_else(|| Error::new(format!("no comparison in {}", term)))?;
+            let mut sides = term.splitn(2, op);
+            let reg = sides.next().unwrap().trim();
+            let reg = Register::from_name(reg)
+                              .ok_or_else(|| Error::new(format!("unknown register {}", reg)))?;
+            let value = parse_number(sides.next().unwrap())?;
+            terms.push((reg, *op, value));
+        }
+        Ok(Condition { text: text.trim().to_string(), terms })
+    }
+
+    pub fn holds(&self, regs: &Registers) -> bool {
+        self.terms.iter().all(|&(reg, op, value)| {
+            let reg = reg.get(regs);
+            match op {
+                "==" => reg == value,
+                "!=" => reg != value,
+                "<=" => reg <= value,
+                ">=" => reg >= value,
+                "<" => reg < value,
+                _ => reg > value,
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    // None to stop wherever the condition holds
+    pub addr: Option<u16>,
+    // 16 KB PRG ROM bank the address must be in
+    pub bank: Option<usize>,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    fn hit(&self, nes: &Nes) -> bool {
+        let regs = nes.cpu().registers();
+        if let Some(addr) = self.addr {
+            if regs.pc != addr {
+                return false;
+            }
+        }
+        if let Some(bank) = self.bank {
+            match nes.bus().cartridge.prg_offset(regs.pc) {
+                Some(offset) if offset / PRG_BANK_SIZE == bank => {}
+                _ => return false,
+            }
+        }
+        self.condition.as_ref().is_none_or(|c| c.holds(&regs))
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.bank, self.addr) {
+            (Some(bank), Some(addr)) => write!(f, "{:02x}:{:04x}", bank, addr)?,
+            (None, Some(addr)) => write!(f, "{:04x}", addr)?,
+            _ => write!(f, "anywhere")?,
+        }
+        if let Some(ref cond) = self.condition {
+            write!(f, " if {}", cond.text)?;
+        }
+        Ok(())
+    }
+}
+
+// why the debugger stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(usize),
+    Watchpoint(WatchHit),
+    // a step was done, or gave up
+    Step,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stop::Breakpoint(n) => write!(f, "breakpoint {}", n),
+            Stop::Watchpoint(hit) => write!(f, "watchpoint: {} {:04x} = {:02x}",
+                                            if hit.write { "write" } else { "read" },
+                                            hit.addr, hit.value),
+            Stop::Step => Ok(()),
+        }
+    }
+}
+
+// what the REPL does after a command
+pub enum Reply {
+    Print(String),
+    Continue,
+    Quit,
+}
+
+fn parse_number(text: &str) -> Result<u16, Error> {
+    let text = text.trim();
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| Error::new(format!("bad number {}", text)))
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub symbols: Symbols,
+    // don't stop again on the breakpoint execution stopped at
+    resuming: bool,
+    // a frame was started and not finished
+    in_frame: bool,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // a number or a symbol
+    fn address(&self, text: &str) -> Result<u16, Error> {
+        parse_number(text).or_else(|e| self.symbols.find(text).map(|(_, addr)| addr).ok_or(e))
+    }
+
+    // ADDR, BANK:ADDR or a symbol, which may be in a bank
+    fn location(&self, text: &str) -> Result<(Option<usize>, u16), Error> {
+        match text.find(':') {
+            Some(i) => Ok((Some(parse_number(&text[..i])? as usize), self.address(&text[i + 1..])?)),
+            None => parse_number(text).map(|addr| (None, addr))
+                                      .or_else(|e| self.symbols.find(text).ok_or(e)),
+        }
+    }
+
+    fn check_breakpoints(&mut self, nes: &Nes) -> Option<Stop> {
+        if self.resuming {
+            self.resuming = false;
+            return None;
+        }
+        self.breakpoints.iter().position(|b| b.hit(nes)).map(Stop::Breakpoint)
+    }
+
+    // Execute one instruction. Returns true if a frame was completed.
+    fn step_one(&mut self, nes: &mut Nes) -> bool {
+        if !self.in_frame {
+            nes.bus_mut().apu.clear_samples();
+            self.in_frame = true;
+        }
+        let frame = nes.step();
+        if frame {
+            nes.bus_mut().apu.end_frame();
+            self.in_frame = false;
+        }
+        frame
+    }
+
+    // Run until `done` says so after an instruction, or a breakpoint
+    // or a watchpoint stops execution.
+    fn run_until<F>(&mut self, nes: &mut Nes, mut done: F) -> Stop
+        where F: FnMut(&mut Debugger, &mut Nes, bool) -> bool
+    {
+        loop {
+            if let Some(stop) = self.check_breakpoints(nes) {
+                self.resuming = true;
+                return stop;
+            }
+            let frame = self.step_one(nes);
+            if let Some(hit) = nes.bus_mut().take_watch_hit() {
+                self.resuming = true;
+                return Stop::Watchpoint(hit);
+            }
+            if done(self, nes, frame) {
+                return Stop::Step;
+            }
+        }
+    }
+
+    // Run the rest of the frame, unless a breakpoint or a watchpoint
+    // stops it first. Use in place of `Nes::run_frame`.
+    pub fn run_frame(&mut self, nes: &mut Nes) -> Option<Stop> {
+        match self.run_until(nes, |_, _, frame| frame) {
+            Stop::Step => None,
+            stop => Some(stop),
+        }
+    }
+
+    // Run one instruction. Continuing afterwards runs the next one even
+    // if a breakpoint is there.
+    pub fn step(&mut self, nes: &mut Nes) -> Stop {
+        self.step_one(nes);
+        self.resuming = true;
+        nes.bus_mut().take_watch_hit().map_or(Stop::Step, Stop::Watchpoint)
+    }
+
+    // Step, running a subroutine called by a JSR as one instruction.
+    pub fn step_over(&mut self, nes: &mut Nes) -> Stop {
+        let regs = nes.cpu().registers();
+        if nes.peek(regs.pc) != JSR {
+            return self.step(nes);
+        }
+        let ret = regs.pc.wrapping_add(3);
+        let mut count = 0;
+        self.resuming = true;
+        let stop = self.run_until(nes, |_, nes, _| {
+            count += 1;
+            let now = nes.cpu().registers();
+            (now.pc == ret && now.sp == regs.sp) || count >= STEP_LIMIT
+        });
+        self.resuming = true;
+        stop
+    }
+
+    // Run until the current subroutine returns: an RTS or an RTI
+    // executed with the stack as high as it is now.
+    pub fn step_out(&mut self, nes: &mut Nes) -> Stop {
+        let sp = nes.cpu().registers().sp;
+        let mut count = 0;
+        let mut returning = false;
+        self.resuming = true;
+        let stop = self.run_until(nes, |_, nes, _| {
+            count += 1;
+            if returning {
+                return true;
+            }
+            let now = nes.cpu().registers();
+            let op = nes.peek(now.pc);
+            // stop after the return instruction
+            returning = (op == RTS || op == RTI) && now.sp >= sp;
+            count >= STEP_LIMIT
+        });
+        self.resuming = true;
+        stop
+    }
+
+    // registers, cycles and the next instruction
+    pub fn status(&self, nes: &Nes) -> String {
+        let r = nes.cpu().registers();
+        let flags: String = "NV-BDIZC".chars().enumerate()
+                                      .map(|(i, c)| if r.p & (0x80 >> i) != 0 { c } else { '.' })
+                                      .collect();
+        let line = format!("{}  A:{:02x} X:{:02x} Y:{:02x} SP:{:02x} P:{} CYC:{}",
+                           self.instruction(nes, r.pc), r.a, r.x, r.y, r.sp, flags, nes.cpu().cycles());
+        self.annotate(nes, r.pc, &line)
+    }
+
+    // address, bytes and disassembly of an instruction
+    fn instruction(&self, nes: &Nes, addr: u16) -> String {
+        let decoded = self.decode(nes, addr);
+        let bytes: Vec<String> = decoded.bytes[..decoded.size()].iter()
+                                                              .map(|b| format!("{:02x}", b))
+                                                              .collect();
+        let text = decoded.to_ca65(&self.symbols.labels(&nes.bus().cartridge));
+        format!("{:04x}  {:<8}  {:<20}", addr, bytes.join(" "), text)
+    }
+
+    // a line about the instruction at `addr`, after its label and with
+    // its source line if they are known
+    fn annotate(&self, nes: &Nes, addr: u16, line: &str) -> String {
+        let offset = nes.bus().cartridge.prg_offset(addr);
+        let mut out = String::new();
+        if let Some(label) = self.symbols.label(addr, offset) {
+            out.push_str(&format!("{}:\n", label));
+        }
+        out.push_str(line.trim_end());
+        if let Some(source) = offset.and_then(|offset| self.symbols.source_line(offset)) {
+            out.push_str(&format!(" ; {}", source));
+        }
+        out.push('\n');
+        out
+    }
+
+    fn decode(&self, nes: &Nes, addr: u16) -> Decoded {
+        disasm::decode_from(addr, |a| nes.peek(a))
+    }
+
+    fn after_stop(&self, nes: &Nes, stop: Stop) -> String {
+        match stop {
+            Stop::Step => self.status(nes),
+            stop => format!("{}\n{}", stop, self.status(nes)),
+        }
+    }
+
+    fn list(&self, nes: &Nes) -> String {
+        let mut out = String::new();
+        for (i, b) in self.breakpoints.iter().enumerate() {
+            out.push_str(&format!("breakpoint {}: {}\n", i, b));
+        }
+        for (i, w) in nes.bus().watchpoints.iter().enumerate() {
+            let kind = match (w.read, w.write) {
+                (true, true) => "rw",
+                (true, false) => "r",
+                _ => "w",
+            };
+            out.push_str(&format!("watchpoint {}: {} {:04x}-{:04x}\n", i, kind, w.start, w.end));
+        }
+        out
+    }
+
+    fn dump(&self, nes: &Nes, addr: u16, len: u16) -> String {
+        let mut out = String::new();
+        let mut line = addr;
+        let end = addr as u32 + len as u32;
+        while (line as u32) < end {
+            out.push_str(&format!("{:04x} ", line));
+            for a in line as u32..(line as u32 + 16).min(end) {
+                out.push_str(&format!(" {:02x}", nes.peek(a as u16)));
+            }
+            out.push('\n');
+            line = match line.checked_add(16) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        out
+    }
+
+    // Run a command of the REPL, see HELP.
+    pub fn command(&mut self, nes: &mut Nes, line: &str) -> Result<Reply, Error> {
+        let line = if line.trim().is_empty() { self.last_command.clone() } else { line.trim().to_string() };
+        self.last_command = line.clone();
+        let mut words = line.split_whitespace();
+        let cmd = match words.next() {
+            Some(cmd) => cmd,
+            None => return Ok(Reply::Print(String::new())),
+        };
+        let args: Vec<&str> = words.collect();
+        let arg = |i: usize| args.get(i).ok_or_else(|| Error::new(format!("{} needs more arguments", cmd)));
+
+        let output = match cmd {
+            "c" | "continue" => return Ok(Reply::Continue),
+            "q" | "quit" => return Ok(Reply::Quit),
+            "h" | "help" => HELP.to_string(),
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(n) => parse_number(n)?.max(1),
+                    None => 1,
+                };
+                let mut stop = Stop::Step;
+                for _ in 0..count {
+                    stop = self.step(nes);
+                    if stop != Stop::Step {
+                        break;
+                    }
+                }
+                self.after_stop(nes, stop)
+            }
+            "n" | "next" => {
+                let stop = self.step_over(nes);
+                self.after_stop(nes, stop)
+            }
+            "f" | "finish" => {
+                let stop = self.step_out(nes);
+                self.after_stop(nes, stop)
+            }
+            "b" | "break" => {
+                // the location is optional before a condition
+                let (location, condition) = match line.find(" if ") {
+                    Some(i) => (line[..i].split_whitespace().nth(1),
+                                Some(Condition::parse(&line[i + 4..])?)),
+                    None => (Some(*arg(0)?), None),
+                };
+                let (bank, addr) = match location {
+                    Some(location) => {
+                        let (bank, addr) = self.location(location)?;
+                        (bank, Some(addr))
+                    }
+                    None => (None, None),
+                };
+                let b = Breakpoint { addr, bank, condition };
+                let out = format!("breakpoint {}: {}\n", self.breakpoints.len(), b);
+                self.breakpoints.push(b);
+                out
+            }
+            "w" | "watch" => {
+                let (kind, range) = match args.len() {
+                    1 => ("rw", *arg(0)?),
+                    _ => (*arg(0)?, *arg(1)?),
+                };
+                let (start, end) = match range.find('-') {
+                    Some(i) => (self.address(&range[..i])?, self.address(&range[i + 1..])?),
+                    None => {
+                        let addr = self.address(range)?;
+                        (addr, addr)
+                    }
+                };
+                let (read, write) = match kind {
+                    "r" => (true, false),
+                    "w" => (false, true),
+                    "rw" => (true, true),
+                    _ => return Err(Error::new(format!("watch kind is r, w or rw, not {}", kind))),
+                };
+                let watchpoints = &mut nes.bus_mut().watchpoints;
+                watchpoints.push(Watchpoint { start, end, read, write });
+                format!("watchpoint {}: {} {:04x}-{:04x}\n", watchpoints.len() - 1, kind, start, end)
+            }
+            "d" | "delete" => {
+                let n = parse_number(arg(0)?)? as usize;
+                if n >= self.breakpoints.len() {
+                    return Err(Error::new(format!("no breakpoint {}", n)));
+                }
+                self.breakpoints.remove(n);
+                String::new()
+            }
+            "u" | "unwatch" => {
+                let n = parse_number(arg(0)?)? as usize;
+                let watchpoints = &mut nes.bus_mut().watchpoints;
+                if n >= watchpoints.len() {
+                    return Err(Error::new(format!("no watchpoint {}", n)));
+                }
+                watchpoints.remove(n);
+                String::new()
+            }
+            "l" | "list" => self.list(nes),
+            "r" | "regs" => self.status(nes),
+            "set" => {
+                let reg = Register::from_name(arg(0)?)
+                                  .ok_or_else(|| Error::new(format!("unknown register {}", args[0])))?;
+                let mut regs = nes.cpu().registers();
+                reg.set(&mut regs, parse_number(arg(1)?)?);
+                nes.cpu_mut().set_registers(regs);
+                self.status(nes)
+            }
+            "m" | "mem" => {
+                let addr = self.address(arg(0)?)?;
+                let len = match args.get(1) {
+                    Some(len) => parse_number(len)?,
+                    None => 0x40,
+                };
+                self.dump(nes, addr, len)
+            }
+            "x" | "disasm" => {
+                let mut addr = match args.first() {
+                    Some(addr) => self.address(addr)?,
+                    None => nes.cpu().registers().pc,
+                };
+                let count = match args.get(1) {
+                    Some(count) => parse_number(count)?,
+                    None => 16,
+                };
+                let mut out = String::new();
+                for _ in 0..count {
+                    out.push_str(&self.annotate(nes, addr, &self.instruction(nes, addr)));
+                    addr = self.decode(nes, addr).next();
+                }
+                out
+            }
+            "sym" => {
+                let path = line[cmd.len()..].trim();
+                self.symbols.load(path)?;
+                format!("loaded {}\n", path)
+            }
+            _ => return Err(Error::new(format!("unknown command {}, try help", cmd))),
+        };
+        Ok(Reply::Print(output))
+    }
+
+    // Read commands until execution is resumed. Returns false to quit,
+    // also at the end of the input.
+    pub fn repl<R: BufRead, W: Write>(&mut self, nes: &mut Nes, input: &mut R, output: &mut W) -> bool {
+        let _ = write!(output, "{}", self.status(nes));
+        loop {
+            let _ = write!(output, "(rw) ");
+            let _ = output.flush();
+            let mut line = String::new();
+            match input.read_line(&mut line) {
+                Ok(0) | Err(_) => return false,
+                Ok(_) => {}
+            }
+            match self.command(nes, &line) {
+                Ok(Reply::Print(text)) => {
+                    let _ = write!(output, "{}", text);
+                }
+                Ok(Reply::Continue) => return true,
+                Ok(Reply::Quit) => return false,
+                Err(e) => {
+                    let _ = writeln!(output, "error: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nes::nes_running;
+
+    fn at(addr: u16) -> Breakpoint {
+        Breakpoint { addr: Some(addr), bank: None, condition: None }
+    }
+
+    #[test]
+    fn continue_after_stepping_onto_a_breakpoint() {
+        // nop, nop, jmp $c000
+        let mut nes = nes_running(&[0xea, 0xea, 0x4c, 0x00, 0xc0]);
+        let mut debugger = Debugger::new();
+        debugger.breakpoints = vec![at(0xc001), at(0xc002)];
+        assert_eq!(debugger.run_frame(&mut nes), Some(Stop::Breakpoint(0)));
+        assert_eq!(nes.cpu().registers().pc, 0xc001);
+        debugger.step(&mut nes);
+        assert_eq!(nes.cpu().registers().pc, 0xc002);
+        // not breakpoint 1 again without running anything
+        assert_eq!(debugger.run_frame(&mut nes), Some(Stop::Breakpoint(0)));
+        assert_eq!(nes.cpu().registers().pc, 0xc001);
+    }
+
+    #[test]
+    fn continue_stops_at_the_next_breakpoint() {
+        let mut nes = nes_running(&[0xea, 0xea, 0x4c, 0x00, 0xc0]);
+        let mut debugger = Debugger::new();
+        debugger.breakpoints = vec![at(0xc001), at(0xc002)];
+        assert_eq!(debugger.run_frame(&mut nes), Some(Stop::Breakpoint(0)));
+        assert_eq!(debugger.run_frame(&mut nes), Some(Stop::Breakpoint(1)));
+        assert_eq!(nes.cpu().registers().pc, 0xc002);
+    }
+}
@@ -0,0 +1,194 @@
+// Disassembly in ca65 syntax, e.g.
+//
+//     lda #$10
+//     sta a:$0010         absolute addressing of a zero page address
+//     bne loop            with labels for the addresses that have one
+//
+// Unofficial opcodes are written as bytes, plain ca65 does not know
+// them.
+
+use std::collections::HashMap;
+use std::fmt;
+use opcode::{Mode, Opcode, OPCODES};
+
+// names of addresses, e.g. read from a symbol file
+pub trait Labels {
+    fn label(&self, addr: u16) -> Option<&str>;
+}
+
+impl Labels for HashMap<u16, String> {
+    fn label(&self, addr: u16) -> Option<&str> {
+        self.get(&addr).map(|s| s.as_str())
+    }
+}
+
+// no labels
+impl Labels for () {
+    fn label(&self, _addr: u16) -> Option<&str> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decoded {
+    pub addr: u16,
+    pub opcode: &'static Opcode,
+    // the opcode and its operand, the rest is 0
+    pub bytes: [u8; 3],
+}
+
+// Decode the instruction at `addr`, whose bytes start `bytes`. None if
+// `bytes` is too short.
+pub fn decode(addr: u16, bytes: &[u8]) -> Option<Decoded> {
+    let opcode = &OPCODES[*bytes.first()? as usize];
+    if bytes.len() < opcode.size() {
+        return None;
+    }
+    let mut decoded = Decoded { addr, opcode, bytes: [0; 3] };
+    decoded.bytes[..opcode.size()].copy_from_slice(&bytes[..opcode.size()]);
+    Some(decoded)
+}
+
+// decode the instruction at `addr` in memory read by `read`
+pub fn decode_from<F: FnMut(u16) -> u8>(addr: u16, mut read: F) -> Decoded {
+    let opcode = &OPCODES[read(addr) as usize];
+    let mut decoded = Decoded { addr, opcode, bytes: [0; 3] };
+    for i in 0..opcode.size() {
+        decoded.bytes[i] = read(addr.wrapping_add(i as u16));
+    }
+    decoded
+}
+
+impl Decoded {
+    pub fn size(&self) -> usize {
+        self.opcode.size()
+    }
+
+    // the address of the next instruction
+    pub fn next(&self) -> u16 {
+        self.addr.wrapping_add(self.size() as u16)
+    }
+
+    // the operand as it is encoded
+    pub fn operand(&self) -> u16 {
+        match self.opcode.mode.operand_len() {
+            0 => 0,
+            1 => self.bytes[1] as u16,
+            _ => self.bytes[1] as u16 | (self.bytes[2] as u16) << 8,
+        }
+    }
+
+    // The address the operand refers to, before indexing, or where a
+    // branch goes. None if there is no address.
+    pub fn target(&self) -> Option<u16> {
+        match self.opcode.mode {
+            Mode::Implied | Mode::Accumulator | Mode::Immediate => None,
+            Mode::Relative => Some(self.next().wrapping_add(self.bytes[1] as i8 as u16)),
+            _ => Some(self.operand()),
+        }
+    }
+
+    // the instruction in ca65 syntax, using `labels` for addresses
+    pub fn to_ca65<L: Labels + ?Sized>(&self, labels: &L) -> String {
+        if self.opcode.official {
+            return self.instruction(labels);
+        }
+        let bytes: Vec<String> = self.bytes[..self.size()].iter()
+                                                      .map(|b| format!("${:02x}", b))
+                                                      .collect();
+        format!(".byte {} ; {}", bytes.join(", "), self.instruction(labels))
+    }
+
+    fn instruction<L: Labels + ?Sized>(&self, labels: &L) -> String {
+        let op = self.opcode;
+        let zeropage = op.mode.operand_len() == 1;
+        let addr = match self.target() {
+            Some(target) => match labels.label(target) {
+                // ca65 would pick zero page addressing for a zero page
+                // label, force absolute addressing
+                Some(label) if !zeropage && target < 0x100 => format!("a:{}", label),
+                Some(label) => label.to_string(),
+                None if zeropage && op.mode != Mode::Relative => format!("${:02x}", target),
+                None if target < 0x100 && op.mode != Mode::Relative => format!("a:${:04x}", target),
+                None => format!("${:04x}", target),
+            },
+            None => String::new(),
+        };
+        let operand = match op.mode {
+            Mode::Implied => String::new(),
+            Mode::Accumulator => "a".to_string(),
+            Mode::Immediate => format!("#${:02x}", self.bytes[1]),
+            Mode::ZeroPage | Mode::Absolute | Mode::Relative => addr,
+            Mode::ZeroPageX | Mode::AbsoluteX => format!("{},x", addr),
+            Mode::ZeroPageY | Mode::AbsoluteY => format!("{},y", addr),
+            Mode::Indirect => format!("({})", addr),
+            Mode::IndirectX => format!("({},x)", addr),
+            Mode::IndirectY => format!("({}),y", addr),
+            Mode::ZeroPageIndirect => format!("({})", addr),
+            Mode::AbsoluteIndirectX => format!("({},x)", addr),
+        };
+        if operand.is_empty() {
+            op.mnemonic().to_string()
+        }
+        else {
+            format!("{} {}", op.mnemonic(), operand)
+        }
+    }
+}
+
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_ca65(&()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ca65(addr: u16, bytes: &[u8]) -> String {
+        decode(addr, bytes).unwrap().to_string()
+    }
+
+    #[test]
+    fn instructions() {
+        assert_eq!(ca65(0x8000, &[0xa9, 0x10]), "lda #$10");
+        assert_eq!(ca65(0x8000, &[0x85, 0x10]), "sta $10");
+        assert_eq!(ca65(0x8000, &[0x8d, 0x10, 0x00]), "sta a:$0010");
+        assert_eq!(ca65(0x8000, &[0xbd, 0x00, 0x02]), "lda $0200,x");
+        assert_eq!(ca65(0x8000, &[0xb6, 0x10]), "ldx $10,y");
+        assert_eq!(ca65(0x8000, &[0x6c, 0xfc, 0xff]), "jmp ($fffc)");
+        assert_eq!(ca65(0x8000, &[0xa1, 0x20]), "lda ($20,x)");
+        assert_eq!(ca65(0x8000, &[0xb1, 0x20]), "lda ($20),y");
+        assert_eq!(ca65(0x8000, &[0x0a]), "asl a");
+        assert_eq!(ca65(0x8000, &[0x60]), "rts");
+        // to the next instruction plus 2, and back to itself
+        assert_eq!(ca65(0x8000, &[0xd0, 0x02]), "bne $8004");
+        assert_eq!(ca65(0x8000, &[0xf0, 0xfe]), "beq $8000");
+    }
+
+    #[test]
+    fn unofficial_as_bytes() {
+        assert_eq!(ca65(0x8000, &[0xa7, 0x10]), ".byte $a7, $10 ; lax $10");
+    }
+
+    #[test]
+    fn labels() {
+        let mut labels = HashMap::new();
+        labels.insert(0x8004, "loop".to_string());
+        labels.insert(0x0010, "temp".to_string());
+        let decoded = |bytes: &[u8]| decode(0x8000, bytes).unwrap();
+        assert_eq!(decoded(&[0xd0, 0x02]).to_ca65(&labels), "bne loop");
+        assert_eq!(decoded(&[0xa5, 0x10]).to_ca65(&labels), "lda temp");
+        assert_eq!(decoded(&[0xad, 0x10, 0x00]).to_ca65(&labels), "lda a:temp");
+    }
+
+    #[test]
+    fn too_short() {
+        assert!(decode(0x8000, &[0xad, 0x10]).is_none());
+        assert!(decode(0x8000, &[]).is_none());
+        let bytes = [0x4c, 0x34, 0x12];
+        let decoded = decode_from(0x8000, |addr| bytes[(addr - 0x8000) as usize]);
+        assert_eq!((decoded.size(), decoded.next(), decoded.target()), (3, 0x8003, Some(0x1234)));
+    }
+}
@@ -0,0 +1,479 @@
+// Serial EEPROMs found on Bandai boards, driven bit by bit over I2C
+// through a mapper register.
+// https://wiki.nesdev.com/w/index.php/Bandai_FCG_board#Serial_EEPROM
+
+use std::mem;
+use error::Error;
+use savestate::{State, StateReader, StateWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip {
+    // 128 bytes, no device address, bits sent LSB first
+    X24C01,
+    // 256 bytes, standard I2C, bits sent MSB first
+    X24C02,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Idle,
+    DeviceAddress,
+    WordAddress,
+    Read,
+    Write,
+    SendAck,
+    WaitAck,
+}
+
+impl Mode {
+    fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    fn from_u8(value: u8) -> Mode {
+        match value {
+            1 => Mode::DeviceAddress,
+            2 => Mode::WordAddress,
+            3 => Mode::Read,
+            4 => Mode::Write,
+            5 => Mode::SendAck,
+            6 => Mode::WaitAck,
+            _ => Mode::Idle,
+        }
+    }
+}
+
+pub struct Eeprom {
+    chip: Chip,
+    data: Vec<u8>,
+    mode: Mode,
+    // the mode after an acknowledge
+    next_mode: Mode,
+    device: u8,
+    address: u8,
+    // byte being shifted in or out
+    shift: u8,
+    bits: u8,
+    scl: bool,
+    sda: bool,
+    output: bool,
+}
+
+impl Eeprom {
+    pub fn new(chip: Chip) -> Self {
+        let size = match chip {
+            Chip::X24C01 => 128,
+            Chip::X24C02 => 256,
+        };
+        Eeprom {
+            chip,
+            data: vec![0xff; size],
+            mode: Mode::Idle,
+            next_mode: Mode::Idle,
+            device: 0,
+            address: 0,
+            shift: 0,
+            bits: 0,
+            scl: false,
+            sda: false,
+            output: true,
+        }
+    }
+
+    // stop any transfer, the data stays
+    pub fn power_on(&mut self) {
+        let data = mem::take(&mut self.data);
+        *self = Eeprom { data, ..Eeprom::new(self.chip) };
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    // the SDA line as driven by the EEPROM
+    pub fn read(&self) -> bool {
+        self.output
+    }
+
+    fn mask(&self) -> u8 {
+        (self.data.len() - 1) as u8
+    }
+
+    fn bit_position(&self) -> u8 {
+        match self.chip {
+            Chip::X24C01 => self.bits,
+            Chip::X24C02 => 7 - self.bits,
+        }
+    }
+
+    fn shift_in(&mut self, sda: bool) -> u8 {
+        if self.bits < 8 {
+            let bit = 1 << self.bit_position();
+            if sda {
+                self.shift |= bit;
+            }
+            else {
+                self.shift &= !bit;
+            }
+            self.bits += 1;
+        }
+        self.shift
+    }
+
+    fn shift_out(&mut self) {
+        if self.bits < 8 {
+            self.output = self.shift & (1 << self.bit_position()) != 0;
+            self.bits += 1;
+        }
+    }
+
+    fn start_byte(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.bits = 0;
+        self.output = true;
+    }
+
+    fn ack(&mut self, next_mode: Mode) {
+        self.next_mode = next_mode;
+        self.start_byte(Mode::SendAck);
+    }
+
+    fn start_read(&mut self) {
+        self.shift = self.data[self.address as usize];
+    }
+
+    // the lines as set by the mapper
+    pub fn write(&mut self, scl: bool, sda: bool) {
+        if self.scl && scl && self.sda && !sda {
+            // start: SDA falls while SCL is high
+            self.start_byte(match self.chip {
+                Chip::X24C01 => Mode::WordAddress,
+                Chip::X24C02 => Mode::DeviceAddress,
+            });
+        }
+        else if self.scl && scl && !self.sda && sda {
+            // stop: SDA rises while SCL is high
+            self.mode = Mode::Idle;
+            self.output = true;
+        }
+        else if !self.scl && scl {
+            self.rising_edge(sda);
+        }
+        else if self.scl && !scl {
+            self.falling_edge();
+        }
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    fn rising_edge(&mut self, sda: bool) {
+        match self.mode {
+            Mode::DeviceAddress => self.device = self.shift_in(sda),
+            // the X24C01 takes 7 address bits and a read bit
+            Mode::WordAddress if self.chip == Chip::X24C01 && self.bits == 7 => {
+                self.address = self.shift & self.mask();
+                self.bits = 8;
+                if sda {
+                    self.next_mode = Mode::Read;
+                    self.start_read();
+                }
+                else {
+                    self.next_mode = Mode::Write;
+                }
+            }
+            Mode::WordAddress => self.address = self.shift_in(sda) & self.mask(),
+            Mode::Read => self.shift_out(),
+            Mode::Write => {
+                self.shift_in(sda);
+            }
+            Mode::SendAck => self.output = false,
+            Mode::WaitAck => {
+                // the master acknowledges to go on reading
+                if !sda {
+                    self.next_mode = Mode::Read;
+                    self.start_read();
+                }
+                else {
+                    self.next_mode = Mode::Idle;
+                }
+            }
+            Mode::Idle => {}
+        }
+    }
+
+    fn falling_edge(&mut self) {
+        match self.mode {
+            Mode::DeviceAddress if self.bits == 8 => {
+                if self.device & 0xf0 == 0xa0 {
+                    if self.device & 0x01 != 0 {
+                        // current address read
+                        self.start_read();
+                        self.ack(Mode::Read);
+                    }
+                    else {
+                        self.ack(Mode::WordAddress);
+                    }
+                }
+                else {
+                    // another device is addressed
+                    self.start_byte(Mode::Idle);
+                }
+            }
+            Mode::WordAddress if self.bits == 8 => {
+                let next_mode = match self.chip {
+                    Chip::X24C01 => self.next_mode,
+                    Chip::X24C02 => Mode::Write,
+                };
+                self.ack(next_mode);
+            }
+            Mode::Read if self.bits == 8 => {
+                self.mode = Mode::WaitAck;
+                self.address = self.address.wrapping_add(1) & self.mask();
+            }
+            Mode::Write if self.bits == 8 => {
+                self.data[self.address as usize] = self.shift;
+                self.address = self.address.wrapping_add(1) & self.mask();
+                // the X24C01 takes one byte per write
+                let next_mode = match self.chip {
+                    Chip::X24C01 => Mode::Idle,
+                    Chip::X24C02 => Mode::Write,
+                };
+                self.ack(next_mode);
+            }
+            Mode::SendAck | Mode::WaitAck => {
+                let next_mode = self.next_mode;
+                self.start_byte(next_mode);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl State for Eeprom {
+    fn save(&self, w: &mut StateWriter) {
+        self.data.save(w);
+        self.mode.to_u8().save(w);
+        self.next_mode.to_u8().save(w);
+        self.device.save(w);
+        self.address.save(w);
+        self.shift.save(w);
+        self.bits.save(w);
+        self.scl.save(w);
+        self.sda.save(w);
+        self.output.save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        let (mut mode, mut next_mode) = (0u8, 0u8);
+        self.data.load(r)?;
+        mode.load(r)?;
+        next_mode.load(r)?;
+        self.device.load(r)?;
+        self.address.load(r)?;
+        self.shift.load(r)?;
+        self.bits.load(r)?;
+        self.scl.load(r)?;
+        self.sda.load(r)?;
+        self.output.load(r)?;
+        self.mode = Mode::from_u8(mode);
+        self.next_mode = Mode::from_u8(next_mode);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an I2C master bit-banging the lines, as a game does through the
+    // mapper
+    struct Master {
+        eeprom: Eeprom,
+    }
+
+    impl Master {
+        fn new(chip: Chip) -> Self {
+            Master { eeprom: Eeprom::new(chip) }
+        }
+
+        fn start(&mut self) {
+            self.eeprom.write(false, true);
+            self.eeprom.write(true, true);
+            self.eeprom.write(true, false);
+            self.eeprom.write(false, false);
+        }
+
+        fn stop(&mut self) {
+            self.eeprom.write(false, false);
+            self.eeprom.write(true, false);
+            self.eeprom.write(true, true);
+        }
+
+        // one clock pulse, returns SDA as the EEPROM drives it with SCL high
+        fn clock(&mut self, sda: bool) -> bool {
+            self.eeprom.write(false, sda);
+            self.eeprom.write(true, sda);
+            let output = self.eeprom.read();
+            self.eeprom.write(false, sda);
+            output
+        }
+
+        fn bit(&self, i: u8) -> u8 {
+            match self.eeprom.chip {
+                Chip::X24C01 => i,
+                Chip::X24C02 => 7 - i,
+            }
+        }
+
+        // returns whether the EEPROM acknowledged it
+        fn send(&mut self, byte: u8) -> bool {
+            for i in 0..8 {
+                let bit = self.bit(i);
+                assert!(self.clock(byte & (1 << bit) != 0), "SDA held while receiving");
+            }
+            let ack = !self.clock(true);
+            assert!(self.eeprom.read(), "SDA held after the acknowledge");
+            ack
+        }
+
+        // the master acknowledges to read on
+        fn receive(&mut self, ack: bool) -> u8 {
+            let mut byte = 0;
+            for i in 0..8 {
+                if self.clock(true) {
+                    byte |= 1 << self.bit(i);
+                }
+            }
+            self.clock(!ack);
+            byte
+        }
+    }
+
+    #[test]
+    fn x24c02_write_and_read() {
+        let mut m = Master::new(Chip::X24C02);
+        m.start();
+        assert!(m.send(0xa0));
+        assert!(m.send(0x10));
+        assert!(m.send(0x12));
+        assert!(m.send(0x34));
+        m.stop();
+        assert_eq!(&m.eeprom.data()[0x0f..0x13], &[0xff, 0x12, 0x34, 0xff]);
+
+        // random read, a dummy write of the address then a restart
+        m.start();
+        assert!(m.send(0xa0));
+        assert!(m.send(0x10));
+        m.start();
+        assert!(m.send(0xa1));
+        assert_eq!(m.receive(true), 0x12);
+        assert_eq!(m.receive(false), 0x34);
+        m.stop();
+
+        // current address read, from where the last one left off
+        m.eeprom.data_mut()[0x12] = 0x56;
+        m.start();
+        assert!(m.send(0xa1));
+        assert_eq!(m.receive(false), 0x56);
+        m.stop();
+    }
+
+    #[test]
+    fn x24c02_addressing_and_wraparound() {
+        let mut m = Master::new(Chip::X24C02);
+        // nothing answers to another device address
+        m.start();
+        assert!(!m.send(0xb0));
+        assert!(!m.send(0x00));
+        assert!(!m.send(0x12));
+        m.stop();
+        assert!(m.eeprom.data().iter().all(|&b| b == 0xff));
+
+        m.start();
+        assert!(m.send(0xa0));
+        assert!(m.send(0xff));
+        assert!(m.send(0x12));
+        assert!(m.send(0x34));
+        m.stop();
+        assert_eq!((m.eeprom.data()[0xff], m.eeprom.data()[0x00]), (0x12, 0x34));
+
+        m.start();
+        assert!(m.send(0xa0));
+        assert!(m.send(0xff));
+        m.start();
+        assert!(m.send(0xa1));
+        assert_eq!(m.receive(true), 0x12);
+        assert_eq!(m.receive(false), 0x34);
+        m.stop();
+    }
+
+    #[test]
+    fn x24c02_start_and_stop() {
+        let mut m = Master::new(Chip::X24C02);
+        // a stop in the middle of a byte drops it
+        m.start();
+        assert!(m.send(0xa0));
+        assert!(m.send(0x20));
+        for _ in 0..4 {
+            m.clock(false);
+        }
+        m.stop();
+        for _ in 0..9 {
+            assert!(m.clock(false));
+        }
+        assert_eq!(m.eeprom.data()[0x20], 0xff);
+
+        // a start in the middle of a byte begins again
+        m.start();
+        m.clock(true);
+        m.clock(false);
+        m.start();
+        assert!(m.send(0xa0));
+        assert!(m.send(0x20));
+        assert!(m.send(0x12));
+        m.stop();
+        assert_eq!(m.eeprom.data()[0x20], 0x12);
+    }
+
+    #[test]
+    fn x24c01_write_and_read() {
+        let mut m = Master::new(Chip::X24C01);
+        // 7 address bits and a write bit, no device address
+        m.start();
+        assert!(m.send(0x10));
+        assert!(m.send(0x12));
+        // one byte per write
+        assert!(!m.send(0x34));
+        m.stop();
+        assert_eq!(&m.eeprom.data()[0x0f..0x13], &[0xff, 0x12, 0xff, 0xff]);
+
+        m.start();
+        assert!(m.send(0x80 | 0x10));
+        assert_eq!(m.receive(true), 0x12);
+        assert_eq!(m.receive(false), 0xff);
+        m.stop();
+    }
+
+    #[test]
+    fn x24c01_wraparound() {
+        let mut m = Master::new(Chip::X24C01);
+        m.start();
+        assert!(m.send(0x7f));
+        assert!(m.send(0x12));
+        m.stop();
+        m.start();
+        assert!(m.send(0x00));
+        assert!(m.send(0x34));
+        m.stop();
+
+        m.start();
+        assert!(m.send(0x80 | 0x7f));
+        assert_eq!(m.receive(true), 0x12);
+        assert_eq!(m.receive(false), 0x34);
+        m.stop();
+    }
+}
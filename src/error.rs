@@ -19,13 +19,13 @@ impl<T, E> ResultContext<T, E> for result::Result<T, E>
         where E: error::Error
 {
     fn context(self, s: String) -> Result<T, Error> {
-        self.map_err(|e| Error::new(format!("{}: {}", s, e.description())))
+        self.map_err(|e| Error::new(format!("{}: {}", s, e)))
     }
 }
 
 impl<E: error::Error> From<E> for Error {
     fn from(e: E) -> Self {
-        Error::new(e.description().to_string())
+        Error::new(e.to_string())
     }
 }
 
@@ -0,0 +1,430 @@
+// A GDB remote serial protocol stub, so that a client speaking the
+// protocol can debug the CPU over a TCP connection:
+//
+//     (gdb) target remote localhost:6502
+//
+// Registers are A, X, Y, P and SP, a byte each, then PC, 2 bytes little
+// endian, which target.xml also tells. Memory is read without side
+// effects (see `Bus::peek`) and written through the bus. Breakpoints
+// are the debugger's, so its symbols and bank handling apply.
+// https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html
+
+use std::io::{self, Read, Write};
+use std::str;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use debugger::{Breakpoint, Debugger};
+use error::Error;
+use mem::Access;
+use nes::Nes;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+// ^C sent by the client while the CPU runs
+const INTERRUPT: u8 = 0x03;
+
+const TARGET_XML: &str = "<?xml version=\"1.0\"?>\
+<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+<target version=\"1.0\">\
+<feature name=\"org.redwhite.6502\">\
+<reg name=\"a\" bitsize=\"8\" regnum=\"0\"/>\
+<reg name=\"x\" bitsize=\"8\"/>\
+<reg name=\"y\" bitsize=\"8\"/>\
+<reg name=\"p\" bitsize=\"8\"/>\
+<reg name=\"sp\" bitsize=\"8\"/>\
+<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+</feature>\
+</target>";
+
+// what the client left the stub in after a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Session {
+    Attached,
+    Detached,
+    Killed,
+}
+
+// what to do after a command
+enum Action {
+    Reply(String),
+    Continue,
+    Step,
+    Detach,
+    Kill,
+}
+
+pub struct GdbStub {
+    stream: TcpStream,
+    debugger: Debugger,
+    // received and not yet handled
+    input: Vec<u8>,
+    // the client asked to continue
+    running: bool,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// by bytes, packets may have anything in them
+fn from_hex(text: &str) -> Result<Vec<u8>, Error> {
+    let digits = text.as_bytes();
+    if !digits.len().is_multiple_of(2) {
+        return Err(Error::new(format!("odd number of hex digits: {}", text)));
+    }
+    digits.chunks(2)
+          .map(|pair| match ((pair[0] as char).to_digit(16), (pair[1] as char).to_digit(16)) {
+              (Some(high), Some(low)) => Ok((high << 4 | low) as u8),
+              _ => Err(Error::new(format!("bad hex: {}", text))),
+          })
+          .collect()
+}
+
+fn parse_hex(text: &str) -> Result<u16, Error> {
+    u16::from_str_radix(text, 16).map_err(|_| Error::new(format!("bad number: {}", text)))
+}
+
+// "ADDR,LEN"
+fn parse_range(text: &str) -> Result<(u16, u16), Error> {
+    match text.find(',') {
+        Some(i) => Ok((parse_hex(&text[..i])?, parse_hex(&text[i + 1..])?)),
+        None => Err(Error::new(format!("bad range: {}", text))),
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+impl GdbStub {
+    // Wait for a client on `addr` and stop the CPU for it.
+    pub fn accept<A: ToSocketAddrs>(addr: A, debugger: Debugger) -> Result<Self, Error> {
+        GdbStub::accept_on(&TcpListener::bind(addr)?, debugger)
+    }
+
+    // the same, on a socket already bound
+    pub fn accept_on(listener: &TcpListener, debugger: Debugger) -> Result<Self, Error> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(GdbStub {
+            stream,
+            debugger,
+            input: Vec::new(),
+            running: false,
+        })
+    }
+
+    // Read what the client sent, waiting for it or not. Returns false
+    // if nothing was there.
+    fn receive(&mut self, wait: bool) -> Result<bool, Error> {
+        let mut buf = [0; 1024];
+        self.stream.set_nonblocking(!wait)?;
+        let result = self.stream.read(&mut buf);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Err(Error::new("client disconnected".to_string())),
+            Ok(n) => {
+                self.input.extend_from_slice(&buf[..n]);
+                Ok(true)
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // whether the client sent ^C, without waiting for it
+    fn interrupted(&mut self) -> Result<bool, Error> {
+        self.receive(false)?;
+        match self.input.iter().position(|&b| b == INTERRUPT) {
+            Some(i) => {
+                self.input.remove(i);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // The next packet. Packets are acked with + as they come, or refused
+    // with - to be sent again.
+    fn packet(&mut self) -> Result<String, Error> {
+        loop {
+            // acks of our replies, and ^C sent too late, come before a packet
+            let start = self.input.iter().position(|&b| b == b'$').unwrap_or(self.input.len());
+            self.input.drain(..start);
+            let end = self.input.iter().position(|&b| b == b'#');
+            if let Some(end) = end.filter(|&end| self.input.len() >= end + 3) {
+                let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+                let data = &packet[1..end];
+                let sum = str::from_utf8(&packet[end + 1..]).ok()
+                                                             .and_then(|s| u8::from_str_radix(s, 16).ok());
+                if sum != Some(checksum(data)) {
+                    self.stream.write_all(b"-")?;
+                    continue;
+                }
+                self.stream.write_all(b"+")?;
+                return Ok(String::from_utf8_lossy(data).into_owned());
+            }
+            self.receive(true)?;
+        }
+    }
+
+    fn send(&mut self, data: &str) -> Result<(), Error> {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())?;
+        Ok(())
+    }
+
+    fn stop(&mut self, signal: u8) -> Result<(), Error> {
+        self.running = false;
+        self.send(&format!("S{:02x}", signal))
+    }
+
+    fn registers(&self, nes: &Nes) -> [u8; 7] {
+        let r = nes.cpu().registers();
+        [r.a, r.x, r.y, r.p, r.sp, r.pc as u8, (r.pc >> 8) as u8]
+    }
+
+    fn set_registers(&self, nes: &mut Nes, bytes: &[u8]) {
+        let mut r = nes.cpu().registers();
+        for (i, &b) in bytes.iter().enumerate() {
+            match i {
+                0 => r.a = b,
+                1 => r.x = b,
+                2 => r.y = b,
+                3 => r.p = b,
+                4 => r.sp = b,
+                5 => r.pc = r.pc & 0xff00 | b as u16,
+                6 => r.pc = r.pc & 0x00ff | (b as u16) << 8,
+                _ => break,
+            }
+        }
+        nes.cpu_mut().set_registers(r);
+    }
+
+    // where register n starts in `registers` and how long it is
+    fn register_bytes(n: u16) -> Result<(usize, usize), Error> {
+        match n {
+            0..=4 => Ok((n as usize, 1)),
+            5 => Ok((5, 2)),
+            _ => Err(Error::new(format!("no register {}", n))),
+        }
+    }
+
+    // qXfer reads send `len` bytes from `offset`, l marks the last part
+    fn transfer(data: &str, args: &str) -> Result<String, Error> {
+        let (offset, len) = parse_range(args)?;
+        let start = (offset as usize).min(data.len());
+        let end = (start + len as usize).min(data.len());
+        let more = if end < data.len() { "m" } else { "l" };
+        Ok(format!("{}{}", more, &data[start..end]))
+    }
+
+    fn handle(&mut self, nes: &mut Nes, command: &str) -> Result<Action, Error> {
+        let (kind, args) = command.split_at(command.chars().next().map_or(0, |c| c.len_utf8()));
+        let reply = match kind {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => to_hex(&self.registers(nes)),
+            "G" => {
+                self.set_registers(nes, &from_hex(args)?);
+                "OK".to_string()
+            }
+            "p" => {
+                let (start, len) = GdbStub::register_bytes(parse_hex(args)?)?;
+                to_hex(&self.registers(nes)[start..start + len])
+            }
+            "P" => {
+                let i = args.find('=').ok_or_else(|| Error::new(format!("bad P packet: {}", args)))?;
+                let (start, len) = GdbStub::register_bytes(parse_hex(&args[..i])?)?;
+                let value = from_hex(&args[i + 1..])?;
+                let mut regs = self.registers(nes);
+                for (reg, &b) in regs[start..start + len].iter_mut().zip(value.iter()) {
+                    *reg = b;
+                }
+                self.set_registers(nes, &regs);
+                "OK".to_string()
+            }
+            "m" => {
+                let (addr, len) = parse_range(args)?;
+                let bytes: Vec<u8> = (0..len).map(|i| nes.peek(addr.wrapping_add(i))).collect();
+                to_hex(&bytes)
+            }
+            "M" => {
+                let i = args.find(':').ok_or_else(|| Error::new(format!("bad M packet: {}", args)))?;
+                let (addr, _) = parse_range(&args[..i])?;
+                let bus = nes.bus_mut();
+                for (i, b) in from_hex(&args[i + 1..])?.into_iter().enumerate() {
+                    bus.write(addr.wrapping_add(i as u16), b);
+                }
+                // not something the program did
+                bus.take_watch_hit();
+                "OK".to_string()
+            }
+            // software and hardware breakpoints are the same here
+            "Z" | "z" if args.starts_with('0') || args.starts_with('1') => {
+                let mut fields = args.split(',');
+                let addr = parse_hex(fields.nth(1).unwrap_or(""))?;
+                let breakpoints = &mut self.debugger.breakpoints;
+                breakpoints.retain(|b| b.addr != Some(addr) || b.bank.is_some() || b.condition.is_some());
+                if kind == "Z" {
+                    breakpoints.push(Breakpoint { addr: Some(addr), bank: None, condition: None });
+                }
+                "OK".to_string()
+            }
+            "c" | "s" => {
+                if !args.is_empty() {
+                    let mut regs = nes.cpu().registers();
+                    regs.pc = parse_hex(args)?;
+                    nes.cpu_mut().set_registers(regs);
+                }
+                return Ok(if kind == "c" { Action::Continue } else { Action::Step });
+            }
+            "D" => return Ok(Action::Detach),
+            "k" => return Ok(Action::Kill),
+            "H" => "OK".to_string(),
+            "q" if args.starts_with("Supported") => "PacketSize=1000;qXfer:features:read+".to_string(),
+            "q" if args == "Attached" => "1".to_string(),
+            "q" if args.starts_with("Xfer:features:read:target.xml:") => {
+                GdbStub::transfer(TARGET_XML, &args["Xfer:features:read:target.xml:".len()..])?
+            }
+            // anything else is not supported
+            _ => String::new(),
+        };
+        Ok(Action::Reply(reply))
+    }
+
+    // Handle commands until the client resumes execution. Returns None
+    // to keep running.
+    fn serve(&mut self, nes: &mut Nes) -> Result<Option<Session>, Error> {
+        while !self.running {
+            let command = self.packet()?;
+            match self.handle(nes, &command) {
+                Ok(Action::Reply(reply)) => self.send(&reply)?,
+                Ok(Action::Continue) => self.running = true,
+                Ok(Action::Step) => {
+                    self.debugger.step(nes);
+                    self.send(&format!("S{:02x}", SIGTRAP))?;
+                }
+                Ok(Action::Detach) => {
+                    self.send("OK")?;
+                    return Ok(Some(Session::Detached));
+                }
+                Ok(Action::Kill) => return Ok(Some(Session::Killed)),
+                Err(_) => self.send("E01")?,
+            }
+        }
+        Ok(None)
+    }
+
+    // Run a frame, or as much of it as the client lets run. Use in place
+    // of `Nes::run_frame`.
+    pub fn run_frame(&mut self, nes: &mut Nes) -> Result<Session, Error> {
+        loop {
+            if let Some(session) = self.serve(nes)? {
+                return Ok(session);
+            }
+            if self.interrupted()? {
+                self.stop(SIGINT)?;
+                continue;
+            }
+            match self.debugger.run_frame(nes) {
+                Some(_) => self.stop(SIGTRAP)?,
+                None => return Ok(Session::Attached),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use nes::nes_running;
+
+    fn read_byte(stream: &mut TcpStream) -> u8 {
+        let mut b = [0];
+        stream.read_exact(&mut b).unwrap();
+        b[0]
+    }
+
+    // send a packet as the client, and get the reply
+    fn command(stream: &mut TcpStream, data: &str) -> String {
+        write!(stream, "${}#{:02x}", data, checksum(data.as_bytes())).unwrap();
+        assert_eq!(read_byte(stream), b'+', "{} not acked", data);
+        assert_eq!(read_byte(stream), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match read_byte(stream) {
+                b'#' => break,
+                b => reply.push(b),
+            }
+        }
+        let sum = [read_byte(stream), read_byte(stream)];
+        assert_eq!(str::from_utf8(&sum).unwrap(), format!("{:02x}", checksum(&reply)));
+        stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    #[test]
+    fn session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut s = TcpStream::connect(addr).unwrap();
+            // registers
+            assert_eq!(command(&mut s, "G0a0b0c24fd00c0"), "OK");
+            assert_eq!(command(&mut s, "g"), "0a0b0c24fd00c0");
+            assert_eq!(command(&mut s, "P0=55"), "OK");
+            assert_eq!(command(&mut s, "p0"), "55");
+            assert_eq!(command(&mut s, "p5"), "00c0");
+            assert_eq!(command(&mut s, "p6"), "E01");
+            // memory
+            assert_eq!(command(&mut s, "M0010,2:beef"), "OK");
+            assert_eq!(command(&mut s, "m0010,2"), "beef");
+            assert_eq!(command(&mut s, "m0010,x"), "E01");
+            // breakpoints, continuing and stepping
+            assert_eq!(command(&mut s, "Z0,c002,1"), "OK");
+            assert_eq!(command(&mut s, "c"), "S05");
+            assert_eq!(command(&mut s, "p5"), "02c0");
+            assert_eq!(command(&mut s, "s"), "S05");
+            assert_eq!(command(&mut s, "p5"), "03c0");
+            assert_eq!(command(&mut s, "z0,c002,1"), "OK");
+            assert_eq!(command(&mut s, "Z0,c001,1"), "OK");
+            assert_eq!(command(&mut s, "c"), "S05");
+            assert_eq!(command(&mut s, "p5"), "01c0");
+            // a bad checksum is refused, the packet sent again
+            s.write_all(b"$g#00").unwrap();
+            assert_eq!(read_byte(&mut s), b'-');
+            assert_eq!(command(&mut s, "p5"), "01c0");
+            // not hex, nor ASCII
+            assert_eq!(command(&mut s, "G\u{e9}"), "E01");
+            // target.xml, in parts
+            let xml = command(&mut s, "qXfer:features:read:target.xml:0,10");
+            assert_eq!(xml, format!("m{}", &TARGET_XML[..0x10]));
+            let xml = command(&mut s, "qXfer:features:read:target.xml:10,1000");
+            assert_eq!(xml, format!("l{}", &TARGET_XML[0x10..]));
+            assert_eq!(command(&mut s, "D"), "OK");
+        });
+
+        // nop, nop, nop, jmp $c000
+        let mut nes = nes_running(&[0xea, 0xea, 0xea, 0x4c, 0x00, 0xc0]);
+        let mut stub = GdbStub::accept_on(&listener, Debugger::new()).unwrap();
+        let session = loop {
+            match stub.run_frame(&mut nes) {
+                Ok(Session::Attached) => continue,
+                session => break session,
+            }
+        };
+        client.join().unwrap();
+        assert_eq!(session.ok(), Some(Session::Detached));
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(from_hex("00a1FF").unwrap(), vec![0x00, 0xa1, 0xff]);
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("+f").is_err());
+        // two bytes, neither of them a digit
+        assert!(from_hex("é").is_err());
+        assert!(from_hex("0é0").is_err());
+    }
+}
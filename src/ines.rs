@@ -3,7 +3,24 @@
 use std::fs::File;
 use std::path::Path;
 use std::io::Read;
-use error::{Error, ResultContext};
+use crc32;
+use error::Error;
+use md5::Md5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+    SingleLower,
+    SingleUpper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+}
 
 #[derive(Debug)]
 pub struct Header {
@@ -14,6 +31,82 @@ pub struct Header {
     pub n_prgram: u8, // size of PRG RAM in 8 KB units
     flag9: u8,
     flag10: u8,
+    // bytes 11-15, only meaningful in NES 2.0
+    // https://wiki.nesdev.com/w/index.php/NES_2.0
+    nes2: [u8; 5],
+}
+
+impl Header {
+    pub fn mapper(&self) -> u8 {
+        (self.flag7 & 0xf0) | (self.flag6 >> 4)
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        if self.flag6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        }
+        else if self.flag6 & 0x01 != 0 {
+            Mirroring::Vertical
+        }
+        else {
+            Mirroring::Horizontal
+        }
+    }
+
+    // NES 2.0 submapper, 0 if unspecified
+    pub fn submapper(&self) -> u8 {
+        if self.is_nes2() { self.n_prgram >> 4 } else { 0 }
+    }
+
+    // battery-backed PRG RAM at $6000-$7fff
+    pub fn has_battery(&self) -> bool {
+        self.flag6 & 0x02 != 0
+    }
+
+    // 512-byte trainer before PRG ROM
+    pub fn has_trainer(&self) -> bool {
+        self.flag6 & 0x04 != 0
+    }
+
+    pub fn is_nes2(&self) -> bool {
+        self.flag7 & 0x0c == 0x08
+    }
+
+    // NES 2.0 default expansion device, 0 if unspecified
+    // https://wiki.nesdev.com/w/index.php/NES_2.0#Default_Expansion_Device
+    pub fn expansion_device(&self) -> u8 {
+        if self.is_nes2() { self.nes2[4] & 0x3f } else { 0 }
+    }
+
+    // The 16 bytes of the header, without the trainer flag since the
+    // trainer is not kept.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..4].copy_from_slice(b"NES\x1a");
+        bytes[4] = self.n_prgrom;
+        bytes[5] = self.n_chrrom;
+        bytes[6] = self.flag6 & !0x04;
+        bytes[7] = self.flag7;
+        bytes[8] = self.n_prgram;
+        bytes[9] = self.flag9;
+        bytes[10] = self.flag10;
+        bytes[11..].copy_from_slice(&self.nes2);
+        bytes
+    }
+
+    pub fn region(&self) -> Region {
+        if self.is_nes2() {
+            // 0: NTSC, 1: PAL, 2: multiple-region, 3: Dendy
+            return if self.nes2[1] & 0x03 == 1 { Region::Pal } else { Region::Ntsc };
+        }
+        // flag 9 is rarely set, flag 10 is the unofficial extension
+        if self.flag9 & 0x01 != 0 || self.flag10 & 0x03 == 0x02 {
+            Region::Pal
+        }
+        else {
+            Region::Ntsc
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -24,14 +117,38 @@ pub struct Ines {
 }
 
 impl Ines {
+    // CRC-32 of PRG ROM followed by CHR ROM, without the header
+    pub fn crc32(&self) -> u32 {
+        crc32::update(crc32::crc32(&self.prgrom), &self.chrrom)
+    }
+
+    // MD5 of the same, FCEUX's checksum
+    pub fn md5(&self) -> [u8; 16] {
+        let mut md5 = Md5::new();
+        md5.update(&self.prgrom);
+        md5.update(&self.chrrom);
+        md5.finish()
+    }
+
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         let mut file = File::open(path)?;
+        Ines::from_reader(&mut file)
+    }
 
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut bytes = bytes;
+        Ines::from_reader(&mut bytes)
+    }
+
+    fn from_reader<R: Read>(file: &mut R) -> Result<Self, Error> {
         let mut bytes = [0u8;11];
         file.read_exact(&mut bytes)?;
-        if &bytes[0..4] != &[b'N', b'E', b'S', 0x1a] {
+        if bytes[0..4] != [b'N', b'E', b'S', 0x1a] {
             return Err(Error::new("not a NES file".to_string()));
         }
+        // 5 zero bytes in iNES, more flags in NES 2.0
+        let mut nes2 = [0u8;5];
+        file.read_exact(&mut nes2)?;
         let header = Header {
             n_prgrom: bytes[4],
             n_chrrom: bytes[5],
@@ -40,10 +157,13 @@ impl Ines {
             n_prgram: bytes[8],
             flag9:    bytes[9],
             flag10:   bytes[10],
+            nes2,
         };
-        // skip 5 zero bytes
-        let mut bytes = [0u8;5];
-        file.read_exact(&mut bytes)?;
+
+        if header.has_trainer() {
+            let mut trainer = [0u8;512];
+            file.read_exact(&mut trainer)?;
+        }
 
         let mut prgrom = Vec::new();
         let mut chrrom = Vec::new();
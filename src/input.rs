@@ -0,0 +1,401 @@
+// Devices that can be plugged into the two controller ports.
+// https://wiki.nesdev.com/w/index.php/Input_devices
+
+use std::any::Any;
+use controller::{Buttons, Controller};
+use palette::{self, PaletteSet, DEFAULT_PALETTE};
+use ppu::{Ppu, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+pub trait InputDevice {
+    // $4016 write, seen by the devices in both ports
+    fn write(&mut self, value: u8);
+
+    // $4016/$4017 read, returns bits 0-4, the upper bits come from
+    // the open bus
+    fn read(&mut self, ppu: &Ppu) -> u8;
+
+    // Set the buttons of a joypad plugged into the device. Adapters
+    // have more than one slot, slot 0 is the joypad read first.
+    fn set_buttons(&mut self, _slot: usize, _buttons: Buttons) {}
+
+    // to reach the state specific to each device, e.g. the Zapper aim
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl InputDevice for Controller {
+    fn write(&mut self, value: u8) {
+        Controller::write(self, value);
+    }
+
+    fn read(&mut self, _ppu: &Ppu) -> u8 {
+        Controller::read(self)
+    }
+
+    fn set_buttons(&mut self, slot: usize, buttons: Buttons) {
+        if slot == 0 {
+            Controller::set_buttons(self, buttons);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// One half of the NES Four Score, the controller in slot 1 is read
+// after the one in slot 0, followed by a signature identifying the port.
+// https://wiki.nesdev.com/w/index.php/Four_Score
+pub struct FourScore {
+    controllers: [Controller; 2],
+    // read LSB first
+    signature: u8,
+    count: u8,
+    strobe: bool,
+}
+
+impl FourScore {
+    // signatures are 0,0,0,1,0,0,0,0 on $4016 and 0,0,1,0,0,0,0,0 on $4017
+    pub fn new(port: usize) -> Self {
+        FourScore {
+            controllers: [Controller::new(), Controller::new()],
+            signature: if port == 0 { 0x08 } else { 0x04 },
+            count: 0,
+            strobe: false,
+        }
+    }
+}
+
+impl InputDevice for FourScore {
+    fn write(&mut self, value: u8) {
+        for c in self.controllers.iter_mut() {
+            c.write(value);
+        }
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.count = 0;
+        }
+    }
+
+    fn read(&mut self, _ppu: &Ppu) -> u8 {
+        if self.strobe {
+            return self.controllers[0].read();
+        }
+        let bit = match self.count {
+            0..=7 => self.controllers[0].read(),
+            8..=15 => self.controllers[1].read(),
+            16..=23 => (self.signature >> (self.count - 16)) & 1,
+            _ => 1,
+        };
+        if self.count < 24 {
+            self.count += 1;
+        }
+        bit
+    }
+
+    fn set_buttons(&mut self, slot: usize, buttons: Buttons) {
+        if let Some(c) = self.controllers.get_mut(slot) {
+            c.set_buttons(buttons);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// One half of a Famicom four player setup, the extra joypad
+// plugged into the expansion port is read on bit 1.
+pub struct FamicomFourPlayer {
+    controllers: [Controller; 2],
+}
+
+impl FamicomFourPlayer {
+    pub fn new() -> Self {
+        FamicomFourPlayer { controllers: [Controller::new(), Controller::new()] }
+    }
+}
+
+impl Default for FamicomFourPlayer {
+    fn default() -> Self {
+        FamicomFourPlayer::new()
+    }
+}
+
+impl InputDevice for FamicomFourPlayer {
+    fn write(&mut self, value: u8) {
+        for c in self.controllers.iter_mut() {
+            c.write(value);
+        }
+    }
+
+    fn read(&mut self, _ppu: &Ppu) -> u8 {
+        self.controllers[0].read() | self.controllers[1].read() << 1
+    }
+
+    fn set_buttons(&mut self, slot: usize, buttons: Buttons) {
+        if let Some(c) = self.controllers.get_mut(slot) {
+            c.set_buttons(buttons);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/Zapper
+pub struct Zapper {
+    // where the gun points on the screen, None if away from it
+    pub aim: Option<(usize, usize)>,
+    pub trigger: bool,
+    palette: PaletteSet,
+}
+
+// how many scanlines the photodiode keeps seeing a lit pixel
+const ZAPPER_PERSISTENCE: usize = 20;
+const ZAPPER_THRESHOLD: u32 = 0x80;
+
+impl Zapper {
+    pub fn new() -> Self {
+        Zapper { aim: None, trigger: false, palette: DEFAULT_PALETTE }
+    }
+
+    // the palette used to tell how bright a pixel is
+    pub fn set_palette(&mut self, palette: PaletteSet) {
+        self.palette = palette;
+    }
+
+    fn light_sensed(&self, ppu: &Ppu) -> bool {
+        let (x, y) = match self.aim {
+            Some((x, y)) if x < SCREEN_WIDTH && y < SCREEN_HEIGHT => (x, y),
+            _ => return false,
+        };
+        // the pixel must have been drawn shortly before
+        let scanline = ppu.scanline();
+        if scanline < y || scanline > y + ZAPPER_PERSISTENCE
+            || (scanline == y && ppu.dot() <= x) {
+            return false;
+        }
+        let color = palette::lookup(&self.palette, ppu.framebuffer()[y * SCREEN_WIDTH + x]);
+        let luma = (color.r as u32 * 299 + color.g as u32 * 587 + color.b as u32 * 114) / 1000;
+        luma >= ZAPPER_THRESHOLD
+    }
+}
+
+impl Default for Zapper {
+    fn default() -> Self {
+        Zapper::new()
+    }
+}
+
+impl InputDevice for Zapper {
+    fn write(&mut self, _value: u8) {}
+
+    // bit 3: 0 if light is sensed, bit 4: 1 if the trigger is pulled
+    fn read(&mut self, ppu: &Ppu) -> u8 {
+        let mut value = 0;
+        if !self.light_sensed(ppu) {
+            value |= 0x08;
+        }
+        if self.trigger {
+            value |= 0x10;
+        }
+        value
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// The NES Arkanoid controller
+// https://wiki.nesdev.com/w/index.php/Arkanoid_controller
+#[derive(Default)]
+pub struct Vaus {
+    // knob position as seen by the game, roughly $62 to $f2
+    pub position: u8,
+    pub button: bool,
+    shift: u8,
+    strobe: bool,
+}
+
+impl Vaus {
+    pub fn new() -> Self {
+        Vaus { position: 0x98, ..Default::default() }
+    }
+}
+
+impl InputDevice for Vaus {
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            // sent inverted
+            self.shift = !self.position;
+        }
+    }
+
+    // bit 3: the button, bit 4: the position, MSB first
+    fn read(&mut self, _ppu: &Ppu) -> u8 {
+        let mut value = ((self.shift >> 7) & 1) << 4;
+        if self.button {
+            value |= 0x08;
+        }
+        if !self.strobe {
+            self.shift <<= 1;
+        }
+        value
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/Power_Pad
+#[derive(Default)]
+pub struct PowerPad {
+    // bit n is the button numbered n + 1 on side B
+    pub buttons: u16,
+    side_a: bool,
+    shift_d3: u8,
+    shift_d4: u8,
+    strobe: bool,
+}
+
+// the order buttons are shifted out, numbered as on side B
+const POWER_PAD_D4: [u16; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const POWER_PAD_D3: [u16; 4] = [4, 3, 12, 8];
+
+impl PowerPad {
+    pub fn new(side_a: bool) -> Self {
+        PowerPad { side_a, ..Default::default() }
+    }
+
+    fn pressed(&self, button: u16) -> bool {
+        // side A is side B flipped horizontally
+        let button = if self.side_a {
+            let row = (button - 1) / 4;
+            let col = (button - 1) % 4;
+            row * 4 + (3 - col) + 1
+        }
+        else {
+            button
+        };
+        self.buttons & (1 << (button - 1)) != 0
+    }
+
+    fn latch(&mut self) {
+        self.shift_d4 = 0;
+        for (i, &b) in POWER_PAD_D4.iter().enumerate() {
+            if self.pressed(b) {
+                self.shift_d4 |= 1 << i;
+            }
+        }
+        // the remaining bits read as 1
+        self.shift_d3 = 0xf0;
+        for (i, &b) in POWER_PAD_D3.iter().enumerate() {
+            if self.pressed(b) {
+                self.shift_d3 |= 1 << i;
+            }
+        }
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self, _ppu: &Ppu) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+        let value = ((self.shift_d3 & 1) << 3) | ((self.shift_d4 & 1) << 4);
+        self.shift_d3 = (self.shift_d3 >> 1) | 0x80;
+        self.shift_d4 = (self.shift_d4 >> 1) | 0x80;
+        value
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// What is plugged into the controller ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expansion {
+    Standard,
+    FourScore,
+    FamicomFourPlayer,
+    // Zapper in port 2, joypad in port 1
+    Zapper,
+    TwoZappers,
+    // Power Pad in port 2, joypad in port 1
+    PowerPadA,
+    PowerPadB,
+    // Arkanoid controller in port 2, joypad in port 1
+    Vaus,
+}
+
+impl Expansion {
+    // https://wiki.nesdev.com/w/index.php/NES_2.0#Default_Expansion_Device
+    pub fn from_nes2(device: u8) -> Option<Self> {
+        match device {
+            0x01 => Some(Expansion::Standard),
+            0x02 => Some(Expansion::FourScore),
+            0x03 => Some(Expansion::FamicomFourPlayer),
+            0x08 => Some(Expansion::Zapper),
+            0x09 => Some(Expansion::TwoZappers),
+            0x0b => Some(Expansion::PowerPadA),
+            0x0c => Some(Expansion::PowerPadB),
+            0x0f => Some(Expansion::Vaus),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(Expansion::Standard),
+            "fourscore" => Some(Expansion::FourScore),
+            "famicom4p" => Some(Expansion::FamicomFourPlayer),
+            "zapper" => Some(Expansion::Zapper),
+            "zapper2" => Some(Expansion::TwoZappers),
+            "powerpad-a" => Some(Expansion::PowerPadA),
+            "powerpad-b" => Some(Expansion::PowerPadB),
+            "vaus" => Some(Expansion::Vaus),
+            _ => None,
+        }
+    }
+
+    // the name `from_name` takes
+    pub fn name(self) -> &'static str {
+        match self {
+            Expansion::Standard => "standard",
+            Expansion::FourScore => "fourscore",
+            Expansion::FamicomFourPlayer => "famicom4p",
+            Expansion::Zapper => "zapper",
+            Expansion::TwoZappers => "zapper2",
+            Expansion::PowerPadA => "powerpad-a",
+            Expansion::PowerPadB => "powerpad-b",
+            Expansion::Vaus => "vaus",
+        }
+    }
+
+    // devices for port 1 and port 2
+    pub fn devices(self) -> [Box<dyn InputDevice>; 2] {
+        let joypad: Box<dyn InputDevice> = Box::new(Controller::new());
+        match self {
+            Expansion::Standard => [joypad, Box::new(Controller::new())],
+            Expansion::FourScore => [Box::new(FourScore::new(0)), Box::new(FourScore::new(1))],
+            Expansion::FamicomFourPlayer => [Box::new(FamicomFourPlayer::new()), Box::new(FamicomFourPlayer::new())],
+            Expansion::Zapper => [joypad, Box::new(Zapper::new())],
+            Expansion::TwoZappers => [Box::new(Zapper::new()), Box::new(Zapper::new())],
+            Expansion::PowerPadA => [joypad, Box::new(PowerPad::new(true))],
+            Expansion::PowerPadB => [joypad, Box::new(PowerPad::new(false))],
+            Expansion::Vaus => [joypad, Box::new(Vaus::new())],
+        }
+    }
+}
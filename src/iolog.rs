@@ -0,0 +1,261 @@
+// Counts, and optionally a log, of what the CPU reads from and writes to
+// the PPU, APU, controller and mapper registers, to see how a game uses
+// the hardware. The bus records the accesses, see `Bus::io_log`.
+//
+// Everything the cartridge sees except PRG ROM reads counts as the
+// mapper, so PRG RAM at $6000-$7fff does too.
+// https://wiki.nesdev.com/w/index.php/CPU_memory_map
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::mem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Device {
+    Ppu,
+    Apu,
+    OamDma,
+    Controller,
+    Mapper,
+}
+
+impl Device {
+    // the device behind an address, None for RAM, PRG ROM and unused
+    // addresses
+    fn of(addr: u16, write: bool) -> Option<Device> {
+        match addr {
+            0x2000..=0x3fff => Some(Device::Ppu),
+            0x4014 => Some(Device::OamDma),
+            0x4016 => Some(Device::Controller),
+            // the APU frame counter when written
+            0x4017 if write => Some(Device::Apu),
+            0x4017 => Some(Device::Controller),
+            0x4000..=0x4015 => Some(Device::Apu),
+            0x4020..=0x7fff => Some(Device::Mapper),
+            0x8000..=0xffff if write => Some(Device::Mapper),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Device::Ppu => "ppu",
+            Device::Apu => "apu",
+            Device::OamDma => "oam dma",
+            Device::Controller => "controller",
+            Device::Mapper => "mapper",
+        }
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/PPU_registers
+// https://wiki.nesdev.com/w/index.php/APU_registers
+fn register_name(addr: u16, write: bool) -> Option<&'static str> {
+    const PPU: [&str; 8] = ["PPUCTRL", "PPUMASK", "PPUSTATUS", "OAMADDR",
+                            "OAMDATA", "PPUSCROLL", "PPUADDR", "PPUDATA"];
+    const APU: [&str; 0x18] = ["SQ1_VOL", "SQ1_SWEEP", "SQ1_LO", "SQ1_HI",
+                               "SQ2_VOL", "SQ2_SWEEP", "SQ2_LO", "SQ2_HI",
+                               "TRI_LINEAR", "$4009", "TRI_LO", "TRI_HI",
+                               "NOISE_VOL", "$400d", "NOISE_LO", "NOISE_HI",
+                               "DMC_FREQ", "DMC_RAW", "DMC_START", "DMC_LEN",
+                               "OAMDMA", "SND_CHN", "JOY1", "JOY2"];
+    match addr {
+        0x2000..=0x3fff => Some(PPU[addr as usize & 7]),
+        0x4017 if write => Some("FRAME_COUNTER"),
+        0x4000..=0x4017 => Some(APU[addr as usize - 0x4000]),
+        _ => None,
+    }
+}
+
+// PPU registers by their address in $2000-$2007, the rest as they are
+fn register(addr: u16) -> u16 {
+    match addr {
+        0x2000..=0x3fff => 0x2000 | (addr & 7),
+        _ => addr,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoAccess {
+    // the CPU cycle it happens on, and the PC of the instruction doing it
+    pub cycle: usize,
+    pub pc: u16,
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+impl fmt::Display for IoAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let device = Device::of(self.addr, self.write).map_or("", |d| d.name());
+        let name = register_name(self.addr, self.write).map_or_else(String::new, |n| format!(" {}", n));
+        write!(f, "{:>10} {:04x} {} {:04x} {:02x} {}{}", self.cycle, self.pc,
+               if self.write { "w" } else { "r" }, self.addr, self.value, device, name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+    }
+}
+
+// accesses counted by register
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IoStats {
+    pub registers: BTreeMap<u16, Counts>,
+}
+
+impl IoStats {
+    fn count(&mut self, addr: u16, write: bool) {
+        let counts = self.registers.entry(register(addr)).or_default();
+        if write {
+            counts.writes += 1;
+        }
+        else {
+            counts.reads += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &IoStats) {
+        for (&addr, &counts) in &other.registers {
+            self.registers.entry(addr).or_default().add(counts);
+        }
+    }
+
+    pub fn device(&self, device: Device) -> Counts {
+        let mut total = Counts::default();
+        for (&addr, &counts) in &self.registers {
+            // $4017 is two devices, count what went where
+            if Device::of(addr, false) == Some(device) {
+                total.reads += counts.reads;
+            }
+            if Device::of(addr, true) == Some(device) {
+                total.writes += counts.writes;
+            }
+        }
+        total
+    }
+}
+
+// a line per device, then the registers accessed
+impl fmt::Display for IoStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let devices = [Device::Ppu, Device::Apu, Device::OamDma, Device::Controller, Device::Mapper];
+        let totals: Vec<String> = devices.iter().map(|&d| {
+            let counts = self.device(d);
+            format!("{} {}r {}w", d.name(), counts.reads, counts.writes)
+        }).collect();
+        writeln!(f, "{}", totals.join(", "))?;
+        for (&addr, counts) in &self.registers {
+            let name = match (register_name(addr, false), register_name(addr, true)) {
+                (Some(read), Some(write)) if read != write => format!("{}/{}", read, write),
+                (Some(name), _) => name.to_string(),
+                _ => format!("${:04x}", addr),
+            };
+            writeln!(f, "    {:<24} {:>6}r {:>6}w", name, counts.reads, counts.writes)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct IoLog {
+    // keep every access too, not only the counts
+    pub log_accesses: bool,
+    accesses: Vec<IoAccess>,
+    frame: IoStats,
+    total: IoStats,
+    // the instruction running, see `start_instruction`
+    pc: u16,
+    // the cycle of the accesses, and of the next bus tick
+    cycle: usize,
+    next_cycle: usize,
+}
+
+impl IoLog {
+    pub fn new(log_accesses: bool) -> Self {
+        IoLog { log_accesses, ..Default::default() }
+    }
+
+    // Tell the instruction about to run, accesses are logged with its PC.
+    // They are on the cycle it starts on until the bus ticks, a
+    // cycle-accurate CPU ticks before every access.
+    pub fn start_instruction(&mut self, pc: u16, cycle: usize) {
+        self.pc = pc;
+        self.cycle = cycle;
+        self.next_cycle = cycle;
+    }
+
+    // the bus started a CPU cycle
+    pub fn tick(&mut self) {
+        self.cycle = self.next_cycle;
+        self.next_cycle += 1;
+    }
+
+    pub fn access(&mut self, addr: u16, value: u8, write: bool) {
+        if Device::of(addr, write).is_none() {
+            return;
+        }
+        self.frame.count(addr, write);
+        if self.log_accesses {
+            self.accesses.push(IoAccess { cycle: self.cycle, pc: self.pc, addr, value, write });
+        }
+    }
+
+    // the accesses logged since the last call
+    pub fn take_accesses(&mut self) -> Vec<IoAccess> {
+        mem::take(&mut self.accesses)
+    }
+
+    // Counts since the last call, to be called once a frame. They are
+    // added to the totals.
+    pub fn end_frame(&mut self) -> IoStats {
+        let frame = mem::take(&mut self.frame);
+        self.total.merge(&frame);
+        frame
+    }
+
+    // counts of every frame ended
+    pub fn total(&self) -> &IoStats {
+        &self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nes::nes_running;
+
+    // the cycles of the accesses of the first instruction
+    fn cycles(accurate: bool, code: &[u8]) -> (usize, Vec<usize>) {
+        let mut nes = nes_running(code);
+        nes.set_cycle_accurate(accurate);
+        nes.bus_mut().io_log = Some(IoLog::new(true));
+        let start = nes.cpu().cycles();
+        nes.step();
+        let log = nes.bus_mut().io_log.as_mut().unwrap();
+        (start, log.take_accesses().iter().map(|a| a.cycle).collect())
+    }
+
+    #[test]
+    fn accesses_on_their_cycle() {
+        // lda $2002, read on the 4th cycle
+        let (start, accesses) = cycles(true, &[0xad, 0x02, 0x20]);
+        assert_eq!(accesses, vec![start + 3]);
+        // inc $2000, read, write back and write on the 4th to 6th
+        let (start, accesses) = cycles(true, &[0xee, 0x00, 0x20]);
+        assert_eq!(accesses, vec![start + 3, start + 4, start + 5]);
+        // the bus ticks after the instruction when not cycle-accurate,
+        // which does not write back either
+        let (start, accesses) = cycles(false, &[0xee, 0x00, 0x20]);
+        assert_eq!(accesses, vec![start, start]);
+    }
+}
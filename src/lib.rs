@@ -1,8 +1,34 @@
+#[cfg(feature = "sdl")]
 extern crate sdl2;
+extern crate zip;
 
+#[macro_use]
+pub mod savestate;
 pub mod cpu;
+pub mod opcode;
+pub mod disasm;
+pub mod cdl;
+pub mod symbols;
 pub mod mem;
+pub mod bus;
+pub mod iolog;
+pub mod ppu;
+pub mod apu;
+pub mod resample;
+pub mod controller;
+pub mod input;
+pub mod cartridge;
+pub mod bandai;
+pub mod eeprom;
+pub mod nes;
+pub mod rewind;
+pub mod movie;
+pub mod cheat;
+pub mod debugger;
+pub mod gdb;
 pub mod ines;
+pub mod crc32;
+pub mod md5;
 pub mod error;
 pub mod palette;
 
@@ -1,30 +1,65 @@
 use std::ops::{Deref, DerefMut};
 
+// what the CPU reads a byte for, code/data loggers tell them apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadKind {
+    Opcode,
+    Operand,
+    Data,
+    // the opcode at the destination of JMP ($nnnn)
+    IndirectOpcode,
+    // data at an address taken from memory, ($nn,x) and ($nn),y
+    IndirectData,
+    // a read whose value is thrown away, only its side effects count
+    Dummy,
+}
+
 pub trait Access {
     // read a single byte
-    fn read(&self, addr: u16) -> u8;
+    fn read(&mut self, addr: u16) -> u8;
+
+    // read a byte, telling why
+    fn read_for(&mut self, addr: u16, _kind: ReadKind) -> u8 {
+        self.read(addr)
+    }
 
     // write a single byte
     fn write(&mut self, addr: u16, value: u8);
 
+    // Advance the rest of the system by one CPU cycle, a cycle-accurate
+    // CPU does before each access. See `Cpu::set_cycle_accurate`.
+    fn tick(&mut self) {}
+
     // read 2 bytes starting from `addr`
-    fn read16(&self, addr: u16) -> u16 {
-        self.read(addr) as u16 | (self.read(addr + 1) as u16) << 8
+    fn read16(&mut self, addr: u16) -> u16 {
+        self.read(addr) as u16 | (self.read(addr.wrapping_add(1)) as u16) << 8
     }
 
     // read 2 bytes but with lower address wrapped around
     // http://nesdev.com/6502_cpu.txt
-    fn read16_wrapped(&self, addr: u16) -> u16 {
-        let wrapped = addr & 0xff00 | (addr + 1) & 0x00ff;
+    fn read16_wrapped(&mut self, addr: u16) -> u16 {
+        let wrapped = addr & 0xff00 | addr.wrapping_add(1) & 0x00ff;
         self.read(addr) as u16 | (self.read(wrapped) as u16) << 8
     }
 }
 
 // RAM
-struct Ram {
+pub struct Ram {
     data: [u8; 0x800]
 }
 
+impl Ram {
+    pub fn new() -> Self {
+        Ram { data: [0; 0x800] }
+    }
+}
+
+impl Default for Ram {
+    fn default() -> Self {
+        Ram::new()
+    }
+}
+
 impl Deref for Ram {
     type Target = [u8; 0x800];
 
@@ -39,8 +74,10 @@ impl DerefMut for Ram {
     }
 }
 
+impl_state!(Ram, data);
+
 impl Access for Ram {
-    fn read(&self, addr: u16) -> u8 {
+    fn read(&mut self, addr: u16) -> u8 {
         self[addr as usize & 0x07ff]
     }
 
@@ -48,36 +85,3 @@ impl Access for Ram {
         self[addr as usize & 0x07ff] = value;
     }
 }
-
-// main memory
-pub struct Memory {
-    ram: Ram,
-}
-
-impl Memory {
-    pub fn new() -> Self {
-        Memory {
-            ram: Ram { data: [0; 0x800] },
-        }
-    }
-}
-
-impl Access for Memory {
-    fn read(&self, addr: u16) -> u8 {
-        if addr < 0x2000 {
-            self.ram.read(addr)
-        }
-        else {
-            panic!("reading other memory sections is not implemented yet!");
-        }
-    }
-
-    fn write(&mut self, addr: u16, value: u8) {
-        if addr < 0x2000 {
-            self.ram.write(addr, value)
-        }
-        else {
-            panic!("writing other memory sections is not implemented yet!");
-        }
-    }
-}
@@ -0,0 +1,744 @@
+// Input movies: the buttons held on each frame, from power-on or from
+// a save state, replayed to reproduce a run exactly.
+//
+// Movies are read and written as FCEUX .fm2 files and BizHawk .bk2
+// archives. A CRC-32 of the RAM is kept every `hash_interval` frames
+// so that a replay going out of sync is caught where it happens. The
+// hashes, the ROM CRC, the devices plugged and the starting state go
+// in extra header keys the other emulators ignore. FM2 files also get
+// the MD5 of the ROM and a GUID, FCEUX checks the one and keeps the
+// other to tell movies apart.
+// http://fceux.com/web/help/fm2.html
+// http://tasvideos.org/Bizhawk/BK2Format.html
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use controller::Buttons;
+use crc32;
+use error::Error;
+use ines::Region;
+use input::Expansion;
+use md5::Md5;
+use nes::Nes;
+use zip::{ZipArchive, ZipWriter};
+use zip::write::FileOptions;
+
+pub const DEFAULT_HASH_INTERVAL: usize = 60;
+
+// FM2 pads are written as RLDUTSBA, BK2 ones as UDLRSsBA
+const FM2_BUTTONS: [(Buttons, char); 8] = [
+    (Buttons::RIGHT, 'R'), (Buttons::LEFT, 'L'), (Buttons::DOWN, 'D'), (Buttons::UP, 'U'),
+    (Buttons::START, 'T'), (Buttons::SELECT, 'S'), (Buttons::B, 'B'), (Buttons::A, 'A'),
+];
+const BK2_BUTTONS: [(Buttons, char); 8] = [
+    (Buttons::UP, 'U'), (Buttons::DOWN, 'D'), (Buttons::LEFT, 'L'), (Buttons::RIGHT, 'R'),
+    (Buttons::START, 'S'), (Buttons::SELECT, 's'), (Buttons::B, 'B'), (Buttons::A, 'A'),
+];
+const BK2_NAMES: [&str; 8] = ["Up", "Down", "Left", "Right", "Start", "Select", "B", "A"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Frame {
+    pub buttons: [Buttons; 4],
+    // pressed before the frame is run
+    pub reset: bool,
+    pub power: bool,
+}
+
+pub struct Movie {
+    pub rom_name: String,
+    // None for movies made elsewhere
+    pub rom_crc: Option<u32>,
+    // FCEUX's romChecksum, None if the movie has none
+    pub rom_md5: Option<[u8; 16]>,
+    // FCEUX's guid, made up when recording starts
+    pub guid: String,
+    // the save state the movie starts from, None for power-on
+    pub start: Option<Vec<u8>>,
+    // the devices plugged when recording started
+    pub expansion: Expansion,
+    // recorded on a PAL console
+    pub pal: bool,
+    pub rerecords: u32,
+    pub frames: Vec<Frame>,
+    pub hash_interval: usize,
+    // RAM CRC after every `hash_interval` frames
+    pub hashes: Vec<u32>,
+}
+
+fn ram_hash(nes: &Nes) -> u32 {
+    crc32::crc32(nes.bus().ram())
+}
+
+impl Frame {
+    // press the buttons and run the frame
+    pub fn run(&self, nes: &mut Nes) {
+        self.apply(nes);
+        nes.run_frame();
+    }
+
+    // press the buttons, for frames run another way, e.g. in a debugger
+    pub fn apply(&self, nes: &mut Nes) {
+        if self.power {
+            nes.power_cycle();
+        }
+        else if self.reset {
+            nes.reset();
+        }
+        for (player, &buttons) in self.buttons.iter().enumerate() {
+            nes.set_input(player, buttons);
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>, Error> {
+    let text = text.as_bytes();
+    if text.len() & 1 != 0 {
+        return Err(Error::new("odd number of hex digits".to_string()));
+    }
+    text.chunks(2).map(|pair| {
+        let pair = ::std::str::from_utf8(pair).unwrap_or("");
+        u8::from_str_radix(pair, 16).map_err(|_| Error::new(format!("bad hex byte {}", pair)))
+    }).collect()
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let mut group = [0; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let bits = (group[0] as u32) << 16 | (group[1] as u32) << 8 | group[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            }
+            else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn from_base64(text: &str) -> Result<Vec<u8>, Error> {
+    let bad = || Error::new(format!("bad base64 {}", text));
+    let digits = text.trim_end_matches('=').as_bytes();
+    if !text.len().is_multiple_of(4) || text.len() - digits.len() > 2 {
+        return Err(bad());
+    }
+    let mut out = Vec::new();
+    for chunk in digits.chunks(4) {
+        let mut bits = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = BASE64.iter().position(|&b| b == c).ok_or_else(bad)?;
+            bits |= (value as u32) << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(out)
+}
+
+// 8-4-4-4-12 hex digits as FCEUX writes them, from the time and the
+// process so that two movies do not get the same
+fn new_guid() -> String {
+    let mut md5 = Md5::new();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    md5.update(&now.as_nanos().to_le_bytes());
+    md5.update(&process::id().to_le_bytes());
+    let digest = md5.finish();
+    let parts: Vec<String> = [0..4, 4..6, 6..8, 8..10, 10..16].iter()
+        .map(|range| to_hex(&digest[range.clone()]).to_uppercase())
+        .collect();
+    parts.join("-")
+}
+
+fn parse_buttons(pad: &str, order: &[(Buttons, char); 8]) -> Buttons {
+    let mut buttons = Buttons::empty();
+    for (&(b, _), c) in order.iter().zip(pad.chars()) {
+        // anything but a dot or a space is pressed
+        if c != '.' && c != ' ' {
+            buttons |= b;
+        }
+    }
+    buttons
+}
+
+fn format_buttons(buttons: Buttons, order: &[(Buttons, char); 8]) -> String {
+    order.iter().map(|&(b, c)| if buttons.contains(b) { c } else { '.' }).collect()
+}
+
+impl Movie {
+    // Start recording. Without `from_state` the console is power cycled
+    // so that the movie starts from power-on. The battery memory is
+    // cleared too, as FCEUX does, the movie would not replay otherwise.
+    pub fn record(nes: &mut Nes, rom_name: &str, from_state: bool) -> Self {
+        let start = if from_state {
+            Some(nes.save_state())
+        }
+        else {
+            nes.clear_battery();
+            nes.power_cycle();
+            None
+        };
+        Movie {
+            rom_name: rom_name.to_string(),
+            rom_crc: Some(nes.rom_crc()),
+            rom_md5: Some(nes.rom_md5()),
+            guid: new_guid(),
+            start,
+            expansion: nes.expansion(),
+            pal: nes.region() == Region::Pal,
+            rerecords: 0,
+            frames: Vec::new(),
+            hash_interval: DEFAULT_HASH_INTERVAL,
+            hashes: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // run a frame with the given input and add it to the movie
+    pub fn record_frame(&mut self, nes: &mut Nes, frame: Frame) {
+        frame.run(nes);
+        self.frames.push(frame);
+        if self.frames.len().is_multiple_of(self.hash_interval) {
+            self.hashes.push(ram_hash(nes));
+        }
+    }
+
+    // Put the console where the movie starts, with the controllers it
+    // was recorded with.
+    pub fn rewind(&self, nes: &mut Nes) -> Result<(), Error> {
+        if let Some(crc) = self.rom_crc {
+            if crc != nes.rom_crc() {
+                return Err(Error::new(format!("movie is for ROM {:08x}, not {:08x}",
+                                              crc, nes.rom_crc())));
+            }
+        }
+        nes.set_expansion(self.expansion);
+        match self.start {
+            Some(ref state) => nes.load_state(state),
+            None => {
+                nes.clear_battery();
+                nes.power_cycle();
+                Ok(())
+            }
+        }
+    }
+
+    // Run frame `index` of the movie, checking the RAM against the
+    // hash recorded after it, if any.
+    pub fn play_frame(&self, nes: &mut Nes, index: usize) -> Result<(), Error> {
+        self.frames[index].run(nes);
+        let count = index + 1;
+        if count.is_multiple_of(self.hash_interval) {
+            if let Some(&hash) = self.hashes.get(count / self.hash_interval - 1) {
+                let actual = ram_hash(nes);
+                if actual != hash {
+                    return Err(Error::new(format!("desync at frame {}: RAM hash {:08x}, recorded {:08x}",
+                                                  index, actual, hash)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // replay the whole movie, e.g. to check that it still syncs
+    pub fn play(&self, nes: &mut Nes) -> Result<(), Error> {
+        self.rewind(nes)?;
+        for index in 0..self.frames.len() {
+            self.play_frame(nes, index)?;
+        }
+        Ok(())
+    }
+
+    // .fm2 or .bk2, by the extension
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if is_bk2(path) {
+            Movie::from_bk2(File::open(path)?)
+        }
+        else {
+            let mut text = String::new();
+            File::open(path)?.read_to_string(&mut text)?;
+            Movie::from_fm2(&text)
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        if is_bk2(path) {
+            self.write_bk2(File::create(path)?)
+        }
+        else {
+            File::create(path)?.write_all(self.to_fm2().as_bytes())?;
+            Ok(())
+        }
+    }
+
+    fn empty() -> Self {
+        Movie {
+            rom_name: String::new(),
+            rom_crc: None,
+            rom_md5: None,
+            guid: new_guid(),
+            start: None,
+            expansion: Expansion::Standard,
+            pal: false,
+            rerecords: 0,
+            frames: Vec::new(),
+            hash_interval: DEFAULT_HASH_INTERVAL,
+            hashes: Vec::new(),
+        }
+    }
+
+    // the buttons of players 3 and 4 are only kept with four pads
+    fn pads(&self) -> usize {
+        match self.expansion {
+            Expansion::FourScore | Expansion::FamicomFourPlayer => 4,
+            _ => 2,
+        }
+    }
+
+    // header keys of our own, shared by both formats
+    fn write_extra_keys(&self, out: &mut String) {
+        if let Some(crc) = self.rom_crc {
+            out.push_str(&format!("redwhiteCrc {:08x}\n", crc));
+        }
+        out.push_str(&format!("redwhiteExpansion {}\n", self.expansion.name()));
+        out.push_str(&format!("redwhiteHashInterval {}\n", self.hash_interval));
+        if !self.hashes.is_empty() {
+            let hashes: Vec<String> = self.hashes.iter().map(|h| format!("{:08x}", h)).collect();
+            out.push_str(&format!("redwhiteHashes {}\n", hashes.join(",")));
+        }
+        if let Some(ref state) = self.start {
+            out.push_str(&format!("redwhiteState {}\n", to_hex(state)));
+        }
+    }
+
+    // returns false if the key is not one of ours
+    fn read_extra_key(&mut self, key: &str, value: &str) -> Result<bool, Error> {
+        let bad = || Error::new(format!("bad value for {}: {}", key, value));
+        match key {
+            "redwhiteCrc" => self.rom_crc = Some(u32::from_str_radix(value, 16).map_err(|_| bad())?),
+            "redwhiteExpansion" => self.expansion = Expansion::from_name(value).ok_or_else(bad)?,
+            "redwhiteHashInterval" => {
+                self.hash_interval = value.parse().map_err(|_| bad())?;
+                if self.hash_interval == 0 {
+                    return Err(bad());
+                }
+            }
+            "redwhiteHashes" => {
+                self.hashes = value.split(',')
+                                   .map(|h| u32::from_str_radix(h, 16))
+                                   .collect::<Result<_, _>>()
+                                   .map_err(|_| bad())?;
+            }
+            "redwhiteState" => self.start = Some(from_hex(value)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    pub fn from_fm2(text: &str) -> Result<Self, Error> {
+        let mut movie = Movie::empty();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            if line.starts_with('|') {
+                movie.frames.push(movie.parse_fm2_frame(line)
+                                       .ok_or_else(|| Error::new(format!("line {}: bad input", n + 1)))?);
+                continue;
+            }
+            let mut parts = line.splitn(2, ' ');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("").trim();
+            match key {
+                "romFilename" => movie.rom_name = value.to_string(),
+                "romChecksum" => {
+                    let bad = || Error::new(format!("bad value for romChecksum: {}", value));
+                    let digest = from_base64(value.strip_prefix("base64:").ok_or_else(bad)?)?;
+                    if digest.len() != 16 {
+                        return Err(bad());
+                    }
+                    let mut md5 = [0; 16];
+                    md5.copy_from_slice(&digest);
+                    movie.rom_md5 = Some(md5);
+                }
+                "guid" => movie.guid = value.to_string(),
+                "rerecordCount" => movie.rerecords = value.parse().unwrap_or(0),
+                "fourscore" if value == "1" && movie.pads() == 2 => movie.expansion = Expansion::FourScore,
+                "palFlag" => movie.pal = value == "1",
+                "savestate" => {
+                    return Err(Error::new("FCEUX movies starting from a save state are not supported"
+                                          .to_string()));
+                }
+                _ => {
+                    movie.read_extra_key(key, value)?;
+                }
+            }
+        }
+        Ok(movie)
+    }
+
+    // |commands|pad|pad||, or four pads with the Four Score
+    fn parse_fm2_frame(&self, line: &str) -> Option<Frame> {
+        let fields: Vec<&str> = line.split('|').collect();
+        let pads = self.pads();
+        if fields.len() < pads + 2 {
+            return None;
+        }
+        let commands: u8 = fields[1].trim().parse().ok()?;
+        let mut frame = Frame {
+            reset: commands & 0x01 != 0,
+            power: commands & 0x02 != 0,
+            ..Default::default()
+        };
+        for (player, pad) in fields[2..pads + 2].iter().enumerate() {
+            frame.buttons[player] = parse_buttons(pad, &FM2_BUTTONS);
+        }
+        Some(frame)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut out = String::new();
+        out.push_str("version 3\n");
+        out.push_str("emuVersion 22020\n");
+        out.push_str(&format!("rerecordCount {}\n", self.rerecords));
+        out.push_str(&format!("palFlag {}\n", self.pal as u8));
+        out.push_str(&format!("romFilename {}\n", self.rom_name));
+        if let Some(md5) = self.rom_md5 {
+            out.push_str(&format!("romChecksum base64:{}\n", to_base64(&md5)));
+        }
+        out.push_str(&format!("guid {}\n", self.guid));
+        let four_players = self.pads() == 4;
+        out.push_str(&format!("fourscore {}\n", four_players as u8));
+        out.push_str("microphone 0\n");
+        if four_players {
+            out.push_str("port0 0\nport1 0\n");
+        }
+        else {
+            out.push_str("port0 1\nport1 1\n");
+        }
+        out.push_str("port2 0\n");
+        self.write_extra_keys(&mut out);
+
+        let pads = self.pads();
+        for frame in self.frames.iter() {
+            let commands = frame.reset as u8 | (frame.power as u8) << 1;
+            out.push_str(&format!("|{}|", commands));
+            for &buttons in frame.buttons[..pads].iter() {
+                out.push_str(&format_buttons(buttons, &FM2_BUTTONS));
+                out.push('|');
+            }
+            // the expansion port
+            out.push_str("|\n");
+        }
+        out
+    }
+
+    // A zip archive holding "Header.txt" and "Input Log.txt".
+    pub fn from_bk2<R: Read + ::std::io::Seek>(reader: R) -> Result<Self, Error> {
+        let mut archive = ZipArchive::new(reader)?;
+        let mut movie = Movie::empty();
+
+        let mut header = String::new();
+        archive.by_name("Header.txt")?.read_to_string(&mut header)?;
+        for line in header.lines() {
+            let mut parts = line.splitn(2, ' ');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("").trim();
+            match key {
+                "GameName" => movie.rom_name = value.to_string(),
+                "rerecordCount" => movie.rerecords = value.parse().unwrap_or(0),
+                "StartsFromSavestate" if value == "True" && !header.contains("redwhiteState") => {
+                    return Err(Error::new("BizHawk movies starting from a save state are not supported"
+                                          .to_string()));
+                }
+                _ => {
+                    movie.read_extra_key(key, value)?;
+                }
+            }
+        }
+
+        let mut log = String::new();
+        archive.by_name("Input Log.txt")?.read_to_string(&mut log)?;
+        // what each flag in a line stands for, from the log key
+        let mut columns = Vec::new();
+        for line in log.lines() {
+            if let Some(key) = line.strip_prefix("LogKey:") {
+                columns = parse_log_key(key);
+                let four_players = columns.iter().any(|c| match *c {
+                    Column::Pad(player, _) => player >= 2,
+                    _ => false,
+                });
+                if four_players && movie.pads() == 2 {
+                    movie.expansion = Expansion::FourScore;
+                }
+            }
+            else if line.starts_with('|') {
+                let mut frame = Frame::default();
+                let flags = line.chars().filter(|&c| c != '|');
+                for (column, c) in columns.iter().zip(flags) {
+                    if c == '.' || c == ' ' {
+                        continue;
+                    }
+                    match *column {
+                        Column::Pad(player, b) => frame.buttons[player] |= b,
+                        Column::Reset => frame.reset = true,
+                        Column::Power => frame.power = true,
+                        Column::Other => {}
+                    }
+                }
+                movie.frames.push(frame);
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn write_bk2<W: Write + ::std::io::Seek>(&self, writer: W) -> Result<(), Error> {
+        let mut zip = ZipWriter::new(writer);
+        let options = FileOptions::default();
+
+        let mut header = String::new();
+        header.push_str("MovieVersion BizHawk v2.0.0\n");
+        header.push_str("Platform NES\n");
+        header.push_str("Core NesHawk\n");
+        header.push_str(&format!("GameName {}\n", self.rom_name));
+        header.push_str(&format!("rerecordCount {}\n", self.rerecords));
+        header.push_str(&format!("StartsFromSavestate {}\n",
+                                 if self.start.is_some() { "True" } else { "False" }));
+        self.write_extra_keys(&mut header);
+        zip.start_file("Header.txt", options)?;
+        zip.write_all(header.as_bytes())?;
+
+        let pads = self.pads();
+        let mut log = String::new();
+        log.push_str("[Input]\nLogKey:#Reset|Power|");
+        for player in 0..pads {
+            log.push('#');
+            for name in BK2_NAMES.iter() {
+                log.push_str(&format!("P{} {}|", player + 1, name));
+            }
+        }
+        log.push('\n');
+        for frame in self.frames.iter() {
+            log.push('|');
+            log.push(if frame.reset { 'r' } else { '.' });
+            log.push(if frame.power { 'P' } else { '.' });
+            log.push('|');
+            for &buttons in frame.buttons[..pads].iter() {
+                log.push_str(&format_buttons(buttons, &BK2_BUTTONS));
+                log.push('|');
+            }
+            log.push('\n');
+        }
+        log.push_str("[/Input]\n");
+        zip.start_file("Input Log.txt", options)?;
+        zip.write_all(log.as_bytes())?;
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+fn is_bk2(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("bk2"))
+}
+
+enum Column {
+    Pad(usize, Buttons),
+    Reset,
+    Power,
+    // buttons of other devices
+    Other,
+}
+
+// "#Reset|Power|#P1 Up|P1 Down|..." to what each column of the log is
+fn parse_log_key(key: &str) -> Vec<Column> {
+    key.split('|')
+       .map(|name| name.trim_start_matches('#'))
+       .filter(|name| !name.is_empty())
+       .map(|name| {
+           let bytes = name.as_bytes();
+           if bytes.len() > 3 && bytes[0] == b'P' && bytes[1].is_ascii_digit() && bytes[2] == b' ' {
+               let player = (bytes[1] - b'0') as usize;
+               match BK2_NAMES.iter().position(|&n| n == &name[3..]) {
+                   Some(i) if (1..=4).contains(&player) => Column::Pad(player - 1, BK2_BUTTONS[i].0),
+                   _ => Column::Other,
+               }
+           }
+           else if name == "Power" {
+               Column::Power
+           }
+           else if name == "Reset" {
+               Column::Reset
+           }
+           else {
+               Column::Other
+           }
+       })
+       .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use ines::Ines;
+    use md5;
+    use input::Zapper;
+    use nes::{nes_running, rom_running};
+
+    const EXPANSIONS: [Expansion; 4] = [
+        Expansion::Standard, Expansion::FourScore, Expansion::FamicomFourPlayer, Expansion::Zapper,
+    ];
+
+    fn sample(expansion: Expansion) -> Movie {
+        let mut movie = Movie::empty();
+        movie.rom_name = "game.nes".to_string();
+        movie.rom_crc = Some(0x1234abcd);
+        movie.rom_md5 = Some(md5::md5(b"game"));
+        movie.expansion = expansion;
+        let four_score = movie.pads() == 4;
+        movie.pal = true;
+        movie.rerecords = 42;
+        movie.hash_interval = 2;
+        movie.hashes = vec![0xdeadbeef, 0x01020304];
+        movie.start = Some(vec![0x00, 0x7f, 0xff]);
+        let mut frame = Frame::default();
+        frame.buttons[0] = Buttons::A | Buttons::RIGHT;
+        frame.buttons[1] = Buttons::START;
+        if four_score {
+            frame.buttons[3] = Buttons::B | Buttons::UP;
+        }
+        movie.frames.push(Frame { power: true, ..Default::default() });
+        movie.frames.push(frame);
+        let extra = if four_score { Buttons::SELECT } else { Buttons::empty() };
+        let buttons = [Buttons::SELECT, Buttons::SELECT, extra, extra];
+        movie.frames.push(Frame { reset: true, buttons, ..Default::default() });
+        movie
+    }
+
+    fn assert_same(a: &Movie, b: &Movie) {
+        assert_eq!(a.rom_name, b.rom_name);
+        assert_eq!(a.rom_crc, b.rom_crc);
+        assert_eq!(a.start, b.start);
+        assert_eq!(a.expansion, b.expansion);
+        assert_eq!(a.rerecords, b.rerecords);
+        assert_eq!(a.frames, b.frames);
+        assert_eq!(a.hash_interval, b.hash_interval);
+        assert_eq!(a.hashes, b.hashes);
+    }
+
+    #[test]
+    fn fm2_round_trip() {
+        for &expansion in EXPANSIONS.iter() {
+            let movie = sample(expansion);
+            let read = Movie::from_fm2(&movie.to_fm2()).unwrap();
+            assert_same(&movie, &read);
+            assert!(read.pal);
+            assert_eq!(read.rom_md5, movie.rom_md5);
+            assert_eq!(read.guid, movie.guid);
+        }
+    }
+
+    #[test]
+    fn bk2_round_trip() {
+        for &expansion in EXPANSIONS.iter() {
+            let movie = sample(expansion);
+            let mut zip = Cursor::new(Vec::new());
+            movie.write_bk2(&mut zip).unwrap();
+            zip.set_position(0);
+            assert_same(&movie, &Movie::from_bk2(zip).unwrap());
+        }
+    }
+
+    #[test]
+    fn fm2_from_fceux() {
+        let text = "version 3\nromFilename smb\nromChecksum base64:1B2M2Y8AsgTpgAmY7PhCfg==\n\
+                    guid 452DE2C3-EF43-2FA9-77AC-0677FC51543B\npalFlag 0\nfourscore 0\n\
+                    |0|R......A|........||\n|1|........|....T...||\n";
+        let movie = Movie::from_fm2(text).unwrap();
+        assert_eq!(movie.rom_name, "smb");
+        assert_eq!(movie.rom_crc, None);
+        assert_eq!(movie.rom_md5, Some(md5::md5(b"")));
+        assert_eq!(movie.guid, "452DE2C3-EF43-2FA9-77AC-0677FC51543B");
+        assert!(!movie.pal);
+        assert_eq!(movie.frames[0].buttons[0], Buttons::RIGHT | Buttons::A);
+        assert!(movie.frames[1].reset);
+        assert_eq!(movie.frames[1].buttons[1], Buttons::START);
+        assert_eq!(movie.expansion, Expansion::Standard);
+
+        let movie = Movie::from_fm2("fourscore 1\n|0|........|........|........|.......A||\n").unwrap();
+        assert_eq!(movie.expansion, Expansion::FourScore);
+        assert_eq!(movie.frames[0].buttons[3], Buttons::A);
+
+        for checksum in ["1B2M2Y8AsgTpgAmY7PhCfg", "base64:1B2M2Y8AsgTpgAmY7PhCf=", "base64:AAAA"].iter() {
+            assert!(Movie::from_fm2(&format!("romChecksum {}\n", checksum)).is_err(), "{}", checksum);
+        }
+    }
+
+    #[test]
+    fn recording_writes_the_checksum_and_a_guid() {
+        let mut nes = nes_running(&[0x4c, 0x00, 0xc0]);
+        let movie = Movie::record(&mut nes, "loop", false);
+        let text = movie.to_fm2();
+        let checksum = format!("romChecksum base64:{}\n", to_base64(&nes.rom_md5()));
+        assert!(text.contains(&checksum), "{}", text);
+        let guid = text.lines().find_map(|line| line.strip_prefix("guid ")).unwrap();
+        let lengths: Vec<usize> = guid.split('-').map(|part| part.len()).collect();
+        assert_eq!(lengths, [8, 4, 4, 4, 12]);
+        assert!(guid.chars().all(|c| c == '-' || c.is_ascii_hexdigit() && !c.is_ascii_lowercase()));
+        assert_ne!(Movie::record(&mut nes, "loop", false).guid, guid);
+    }
+
+    #[test]
+    fn base64() {
+        for &(bytes, text) in [(&b""[..], ""), (b"f", "Zg=="), (b"fo", "Zm8="), (b"foo", "Zm9v"),
+                               (b"foob", "Zm9vYg=="), (b"fooba", "Zm9vYmE="), (b"foobar", "Zm9vYmFy")].iter() {
+            assert_eq!(to_base64(bytes), text);
+            assert_eq!(from_base64(text).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn rewind_plugs_the_recorded_devices() {
+        let mut nes = nes_running(&[0x4c, 0x00, 0xc0]);
+        nes.set_expansion(Expansion::Zapper);
+        let mut movie = Movie::record(&mut nes, "loop", false);
+        // nothing tells from the input
+        movie.record_frame(&mut nes, Frame::default());
+        nes.set_expansion(Expansion::FourScore);
+        movie.rewind(&mut nes).unwrap();
+        assert_eq!(nes.expansion(), Expansion::Zapper);
+        assert!(nes.device_mut::<Zapper>(1).is_some());
+    }
+
+    #[test]
+    fn movies_start_from_a_blank_battery() {
+        let mut rom = rom_running(&[0x4c, 0x00, 0xc0]);
+        rom[6] |= 0x02;
+        let mut nes = Nes::new(Ines::from_bytes(&rom).unwrap()).unwrap();
+        nes.load_battery(&[0x55; 0x2000]).unwrap();
+        let movie = Movie::record(&mut nes, "loop", false);
+        assert!(nes.battery().unwrap().iter().all(|&b| b == 0));
+        nes.load_battery(&[0x55; 0x2000]).unwrap();
+        movie.rewind(&mut nes).unwrap();
+        assert!(nes.battery().unwrap().iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,363 @@
+// The whole console, with no frontend attached. A frontend, a bot or
+// a test drives it one frame at a time and reads back the picture
+// and the sound produced during that frame.
+
+use std::path::Path;
+use bus::Bus;
+use cartridge::Cartridge;
+use cdl::Cdl;
+use controller::Buttons;
+use cpu::Cpu;
+use error::Error;
+use ines::{Ines, Region};
+use input::{Expansion, InputDevice};
+use mem::Access;
+use savestate::{self, State, StateReader, StateWriter};
+
+pub struct Nes {
+    cpu: Cpu<Bus>,
+    crc: u32,
+    md5: [u8; 16],
+    // what `set_expansion` last plugged
+    expansion: Expansion,
+}
+
+impl Nes {
+    pub fn new(rom: Ines) -> Result<Self, Error> {
+        let (crc, md5) = (rom.crc32(), rom.md5());
+        let region = rom.header.region();
+        let expansion = Expansion::from_nes2(rom.header.expansion_device());
+        let cartridge = Cartridge::new(rom)?;
+        let mut cpu = Cpu::new(Bus::new(cartridge, region));
+        cpu.power_on();
+        let mut nes = Nes { cpu, crc, md5, expansion: Expansion::Standard };
+        if let Some(expansion) = expansion {
+            nes.set_expansion(expansion);
+        }
+        Ok(nes)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Nes::new(Ines::from_file(path)?)
+    }
+
+    // CRC-32 of the ROM, see `Ines::crc32`
+    pub fn rom_crc(&self) -> u32 {
+        self.crc
+    }
+
+    // see `Ines::md5`
+    pub fn rom_md5(&self) -> [u8; 16] {
+        self.md5
+    }
+
+    pub fn region(&self) -> Region {
+        self.cpu.mem().region()
+    }
+
+    pub fn cpu(&self) -> &Cpu<Bus> {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu<Bus> {
+        &mut self.cpu
+    }
+
+    pub fn bus(&self) -> &Bus {
+        self.cpu.mem()
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        self.cpu.mem_mut()
+    }
+
+    // see `Cpu::set_cycle_accurate`
+    pub fn set_cycle_accurate(&mut self, on: bool) {
+        self.cpu.set_cycle_accurate(on);
+    }
+
+    // read memory as the CPU sees it, see `Bus::peek`
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.cpu.mem().peek(addr)
+    }
+
+    // Execute one CPU instruction and let the other chips catch up.
+    // Returns true if a frame was completed.
+    pub fn step(&mut self) -> bool {
+        if self.cpu.mem().io_log.is_some() {
+            let (pc, cycle) = (self.cpu.registers().pc, self.cpu.cycles());
+            if let Some(ref mut log) = self.cpu.mem_mut().io_log {
+                log.start_instruction(pc, cycle);
+            }
+        }
+        let mut cycles = self.cpu.step();
+        // a cycle-accurate CPU has ticked the bus as it went
+        if self.cpu.cycle_accurate() {
+            cycles = 0;
+        }
+        let cycle = self.cpu.cycles();
+        let stall = self.cpu.mem_mut().take_dma_stall(cycle);
+        self.cpu.stall(stall);
+        cycles += stall;
+
+        let (nmi, irq, frame) = {
+            let bus = self.cpu.mem_mut();
+            for _ in 0..cycles {
+                bus.tick();
+            }
+            let irq = bus.apu.irq() || bus.cartridge.irq();
+            (bus.ppu.take_nmi(), irq, bus.ppu.take_frame())
+        };
+        if nmi {
+            self.cpu.trigger_nmi();
+        }
+        self.cpu.set_irq(irq);
+        frame
+    }
+
+    // Run until the PPU enters vertical blank.
+    pub fn run_frame(&mut self) {
+        self.cpu.mem_mut().apu.clear_samples();
+        while !self.step() {}
+        self.cpu.mem_mut().apu.end_frame();
+    }
+
+    // 256x240 palette indices of the last frame, see `palette`
+    pub fn framebuffer(&self) -> &[u8] {
+        self.cpu.mem().ppu.framebuffer()
+    }
+
+    // mono samples produced during the last frame
+    pub fn audio_samples(&self) -> &[i16] {
+        self.cpu.mem().apu.samples()
+    }
+
+    // the same samples, from -1.0 to 1.0
+    pub fn audio_samples_f32(&self) -> &[f32] {
+        self.cpu.mem().apu.samples_f32()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.cpu.mem().apu.sample_rate()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.cpu.mem_mut().apu.set_sample_rate(sample_rate);
+    }
+
+    // Buttons held by a player for the next frame. Players 0 and 1
+    // use ports 1 and 2, players 2 and 3 need a four player adapter.
+    pub fn set_input(&mut self, player: usize, buttons: Buttons) {
+        self.cpu.mem_mut().ports[player % 2].set_buttons(player / 2, buttons);
+    }
+
+    // plug other devices into the controller ports
+    pub fn set_expansion(&mut self, expansion: Expansion) {
+        self.cpu.mem_mut().ports = expansion.devices();
+        self.expansion = expansion;
+    }
+
+    // the devices plugged by `set_expansion`, `set_port` aside
+    pub fn expansion(&self) -> Expansion {
+        self.expansion
+    }
+
+    // plug a device into port 0 or 1
+    pub fn set_port(&mut self, port: usize, device: Box<dyn InputDevice>) -> Result<(), Error> {
+        match self.cpu.mem_mut().ports.get_mut(port) {
+            Some(slot) => {
+                *slot = device;
+                Ok(())
+            }
+            None => Err(Error::new(format!("no controller port {}", port))),
+        }
+    }
+
+    // the device in a port, if there is such a port and the device is a
+    // `T`, e.g. a `Zapper`
+    pub fn device_mut<T: InputDevice + 'static>(&mut self, port: usize) -> Option<&mut T> {
+        self.cpu.mem_mut().ports.get_mut(port)?.as_any_mut().downcast_mut::<T>()
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.mem_mut().reset();
+        self.cpu.reset();
+    }
+
+    pub fn power_cycle(&mut self) {
+        self.cpu.mem_mut().power_cycle();
+        self.cpu.power_on();
+    }
+
+    // PRG RAM or EEPROM kept by the battery, None if the cartridge
+    // has no battery. Frontends save it on exit.
+    pub fn battery(&self) -> Option<&[u8]> {
+        self.cpu.mem().cartridge.battery()
+    }
+
+    pub fn load_battery(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.cpu.mem_mut().cartridge.load_battery(data)
+    }
+
+    // blank the battery memory, as for a new cartridge
+    pub fn clear_battery(&mut self) {
+        self.cpu.mem_mut().cartridge.clear_battery()
+    }
+
+    // an empty code/data log for the ROM, see `set_cdl`
+    pub fn new_cdl(&self) -> Cdl {
+        self.cpu.mem().cartridge.new_cdl()
+    }
+
+    // Log code and data on top of `cdl`, e.g. the log of an earlier
+    // session, or stop logging with None.
+    pub fn set_cdl(&mut self, cdl: Option<Cdl>) -> Result<(), Error> {
+        self.cpu.mem_mut().cartridge.set_cdl(cdl)
+    }
+
+    pub fn cdl(&self) -> Option<&Cdl> {
+        self.cpu.mem().cartridge.cdl()
+    }
+
+    // Serialize the whole machine, see `savestate`.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.write_bytes(savestate::MAGIC);
+        savestate::VERSION.save(&mut w);
+        self.crc.save(&mut w);
+        self.cpu.save(&mut w);
+        w.into_bytes()
+    }
+
+    // Restore a state made by `save_state`. On error the machine is
+    // left as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut r = StateReader::new(data);
+        if r.read_bytes(savestate::MAGIC.len()).ok() != Some(&savestate::MAGIC[..]) {
+            return Err(Error::new("not a save state".to_string()));
+        }
+        let (mut version, mut crc) = (0u16, 0u32);
+        version.load(&mut r)?;
+        if version != savestate::VERSION {
+            return Err(Error::new(format!("save state version {} is not supported", version)));
+        }
+        crc.load(&mut r)?;
+        if crc != self.crc {
+            return Err(Error::new(format!("save state is for ROM {:08x}, not {:08x}", crc, self.crc)));
+        }
+
+        let mut backup = StateWriter::new();
+        self.cpu.save(&mut backup);
+        let backup = backup.into_bytes();
+        let result = self.cpu.load(&mut r).and_then(|_| {
+            if r.is_empty() {
+                Ok(())
+            }
+            else {
+                Err(Error::new("trailing data in save state".to_string()))
+            }
+        });
+        if result.is_err() {
+            self.cpu.load(&mut StateReader::new(&backup))
+                .expect("restoring the state before loading");
+        }
+        result
+    }
+}
+
+// an NROM image running `code` at $c000, for tests
+#[cfg(test)]
+pub(crate) fn rom_running(code: &[u8]) -> Vec<u8> {
+    let mut rom = vec![b'N', b'E', b'S', 0x1a, 1, 1];
+    rom.resize(16, 0);
+    let mut prg = vec![0; 0x4000];
+    prg[..code.len()].copy_from_slice(code);
+    prg[0x3ffc] = 0x00;
+    prg[0x3ffd] = 0xc0;
+    rom.extend(prg);
+    rom.resize(16 + 0x4000 + 0x2000, 0);
+    rom
+}
+
+#[cfg(test)]
+pub(crate) fn nes_running(code: &[u8]) -> Nes {
+    Nes::new(Ines::from_bytes(&rom_running(code)).unwrap()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // inc $10, jmp $c000
+    const COUNTING: &[u8] = &[0xe6, 0x10, 0x4c, 0x00, 0xc0];
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut nes = nes_running(COUNTING);
+        nes.run_frame();
+        let state = nes.save_state();
+
+        let mut other = nes_running(COUNTING);
+        other.load_state(&state).unwrap();
+        assert_eq!(other.save_state(), state);
+        // and both run the same from there
+        nes.run_frame();
+        other.run_frame();
+        assert_eq!(other.save_state(), nes.save_state());
+    }
+
+    #[test]
+    fn bad_save_states_are_rejected() {
+        let mut nes = nes_running(COUNTING);
+        nes.run_frame();
+        let state = nes.save_state();
+        nes.run_frame();
+        let before = nes.save_state();
+        assert_ne!(state, before);
+
+        let mut magic = state.clone();
+        magic[0] ^= 0xff;
+        // the version, then the CRC of the ROM
+        let mut version = state.clone();
+        version[4] ^= 0xff;
+        let mut crc = state.clone();
+        crc[6] ^= 0xff;
+        let mut trailing = state.clone();
+        trailing.push(0);
+        // cut in the middle of the machine, part of it is loaded
+        let truncated = &state[..state.len() - 1];
+        let other_rom = nes_running(&[0x4c, 0x00, 0xc0]).save_state();
+
+        for (data, error) in &[(&magic[..], "not a save state"),
+                               (&version[..], "version"),
+                               (&crc[..], "is for ROM"),
+                               (&trailing[..], "trailing data"),
+                               (truncated, "truncated"),
+                               (&other_rom[..], "is for ROM")] {
+            let e = nes.load_state(data).unwrap_err();
+            assert!(e.to_string().contains(error), "{}", e);
+            // the machine is left as it was
+            assert_eq!(nes.save_state(), before);
+        }
+    }
+
+    #[test]
+    fn power_cycle_keeps_the_battery() {
+        // lda #$55, sta $6000
+        let code = [0xa9, 0x55, 0x8d, 0x00, 0x60];
+        let mut rom = rom_running(&code);
+        rom[6] |= 0x02;
+        let mut battery = Nes::new(Ines::from_bytes(&rom).unwrap()).unwrap();
+        let mut nes = nes_running(&code);
+        for nes in [&mut nes, &mut battery].iter_mut() {
+            nes.step();
+            nes.step();
+            assert_eq!(nes.peek(0x6000), 0x55);
+            nes.power_cycle();
+        }
+        assert_eq!(nes.peek(0x6000), 0x00);
+        assert_eq!(battery.peek(0x6000), 0x55);
+        battery.clear_battery();
+        assert_eq!(battery.peek(0x6000), 0x00);
+    }
+}
@@ -0,0 +1,633 @@
+// The 256 opcodes of the 6502: what they do, how they address their
+// operand and how long they take. The CPU executes instructions from
+// this table and the disassembler prints them. The 65C02 has a table
+// of its own.
+// http://www.oxyron.de/html/opcodes02.html
+// https://wiki.nesdev.com/w/index.php/CPU_unofficial_opcodes
+// http://www.6502.org/tutorials/65c02opcodes.html
+
+use self::Instruction::*;
+use self::Mode::*;
+
+macro_rules! instructions {
+    ($($name:ident $mnemonic:expr,)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Instruction {
+            $($name,)*
+        }
+
+        impl Instruction {
+            pub fn mnemonic(self) -> &'static str {
+                match self {
+                    $(Instruction::$name => $mnemonic,)*
+                }
+            }
+        }
+    }
+}
+
+instructions! {
+    Adc "adc", Ahx "ahx", Alr "alr", Anc "anc", And "and", Arr "arr",
+    Asl "asl", Axs "axs", Bcc "bcc", Bcs "bcs", Beq "beq", Bit "bit",
+    Bmi "bmi", Bne "bne", Bpl "bpl", Bra "bra", Brk "brk", Bvc "bvc",
+    Bvs "bvs", Clc "clc", Cld "cld", Cli "cli", Clv "clv", Cmp "cmp",
+    Cpx "cpx", Cpy "cpy", Dcp "dcp", Dec "dec", Dex "dex", Dey "dey",
+    Eor "eor", Inc "inc", Inx "inx", Iny "iny", Isc "isc", Jmp "jmp",
+    Jsr "jsr", Kil "kil", Las "las", Lax "lax", Lda "lda", Ldx "ldx",
+    Ldy "ldy", Lsr "lsr", Nop "nop", Ora "ora", Pha "pha", Php "php",
+    Phx "phx", Phy "phy", Pla "pla", Plp "plp", Plx "plx", Ply "ply",
+    Rla "rla", Rol "rol", Ror "ror", Rra "rra", Rti "rti", Rts "rts",
+    Sax "sax", Sbc "sbc", Sec "sec", Sed "sed", Sei "sei", Shx "shx",
+    Shy "shy", Slo "slo", Sre "sre", Sta "sta", Stx "stx", Sty "sty",
+    Stz "stz", Tas "tas", Tax "tax", Tay "tay", Trb "trb", Tsb "tsb",
+    Tsx "tsx", Txa "txa", Txs "txs", Tya "tya", Xaa "xaa",
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    // ($nn,x)
+    IndirectX,
+    // ($nn),y
+    IndirectY,
+    Relative,
+    // ($nn), 65C02 only
+    ZeroPageIndirect,
+    // ($nnnn,x), JMP on the 65C02
+    AbsoluteIndirectX,
+}
+
+impl Mode {
+    // bytes of operand after the opcode
+    pub fn operand_len(self) -> usize {
+        match self {
+            Implied | Accumulator => 0,
+            Immediate | ZeroPage | ZeroPageX | ZeroPageY | IndirectX | IndirectY | Relative |
+            ZeroPageIndirect => 1,
+            Absolute | AbsoluteX | AbsoluteY | Indirect | AbsoluteIndirectX => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opcode {
+    pub instruction: Instruction,
+    pub mode: Mode,
+    // cycles taken, not counting taken branches
+    pub cycles: u8,
+    // extra cycles when indexing crosses a page
+    pub xpage_cycles: u8,
+    // The CPU executes unofficial opcodes all the same. The disassembler
+    // writes them as bytes and readnesrom does not follow them as code.
+    pub official: bool,
+}
+
+impl Opcode {
+    // bytes taken by the instruction, opcode included
+    pub fn size(&self) -> usize {
+        1 + self.mode.operand_len()
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        self.instruction.mnemonic()
+    }
+}
+
+const fn op(instruction: Instruction, mode: Mode, cycles: u8, xpage_cycles: u8) -> Opcode {
+    Opcode { instruction, mode, cycles, xpage_cycles, official: true }
+}
+
+const fn unofficial(instruction: Instruction, mode: Mode, cycles: u8, xpage_cycles: u8) -> Opcode {
+    Opcode { instruction, mode, cycles, xpage_cycles, official: false }
+}
+
+pub static OPCODES: [Opcode; 256] = [
+    /* 00 */ op(Brk, Implied, 7, 0),
+    /* 01 */ op(Ora, IndirectX, 6, 0),
+    /* 02 */ unofficial(Kil, Implied, 0, 0),
+    /* 03 */ unofficial(Slo, IndirectX, 8, 0),
+    /* 04 */ unofficial(Nop, ZeroPage, 3, 0),
+    /* 05 */ op(Ora, ZeroPage, 3, 0),
+    /* 06 */ op(Asl, ZeroPage, 5, 0),
+    /* 07 */ unofficial(Slo, ZeroPage, 5, 0),
+    /* 08 */ op(Php, Implied, 3, 0),
+    /* 09 */ op(Ora, Immediate, 2, 0),
+    /* 0a */ op(Asl, Accumulator, 2, 0),
+    /* 0b */ unofficial(Anc, Immediate, 2, 0),
+    /* 0c */ unofficial(Nop, Absolute, 4, 0),
+    /* 0d */ op(Ora, Absolute, 4, 0),
+    /* 0e */ op(Asl, Absolute, 6, 0),
+    /* 0f */ unofficial(Slo, Absolute, 6, 0),
+    /* 10 */ op(Bpl, Relative, 2, 1),
+    /* 11 */ op(Ora, IndirectY, 5, 1),
+    /* 12 */ unofficial(Kil, Implied, 0, 0),
+    /* 13 */ unofficial(Slo, IndirectY, 8, 0),
+    /* 14 */ unofficial(Nop, ZeroPageX, 4, 0),
+    /* 15 */ op(Ora, ZeroPageX, 4, 0),
+    /* 16 */ op(Asl, ZeroPageX, 6, 0),
+    /* 17 */ unofficial(Slo, ZeroPageX, 6, 0),
+    /* 18 */ op(Clc, Implied, 2, 0),
+    /* 19 */ op(Ora, AbsoluteY, 4, 1),
+    /* 1a */ unofficial(Nop, Implied, 2, 0),
+    /* 1b */ unofficial(Slo, AbsoluteY, 7, 0),
+    /* 1c */ unofficial(Nop, AbsoluteX, 4, 1),
+    /* 1d */ op(Ora, AbsoluteX, 4, 1),
+    /* 1e */ op(Asl, AbsoluteX, 7, 0),
+    /* 1f */ unofficial(Slo, AbsoluteX, 7, 0),
+    /* 20 */ op(Jsr, Absolute, 6, 0),
+    /* 21 */ op(And, IndirectX, 6, 0),
+    /* 22 */ unofficial(Kil, Implied, 0, 0),
+    /* 23 */ unofficial(Rla, IndirectX, 8, 0),
+    /* 24 */ op(Bit, ZeroPage, 3, 0),
+    /* 25 */ op(And, ZeroPage, 3, 0),
+    /* 26 */ op(Rol, ZeroPage, 5, 0),
+    /* 27 */ unofficial(Rla, ZeroPage, 5, 0),
+    /* 28 */ op(Plp, Implied, 4, 0),
+    /* 29 */ op(And, Immediate, 2, 0),
+    /* 2a */ op(Rol, Accumulator, 2, 0),
+    /* 2b */ unofficial(Anc, Immediate, 2, 0),
+    /* 2c */ op(Bit, Absolute, 4, 0),
+    /* 2d */ op(And, Absolute, 4, 0),
+    /* 2e */ op(Rol, Absolute, 6, 0),
+    /* 2f */ unofficial(Rla, Absolute, 6, 0),
+    /* 30 */ op(Bmi, Relative, 2, 1),
+    /* 31 */ op(And, IndirectY, 5, 1),
+    /* 32 */ unofficial(Kil, Implied, 0, 0),
+    /* 33 */ unofficial(Rla, IndirectY, 8, 0),
+    /* 34 */ unofficial(Nop, ZeroPageX, 4, 0),
+    /* 35 */ op(And, ZeroPageX, 4, 0),
+    /* 36 */ op(Rol, ZeroPageX, 6, 0),
+    /* 37 */ unofficial(Rla, ZeroPageX, 6, 0),
+    /* 38 */ op(Sec, Implied, 2, 0),
+    /* 39 */ op(And, AbsoluteY, 4, 1),
+    /* 3a */ unofficial(Nop, Implied, 2, 0),
+    /* 3b */ unofficial(Rla, AbsoluteY, 7, 0),
+    /* 3c */ unofficial(Nop, AbsoluteX, 4, 1),
+    /* 3d */ op(And, AbsoluteX, 4, 1),
+    /* 3e */ op(Rol, AbsoluteX, 7, 0),
+    /* 3f */ unofficial(Rla, AbsoluteX, 7, 0),
+    /* 40 */ op(Rti, Implied, 6, 0),
+    /* 41 */ op(Eor, IndirectX, 6, 0),
+    /* 42 */ unofficial(Kil, Implied, 0, 0),
+    /* 43 */ unofficial(Sre, IndirectX, 8, 0),
+    /* 44 */ unofficial(Nop, ZeroPage, 3, 0),
+    /* 45 */ op(Eor, ZeroPage, 3, 0),
+    /* 46 */ op(Lsr, ZeroPage, 5, 0),
+    /* 47 */ unofficial(Sre, ZeroPage, 5, 0),
+    /* 48 */ op(Pha, Implied, 3, 0),
+    /* 49 */ op(Eor, Immediate, 2, 0),
+    /* 4a */ op(Lsr, Accumulator, 2, 0),
+    /* 4b */ unofficial(Alr, Immediate, 2, 0),
+    /* 4c */ op(Jmp, Absolute, 3, 0),
+    /* 4d */ op(Eor, Absolute, 4, 0),
+    /* 4e */ op(Lsr, Absolute, 6, 0),
+    /* 4f */ unofficial(Sre, Absolute, 6, 0),
+    /* 50 */ op(Bvc, Relative, 2, 1),
+    /* 51 */ op(Eor, IndirectY, 5, 1),
+    /* 52 */ unofficial(Kil, Implied, 0, 0),
+    /* 53 */ unofficial(Sre, IndirectY, 8, 0),
+    /* 54 */ unofficial(Nop, ZeroPageX, 4, 0),
+    /* 55 */ op(Eor, ZeroPageX, 4, 0),
+    /* 56 */ op(Lsr, ZeroPageX, 6, 0),
+    /* 57 */ unofficial(Sre, ZeroPageX, 6, 0),
+    /* 58 */ op(Cli, Implied, 2, 0),
+    /* 59 */ op(Eor, AbsoluteY, 4, 1),
+    /* 5a */ unofficial(Nop, Implied, 2, 0),
+    /* 5b */ unofficial(Sre, AbsoluteY, 7, 0),
+    /* 5c */ unofficial(Nop, AbsoluteX, 4, 1),
+    /* 5d */ op(Eor, AbsoluteX, 4, 1),
+    /* 5e */ op(Lsr, AbsoluteX, 7, 0),
+    /* 5f */ unofficial(Sre, AbsoluteX, 7, 0),
+    /* 60 */ op(Rts, Implied, 6, 0),
+    /* 61 */ op(Adc, IndirectX, 6, 0),
+    /* 62 */ unofficial(Kil, Implied, 0, 0),
+    /* 63 */ unofficial(Rra, IndirectX, 8, 0),
+    /* 64 */ unofficial(Nop, ZeroPage, 3, 0),
+    /* 65 */ op(Adc, ZeroPage, 3, 0),
+    /* 66 */ op(Ror, ZeroPage, 5, 0),
+    /* 67 */ unofficial(Rra, ZeroPage, 5, 0),
+    /* 68 */ op(Pla, Implied, 4, 0),
+    /* 69 */ op(Adc, Immediate, 2, 0),
+    /* 6a */ op(Ror, Accumulator, 2, 0),
+    /* 6b */ unofficial(Arr, Immediate, 2, 0),
+    /* 6c */ op(Jmp, Indirect, 5, 0),
+    /* 6d */ op(Adc, Absolute, 4, 0),
+    /* 6e */ op(Ror, Absolute, 6, 0),
+    /* 6f */ unofficial(Rra, Absolute, 6, 0),
+    /* 70 */ op(Bvs, Relative, 2, 1),
+    /* 71 */ op(Adc, IndirectY, 5, 1),
+    /* 72 */ unofficial(Kil, Implied, 0, 0),
+    /* 73 */ unofficial(Rra, IndirectY, 8, 0),
+    /* 74 */ unofficial(Nop, ZeroPageX, 4, 0),
+    /* 75 */ op(Adc, ZeroPageX, 4, 0),
+    /* 76 */ op(Ror, ZeroPageX, 6, 0),
+    /* 77 */ unofficial(Rra, ZeroPageX, 6, 0),
+    /* 78 */ op(Sei, Implied, 2, 0),
+    /* 79 */ op(Adc, AbsoluteY, 4, 1),
+    /* 7a */ unofficial(Nop, Implied, 2, 0),
+    /* 7b */ unofficial(Rra, AbsoluteY, 7, 0),
+    /* 7c */ unofficial(Nop, AbsoluteX, 4, 1),
+    /* 7d */ op(Adc, AbsoluteX, 4, 1),
+    /* 7e */ op(Ror, AbsoluteX, 7, 0),
+    /* 7f */ unofficial(Rra, AbsoluteX, 7, 0),
+    /* 80 */ unofficial(Nop, Immediate, 2, 0),
+    /* 81 */ op(Sta, IndirectX, 6, 0),
+    /* 82 */ unofficial(Nop, Immediate, 2, 0),
+    /* 83 */ unofficial(Sax, IndirectX, 6, 0),
+    /* 84 */ op(Sty, ZeroPage, 3, 0),
+    /* 85 */ op(Sta, ZeroPage, 3, 0),
+    /* 86 */ op(Stx, ZeroPage, 3, 0),
+    /* 87 */ unofficial(Sax, ZeroPage, 3, 0),
+    /* 88 */ op(Dey, Implied, 2, 0),
+    /* 89 */ unofficial(Nop, Immediate, 2, 0),
+    /* 8a */ op(Txa, Implied, 2, 0),
+    /* 8b */ unofficial(Xaa, Immediate, 2, 0),
+    /* 8c */ op(Sty, Absolute, 4, 0),
+    /* 8d */ op(Sta, Absolute, 4, 0),
+    /* 8e */ op(Stx, Absolute, 4, 0),
+    /* 8f */ unofficial(Sax, Absolute, 4, 0),
+    /* 90 */ op(Bcc, Relative, 2, 1),
+    /* 91 */ op(Sta, IndirectY, 6, 0),
+    /* 92 */ unofficial(Kil, Implied, 0, 0),
+    /* 93 */ unofficial(Ahx, IndirectY, 6, 0),
+    /* 94 */ op(Sty, ZeroPageX, 4, 0),
+    /* 95 */ op(Sta, ZeroPageX, 4, 0),
+    /* 96 */ op(Stx, ZeroPageY, 4, 0),
+    /* 97 */ unofficial(Sax, ZeroPageY, 4, 0),
+    /* 98 */ op(Tya, Implied, 2, 0),
+    /* 99 */ op(Sta, AbsoluteY, 5, 0),
+    /* 9a */ op(Txs, Implied, 2, 0),
+    /* 9b */ unofficial(Tas, AbsoluteY, 5, 0),
+    /* 9c */ unofficial(Shy, AbsoluteX, 5, 0),
+    /* 9d */ op(Sta, AbsoluteX, 5, 0),
+    /* 9e */ unofficial(Shx, AbsoluteY, 5, 0),
+    /* 9f */ unofficial(Ahx, AbsoluteY, 5, 0),
+    /* a0 */ op(Ldy, Immediate, 2, 0),
+    /* a1 */ op(Lda, IndirectX, 6, 0),
+    /* a2 */ op(Ldx, Immediate, 2, 0),
+    /* a3 */ unofficial(Lax, IndirectX, 6, 0),
+    /* a4 */ op(Ldy, ZeroPage, 3, 0),
+    /* a5 */ op(Lda, ZeroPage, 3, 0),
+    /* a6 */ op(Ldx, ZeroPage, 3, 0),
+    /* a7 */ unofficial(Lax, ZeroPage, 3, 0),
+    /* a8 */ op(Tay, Implied, 2, 0),
+    /* a9 */ op(Lda, Immediate, 2, 0),
+    /* aa */ op(Tax, Implied, 2, 0),
+    /* ab */ unofficial(Lax, Immediate, 2, 0),
+    /* ac */ op(Ldy, Absolute, 4, 0),
+    /* ad */ op(Lda, Absolute, 4, 0),
+    /* ae */ op(Ldx, Absolute, 4, 0),
+    /* af */ unofficial(Lax, Absolute, 4, 0),
+    /* b0 */ op(Bcs, Relative, 2, 1),
+    /* b1 */ op(Lda, IndirectY, 5, 1),
+    /* b2 */ unofficial(Kil, Implied, 0, 0),
+    /* b3 */ unofficial(Lax, IndirectY, 5, 1),
+    /* b4 */ op(Ldy, ZeroPageX, 4, 0),
+    /* b5 */ op(Lda, ZeroPageX, 4, 0),
+    /* b6 */ op(Ldx, ZeroPageY, 4, 0),
+    /* b7 */ unofficial(Lax, ZeroPageY, 4, 0),
+    /* b8 */ op(Clv, Implied, 2, 0),
+    /* b9 */ op(Lda, AbsoluteY, 4, 1),
+    /* ba */ op(Tsx, Implied, 2, 0),
+    /* bb */ unofficial(Las, AbsoluteY, 4, 1),
+    /* bc */ op(Ldy, AbsoluteX, 4, 1),
+    /* bd */ op(Lda, AbsoluteX, 4, 1),
+    /* be */ op(Ldx, AbsoluteY, 4, 1),
+    /* bf */ unofficial(Lax, AbsoluteY, 4, 1),
+    /* c0 */ op(Cpy, Immediate, 2, 0),
+    /* c1 */ op(Cmp, IndirectX, 6, 0),
+    /* c2 */ unofficial(Nop, Immediate, 2, 0),
+    /* c3 */ unofficial(Dcp, IndirectX, 8, 0),
+    /* c4 */ op(Cpy, ZeroPage, 3, 0),
+    /* c5 */ op(Cmp, ZeroPage, 3, 0),
+    /* c6 */ op(Dec, ZeroPage, 5, 0),
+    /* c7 */ unofficial(Dcp, ZeroPage, 5, 0),
+    /* c8 */ op(Iny, Implied, 2, 0),
+    /* c9 */ op(Cmp, Immediate, 2, 0),
+    /* ca */ op(Dex, Implied, 2, 0),
+    /* cb */ unofficial(Axs, Immediate, 2, 0),
+    /* cc */ op(Cpy, Absolute, 4, 0),
+    /* cd */ op(Cmp, Absolute, 4, 0),
+    /* ce */ op(Dec, Absolute, 6, 0),
+    /* cf */ unofficial(Dcp, Absolute, 6, 0),
+    /* d0 */ op(Bne, Relative, 2, 1),
+    /* d1 */ op(Cmp, IndirectY, 5, 1),
+    /* d2 */ unofficial(Kil, Implied, 0, 0),
+    /* d3 */ unofficial(Dcp, IndirectY, 8, 0),
+    /* d4 */ unofficial(Nop, ZeroPageX, 4, 0),
+    /* d5 */ op(Cmp, ZeroPageX, 4, 0),
+    /* d6 */ op(Dec, ZeroPageX, 6, 0),
+    /* d7 */ unofficial(Dcp, ZeroPageX, 6, 0),
+    /* d8 */ op(Cld, Implied, 2, 0),
+    /* d9 */ op(Cmp, AbsoluteY, 4, 1),
+    /* da */ unofficial(Nop, Implied, 2, 0),
+    /* db */ unofficial(Dcp, AbsoluteY, 7, 0),
+    /* dc */ unofficial(Nop, AbsoluteX, 4, 1),
+    /* dd */ op(Cmp, AbsoluteX, 4, 1),
+    /* de */ op(Dec, AbsoluteX, 7, 0),
+    /* df */ unofficial(Dcp, AbsoluteX, 7, 0),
+    /* e0 */ op(Cpx, Immediate, 2, 0),
+    /* e1 */ op(Sbc, IndirectX, 6, 0),
+    /* e2 */ unofficial(Nop, Immediate, 2, 0),
+    /* e3 */ unofficial(Isc, IndirectX, 8, 0),
+    /* e4 */ op(Cpx, ZeroPage, 3, 0),
+    /* e5 */ op(Sbc, ZeroPage, 3, 0),
+    /* e6 */ op(Inc, ZeroPage, 5, 0),
+    /* e7 */ unofficial(Isc, ZeroPage, 5, 0),
+    /* e8 */ op(Inx, Implied, 2, 0),
+    /* e9 */ op(Sbc, Immediate, 2, 0),
+    /* ea */ op(Nop, Implied, 2, 0),
+    /* eb */ unofficial(Sbc, Immediate, 2, 0),
+    /* ec */ op(Cpx, Absolute, 4, 0),
+    /* ed */ op(Sbc, Absolute, 4, 0),
+    /* ee */ op(Inc, Absolute, 6, 0),
+    /* ef */ unofficial(Isc, Absolute, 6, 0),
+    /* f0 */ op(Beq, Relative, 2, 1),
+    /* f1 */ op(Sbc, IndirectY, 5, 1),
+    /* f2 */ unofficial(Kil, Implied, 0, 0),
+    /* f3 */ unofficial(Isc, IndirectY, 8, 0),
+    /* f4 */ unofficial(Nop, ZeroPageX, 4, 0),
+    /* f5 */ op(Sbc, ZeroPageX, 4, 0),
+    /* f6 */ op(Inc, ZeroPageX, 6, 0),
+    /* f7 */ unofficial(Isc, ZeroPageX, 6, 0),
+    /* f8 */ op(Sed, Implied, 2, 0),
+    /* f9 */ op(Sbc, AbsoluteY, 4, 1),
+    /* fa */ unofficial(Nop, Implied, 2, 0),
+    /* fb */ unofficial(Isc, AbsoluteY, 7, 0),
+    /* fc */ unofficial(Nop, AbsoluteX, 4, 1),
+    /* fd */ op(Sbc, AbsoluteX, 4, 1),
+    /* fe */ op(Inc, AbsoluteX, 7, 0),
+    /* ff */ unofficial(Isc, AbsoluteX, 7, 0),
+];
+
+// The CMOS 65C02 as first made, without the bit instructions Rockwell
+// and WDC added. Opcodes the 6502 leaves undefined are NOPs taking
+// from 1 to 8 cycles, unofficial as assemblers do not know them. JMP
+// ($nnnn) reads its pointer across pages.
+pub static OPCODES_65C02: [Opcode; 256] = [
+    /* 00 */ op(Brk, Implied, 7, 0),
+    /* 01 */ op(Ora, IndirectX, 6, 0),
+    /* 02 */ unofficial(Nop, Immediate, 2, 0),
+    /* 03 */ unofficial(Nop, Implied, 1, 0),
+    /* 04 */ op(Tsb, ZeroPage, 5, 0),
+    /* 05 */ op(Ora, ZeroPage, 3, 0),
+    /* 06 */ op(Asl, ZeroPage, 5, 0),
+    /* 07 */ unofficial(Nop, Implied, 1, 0),
+    /* 08 */ op(Php, Implied, 3, 0),
+    /* 09 */ op(Ora, Immediate, 2, 0),
+    /* 0a */ op(Asl, Accumulator, 2, 0),
+    /* 0b */ unofficial(Nop, Implied, 1, 0),
+    /* 0c */ op(Tsb, Absolute, 6, 0),
+    /* 0d */ op(Ora, Absolute, 4, 0),
+    /* 0e */ op(Asl, Absolute, 6, 0),
+    /* 0f */ unofficial(Nop, Implied, 1, 0),
+    /* 10 */ op(Bpl, Relative, 2, 1),
+    /* 11 */ op(Ora, IndirectY, 5, 1),
+    /* 12 */ op(Ora, ZeroPageIndirect, 5, 0),
+    /* 13 */ unofficial(Nop, Implied, 1, 0),
+    /* 14 */ op(Trb, ZeroPage, 5, 0),
+    /* 15 */ op(Ora, ZeroPageX, 4, 0),
+    /* 16 */ op(Asl, ZeroPageX, 6, 0),
+    /* 17 */ unofficial(Nop, Implied, 1, 0),
+    /* 18 */ op(Clc, Implied, 2, 0),
+    /* 19 */ op(Ora, AbsoluteY, 4, 1),
+    /* 1a */ op(Inc, Accumulator, 2, 0),
+    /* 1b */ unofficial(Nop, Implied, 1, 0),
+    /* 1c */ op(Trb, Absolute, 6, 0),
+    /* 1d */ op(Ora, AbsoluteX, 4, 1),
+    /* 1e */ op(Asl, AbsoluteX, 6, 1),
+    /* 1f */ unofficial(Nop, Implied, 1, 0),
+    /* 20 */ op(Jsr, Absolute, 6, 0),
+    /* 21 */ op(And, IndirectX, 6, 0),
+    /* 22 */ unofficial(Nop, Immediate, 2, 0),
+    /* 23 */ unofficial(Nop, Implied, 1, 0),
+    /* 24 */ op(Bit, ZeroPage, 3, 0),
+    /* 25 */ op(And, ZeroPage, 3, 0),
+    /* 26 */ op(Rol, ZeroPage, 5, 0),
+    /* 27 */ unofficial(Nop, Implied, 1, 0),
+    /* 28 */ op(Plp, Implied, 4, 0),
+    /* 29 */ op(And, Immediate, 2, 0),
+    /* 2a */ op(Rol, Accumulator, 2, 0),
+    /* 2b */ unofficial(Nop, Implied, 1, 0),
+    /* 2c */ op(Bit, Absolute, 4, 0),
+    /* 2d */ op(And, Absolute, 4, 0),
+    /* 2e */ op(Rol, Absolute, 6, 0),
+    /* 2f */ unofficial(Nop, Implied, 1, 0),
+    /* 30 */ op(Bmi, Relative, 2, 1),
+    /* 31 */ op(And, IndirectY, 5, 1),
+    /* 32 */ op(And, ZeroPageIndirect, 5, 0),
+    /* 33 */ unofficial(Nop, Implied, 1, 0),
+    /* 34 */ op(Bit, ZeroPageX, 4, 0),
+    /* 35 */ op(And, ZeroPageX, 4, 0),
+    /* 36 */ op(Rol, ZeroPageX, 6, 0),
+    /* 37 */ unofficial(Nop, Implied, 1, 0),
+    /* 38 */ op(Sec, Implied, 2, 0),
+    /* 39 */ op(And, AbsoluteY, 4, 1),
+    /* 3a */ op(Dec, Accumulator, 2, 0),
+    /* 3b */ unofficial(Nop, Implied, 1, 0),
+    /* 3c */ op(Bit, AbsoluteX, 4, 1),
+    /* 3d */ op(And, AbsoluteX, 4, 1),
+    /* 3e */ op(Rol, AbsoluteX, 6, 1),
+    /* 3f */ unofficial(Nop, Implied, 1, 0),
+    /* 40 */ op(Rti, Implied, 6, 0),
+    /* 41 */ op(Eor, IndirectX, 6, 0),
+    /* 42 */ unofficial(Nop, Immediate, 2, 0),
+    /* 43 */ unofficial(Nop, Implied, 1, 0),
+    /* 44 */ unofficial(Nop, ZeroPage, 3, 0),
+    /* 45 */ op(Eor, ZeroPage, 3, 0),
+    /* 46 */ op(Lsr, ZeroPage, 5, 0),
+    /* 47 */ unofficial(Nop, Implied, 1, 0),
+    /* 48 */ op(Pha, Implied, 3, 0),
+    /* 49 */ op(Eor, Immediate, 2, 0),
+    /* 4a */ op(Lsr, Accumulator, 2, 0),
+    /* 4b */ unofficial(Nop, Implied, 1, 0),
+    /* 4c */ op(Jmp, Absolute, 3, 0),
+    /* 4d */ op(Eor, Absolute, 4, 0),
+    /* 4e */ op(Lsr, Absolute, 6, 0),
+    /* 4f */ unofficial(Nop, Implied, 1, 0),
+    /* 50 */ op(Bvc, Relative, 2, 1),
+    /* 51 */ op(Eor, IndirectY, 5, 1),
+    /* 52 */ op(Eor, ZeroPageIndirect, 5, 0),
+    /* 53 */ unofficial(Nop, Implied, 1, 0),
+    /* 54 */ unofficial(Nop, ZeroPageX, 4, 0),
+    /* 55 */ op(Eor, ZeroPageX, 4, 0),
+    /* 56 */ op(Lsr, ZeroPageX, 6, 0),
+    /* 57 */ unofficial(Nop, Implied, 1, 0),
+    /* 58 */ op(Cli, Implied, 2, 0),
+    /* 59 */ op(Eor, AbsoluteY, 4, 1),
+    /* 5a */ op(Phy, Implied, 3, 0),
+    /* 5b */ unofficial(Nop, Implied, 1, 0),
+    /* 5c */ unofficial(Nop, Absolute, 8, 0),
+    /* 5d */ op(Eor, AbsoluteX, 4, 1),
+    /* 5e */ op(Lsr, AbsoluteX, 6, 1),
+    /* 5f */ unofficial(Nop, Implied, 1, 0),
+    /* 60 */ op(Rts, Implied, 6, 0),
+    /* 61 */ op(Adc, IndirectX, 6, 0),
+    /* 62 */ unofficial(Nop, Immediate, 2, 0),
+    /* 63 */ unofficial(Nop, Implied, 1, 0),
+    /* 64 */ op(Stz, ZeroPage, 3, 0),
+    /* 65 */ op(Adc, ZeroPage, 3, 0),
+    /* 66 */ op(Ror, ZeroPage, 5, 0),
+    /* 67 */ unofficial(Nop, Implied, 1, 0),
+    /* 68 */ op(Pla, Implied, 4, 0),
+    /* 69 */ op(Adc, Immediate, 2, 0),
+    /* 6a */ op(Ror, Accumulator, 2, 0),
+    /* 6b */ unofficial(Nop, Implied, 1, 0),
+    /* 6c */ op(Jmp, Indirect, 6, 0),
+    /* 6d */ op(Adc, Absolute, 4, 0),
+    /* 6e */ op(Ror, Absolute, 6, 0),
+    /* 6f */ unofficial(Nop, Implied, 1, 0),
+    /* 70 */ op(Bvs, Relative, 2, 1),
+    /* 71 */ op(Adc, IndirectY, 5, 1),
+    /* 72 */ op(Adc, ZeroPageIndirect, 5, 0),
+    /* 73 */ unofficial(Nop, Implied, 1, 0),
+    /* 74 */ op(Stz, ZeroPageX, 4, 0),
+    /* 75 */ op(Adc, ZeroPageX, 4, 0),
+    /* 76 */ op(Ror, ZeroPageX, 6, 0),
+    /* 77 */ unofficial(Nop, Implied, 1, 0),
+    /* 78 */ op(Sei, Implied, 2, 0),
+    /* 79 */ op(Adc, AbsoluteY, 4, 1),
+    /* 7a */ op(Ply, Implied, 4, 0),
+    /* 7b */ unofficial(Nop, Implied, 1, 0),
+    /* 7c */ op(Jmp, AbsoluteIndirectX, 6, 0),
+    /* 7d */ op(Adc, AbsoluteX, 4, 1),
+    /* 7e */ op(Ror, AbsoluteX, 6, 1),
+    /* 7f */ unofficial(Nop, Implied, 1, 0),
+    /* 80 */ op(Bra, Relative, 2, 1),
+    /* 81 */ op(Sta, IndirectX, 6, 0),
+    /* 82 */ unofficial(Nop, Immediate, 2, 0),
+    /* 83 */ unofficial(Nop, Implied, 1, 0),
+    /* 84 */ op(Sty, ZeroPage, 3, 0),
+    /* 85 */ op(Sta, ZeroPage, 3, 0),
+    /* 86 */ op(Stx, ZeroPage, 3, 0),
+    /* 87 */ unofficial(Nop, Implied, 1, 0),
+    /* 88 */ op(Dey, Implied, 2, 0),
+    /* 89 */ op(Bit, Immediate, 2, 0),
+    /* 8a */ op(Txa, Implied, 2, 0),
+    /* 8b */ unofficial(Nop, Implied, 1, 0),
+    /* 8c */ op(Sty, Absolute, 4, 0),
+    /* 8d */ op(Sta, Absolute, 4, 0),
+    /* 8e */ op(Stx, Absolute, 4, 0),
+    /* 8f */ unofficial(Nop, Implied, 1, 0),
+    /* 90 */ op(Bcc, Relative, 2, 1),
+    /* 91 */ op(Sta, IndirectY, 6, 0),
+    /* 92 */ op(Sta, ZeroPageIndirect, 5, 0),
+    /* 93 */ unofficial(Nop, Implied, 1, 0),
+    /* 94 */ op(Sty, ZeroPageX, 4, 0),
+    /* 95 */ op(Sta, ZeroPageX, 4, 0),
+    /* 96 */ op(Stx, ZeroPageY, 4, 0),
+    /* 97 */ unofficial(Nop, Implied, 1, 0),
+    /* 98 */ op(Tya, Implied, 2, 0),
+    /* 99 */ op(Sta, AbsoluteY, 5, 0),
+    /* 9a */ op(Txs, Implied, 2, 0),
+    /* 9b */ unofficial(Nop, Implied, 1, 0),
+    /* 9c */ op(Stz, Absolute, 4, 0),
+    /* 9d */ op(Sta, AbsoluteX, 5, 0),
+    /* 9e */ op(Stz, AbsoluteX, 5, 0),
+    /* 9f */ unofficial(Nop, Implied, 1, 0),
+    /* a0 */ op(Ldy, Immediate, 2, 0),
+    /* a1 */ op(Lda, IndirectX, 6, 0),
+    /* a2 */ op(Ldx, Immediate, 2, 0),
+    /* a3 */ unofficial(Nop, Implied, 1, 0),
+    /* a4 */ op(Ldy, ZeroPage, 3, 0),
+    /* a5 */ op(Lda, ZeroPage, 3, 0),
+    /* a6 */ op(Ldx, ZeroPage, 3, 0),
+    /* a7 */ unofficial(Nop, Implied, 1, 0),
+    /* a8 */ op(Tay, Implied, 2, 0),
+    /* a9 */ op(Lda, Immediate, 2, 0),
+    /* aa */ op(Tax, Implied, 2, 0),
+    /* ab */ unofficial(Nop, Implied, 1, 0),
+    /* ac */ op(Ldy, Absolute, 4, 0),
+    /* ad */ op(Lda, Absolute, 4, 0),
+    /* ae */ op(Ldx, Absolute, 4, 0),
+    /* af */ unofficial(Nop, Implied, 1, 0),
+    /* b0 */ op(Bcs, Relative, 2, 1),
+    /* b1 */ op(Lda, IndirectY, 5, 1),
+    /* b2 */ op(Lda, ZeroPageIndirect, 5, 0),
+    /* b3 */ unofficial(Nop, Implied, 1, 0),
+    /* b4 */ op(Ldy, ZeroPageX, 4, 0),
+    /* b5 */ op(Lda, ZeroPageX, 4, 0),
+    /* b6 */ op(Ldx, ZeroPageY, 4, 0),
+    /* b7 */ unofficial(Nop, Implied, 1, 0),
+    /* b8 */ op(Clv, Implied, 2, 0),
+    /* b9 */ op(Lda, AbsoluteY, 4, 1),
+    /* ba */ op(Tsx, Implied, 2, 0),
+    /* bb */ unofficial(Nop, Implied, 1, 0),
+    /* bc */ op(Ldy, AbsoluteX, 4, 1),
+    /* bd */ op(Lda, AbsoluteX, 4, 1),
+    /* be */ op(Ldx, AbsoluteY, 4, 1),
+    /* bf */ unofficial(Nop, Implied, 1, 0),
+    /* c0 */ op(Cpy, Immediate, 2, 0),
+    /* c1 */ op(Cmp, IndirectX, 6, 0),
+    /* c2 */ unofficial(Nop, Immediate, 2, 0),
+    /* c3 */ unofficial(Nop, Implied, 1, 0),
+    /* c4 */ op(Cpy, ZeroPage, 3, 0),
+    /* c5 */ op(Cmp, ZeroPage, 3, 0),
+    /* c6 */ op(Dec, ZeroPage, 5, 0),
+    /* c7 */ unofficial(Nop, Implied, 1, 0),
+    /* c8 */ op(Iny, Implied, 2, 0),
+    /* c9 */ op(Cmp, Immediate, 2, 0),
+    /* ca */ op(Dex, Implied, 2, 0),
+    /* cb */ unofficial(Nop, Implied, 1, 0),
+    /* cc */ op(Cpy, Absolute, 4, 0),
+    /* cd */ op(Cmp, Absolute, 4, 0),
+    /* ce */ op(Dec, Absolute, 6, 0),
+    /* cf */ unofficial(Nop, Implied, 1, 0),
+    /* d0 */ op(Bne, Relative, 2, 1),
+    /* d1 */ op(Cmp, IndirectY, 5, 1),
+    /* d2 */ op(Cmp, ZeroPageIndirect, 5, 0),
+    /* d3 */ unofficial(Nop, Implied, 1, 0),
+    /* d4 */ unofficial(Nop, ZeroPageX, 4, 0),
+    /* d5 */ op(Cmp, ZeroPageX, 4, 0),
+    /* d6 */ op(Dec, ZeroPageX, 6, 0),
+    /* d7 */ unofficial(Nop, Implied, 1, 0),
+    /* d8 */ op(Cld, Implied, 2, 0),
+    /* d9 */ op(Cmp, AbsoluteY, 4, 1),
+    /* da */ op(Phx, Implied, 3, 0),
+    /* db */ unofficial(Nop, Implied, 1, 0),
+    /* dc */ unofficial(Nop, Absolute, 4, 0),
+    /* dd */ op(Cmp, AbsoluteX, 4, 1),
+    /* de */ op(Dec, AbsoluteX, 7, 0),
+    /* df */ unofficial(Nop, Implied, 1, 0),
+    /* e0 */ op(Cpx, Immediate, 2, 0),
+    /* e1 */ op(Sbc, IndirectX, 6, 0),
+    /* e2 */ unofficial(Nop, Immediate, 2, 0),
+    /* e3 */ unofficial(Nop, Implied, 1, 0),
+    /* e4 */ op(Cpx, ZeroPage, 3, 0),
+    /* e5 */ op(Sbc, ZeroPage, 3, 0),
+    /* e6 */ op(Inc, ZeroPage, 5, 0),
+    /* e7 */ unofficial(Nop, Implied, 1, 0),
+    /* e8 */ op(Inx, Implied, 2, 0),
+    /* e9 */ op(Sbc, Immediate, 2, 0),
+    /* ea */ op(Nop, Implied, 2, 0),
+    /* eb */ unofficial(Nop, Implied, 1, 0),
+    /* ec */ op(Cpx, Absolute, 4, 0),
+    /* ed */ op(Sbc, Absolute, 4, 0),
+    /* ee */ op(Inc, Absolute, 6, 0),
+    /* ef */ unofficial(Nop, Implied, 1, 0),
+    /* f0 */ op(Beq, Relative, 2, 1),
+    /* f1 */ op(Sbc, IndirectY, 5, 1),
+    /* f2 */ op(Sbc, ZeroPageIndirect, 5, 0),
+    /* f3 */ unofficial(Nop, Implied, 1, 0),
+    /* f4 */ unofficial(Nop, ZeroPageX, 4, 0),
+    /* f5 */ op(Sbc, ZeroPageX, 4, 0),
+    /* f6 */ op(Inc, ZeroPageX, 6, 0),
+    /* f7 */ unofficial(Nop, Implied, 1, 0),
+    /* f8 */ op(Sed, Implied, 2, 0),
+    /* f9 */ op(Sbc, AbsoluteY, 4, 1),
+    /* fa */ op(Plx, Implied, 4, 0),
+    /* fb */ unofficial(Nop, Implied, 1, 0),
+    /* fc */ unofficial(Nop, Absolute, 4, 0),
+    /* fd */ op(Sbc, AbsoluteX, 4, 1),
+    /* fe */ op(Inc, AbsoluteX, 7, 0),
+    /* ff */ unofficial(Nop, Implied, 1, 0),
+];
@@ -2,24 +2,86 @@ use std::fs::File;
 use std::path::Path;
 use std::io::Read;
 use error::Error;
+#[cfg(feature = "sdl")]
 use sdl2::pixels::Color;
 
 const NCOLOR: usize = 16;
 const NPALETTE: usize = 4;
 const PALETTE_SIZE: usize = 48;
 
-pub type Palette = [Color;NCOLOR];
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl From<Rgb> for Color {
+    fn from(c: Rgb) -> Color {
+        Color::RGB(c.r, c.g, c.b)
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl From<Color> for Rgb {
+    fn from(c: Color) -> Rgb {
+        Rgb::new(c.r, c.g, c.b)
+    }
+}
+
+pub type Palette = [Rgb;NCOLOR];
 pub type PaletteSet = [Palette;NPALETTE];
 
+// The 2C02 palette, used when no palette file is given.
+// https://wiki.nesdev.com/w/index.php/PPU_palettes
+pub const DEFAULT_PALETTE: PaletteSet = [
+    [
+        Rgb::new(0x66, 0x66, 0x66), Rgb::new(0x00, 0x2a, 0x88), Rgb::new(0x14, 0x12, 0xa7), Rgb::new(0x3b, 0x00, 0xa4),
+        Rgb::new(0x5c, 0x00, 0x7e), Rgb::new(0x6e, 0x00, 0x40), Rgb::new(0x6c, 0x06, 0x00), Rgb::new(0x56, 0x1d, 0x00),
+        Rgb::new(0x33, 0x35, 0x00), Rgb::new(0x0b, 0x48, 0x00), Rgb::new(0x00, 0x52, 0x00), Rgb::new(0x00, 0x4f, 0x08),
+        Rgb::new(0x00, 0x40, 0x4d), Rgb::new(0x00, 0x00, 0x00), Rgb::new(0x00, 0x00, 0x00), Rgb::new(0x00, 0x00, 0x00),
+    ],
+    [
+        Rgb::new(0xad, 0xad, 0xad), Rgb::new(0x15, 0x5f, 0xd9), Rgb::new(0x42, 0x40, 0xff), Rgb::new(0x75, 0x27, 0xfe),
+        Rgb::new(0xa0, 0x1a, 0xcc), Rgb::new(0xb7, 0x1e, 0x7b), Rgb::new(0xb5, 0x31, 0x20), Rgb::new(0x99, 0x4e, 0x00),
+        Rgb::new(0x6b, 0x6d, 0x00), Rgb::new(0x38, 0x87, 0x00), Rgb::new(0x0c, 0x93, 0x00), Rgb::new(0x00, 0x8f, 0x32),
+        Rgb::new(0x00, 0x7c, 0x8d), Rgb::new(0x00, 0x00, 0x00), Rgb::new(0x00, 0x00, 0x00), Rgb::new(0x00, 0x00, 0x00),
+    ],
+    [
+        Rgb::new(0xff, 0xfe, 0xff), Rgb::new(0x64, 0xb0, 0xff), Rgb::new(0x92, 0x90, 0xff), Rgb::new(0xc6, 0x76, 0xff),
+        Rgb::new(0xf3, 0x6a, 0xff), Rgb::new(0xfe, 0x6e, 0xcc), Rgb::new(0xfe, 0x81, 0x70), Rgb::new(0xea, 0x9e, 0x22),
+        Rgb::new(0xbc, 0xbe, 0x00), Rgb::new(0x88, 0xd8, 0x00), Rgb::new(0x5c, 0xe4, 0x30), Rgb::new(0x45, 0xe0, 0x82),
+        Rgb::new(0x48, 0xcd, 0xde), Rgb::new(0x4f, 0x4f, 0x4f), Rgb::new(0x00, 0x00, 0x00), Rgb::new(0x00, 0x00, 0x00),
+    ],
+    [
+        Rgb::new(0xff, 0xfe, 0xff), Rgb::new(0xc0, 0xdf, 0xff), Rgb::new(0xd3, 0xd2, 0xff), Rgb::new(0xe8, 0xc8, 0xff),
+        Rgb::new(0xfb, 0xc2, 0xff), Rgb::new(0xfe, 0xc4, 0xea), Rgb::new(0xfe, 0xcc, 0xc5), Rgb::new(0xf7, 0xd8, 0xa5),
+        Rgb::new(0xe4, 0xe5, 0x94), Rgb::new(0xcf, 0xef, 0x96), Rgb::new(0xbd, 0xf4, 0xab), Rgb::new(0xb3, 0xf3, 0xcc),
+        Rgb::new(0xb5, 0xeb, 0xf2), Rgb::new(0xb8, 0xb8, 0xb8), Rgb::new(0x00, 0x00, 0x00), Rgb::new(0x00, 0x00, 0x00),
+    ],
+];
+
+// color of a palette index as found in the framebuffer
+pub fn lookup(pal: &PaletteSet, index: u8) -> Rgb {
+    pal[(index as usize >> 4) & 0x3][index as usize & 0xf]
+}
+
 pub fn palette_from_file<P: AsRef<Path>>(path: P) -> Result<PaletteSet, Error> {
     let mut file = File::open(path)?;
-    let mut pal = [[Color::RGB(255, 255, 255); NCOLOR]; NPALETTE];
+    let mut pal = [[Rgb::new(255, 255, 255); NCOLOR]; NPALETTE];
 
-    for i in 0..NPALETTE {
+    for palette in pal.iter_mut() {
         let mut bytes = [0u8;PALETTE_SIZE];
         file.read_exact(&mut bytes)?;
         for (j, chunk) in bytes.chunks(3).enumerate() {
-            pal[i][j] = Color::RGB(chunk[0], chunk[1], chunk[2]);
+            palette[j] = Rgb::new(chunk[0], chunk[1], chunk[2]);
         }
     }
 
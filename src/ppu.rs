@@ -0,0 +1,611 @@
+// https://wiki.nesdev.com/w/index.php/PPU
+
+use std::mem;
+use cartridge::Cartridge;
+use ines::{Mirroring, Region};
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+// PPUCTRL
+const CTRL_INCREMENT:    u8 = 0b0000_0100;
+const CTRL_SPRITE_TABLE: u8 = 0b0000_1000;
+const CTRL_BG_TABLE:     u8 = 0b0001_0000;
+const CTRL_SPRITE_SIZE:  u8 = 0b0010_0000;
+const CTRL_NMI:          u8 = 0b1000_0000;
+
+// PPUMASK
+const MASK_GREYSCALE:   u8 = 0b0000_0001;
+const MASK_BG_LEFT:     u8 = 0b0000_0010;
+const MASK_SPRITE_LEFT: u8 = 0b0000_0100;
+const MASK_BG:          u8 = 0b0000_1000;
+const MASK_SPRITE:      u8 = 0b0001_0000;
+
+// PPUSTATUS
+const STATUS_OVERFLOW: u8 = 0b0010_0000;
+const STATUS_SPRITE0:  u8 = 0b0100_0000;
+const STATUS_VBLANK:   u8 = 0b1000_0000;
+
+const VBLANK_LINE: usize = 241;
+const MAX_SPRITES: usize = 8;
+
+pub struct Ppu {
+    ctrl: u8,
+    mask: u8,
+    status: u8,
+    oam_addr: u8,
+    oam: [u8; 256],
+
+    // internal registers
+    // https://wiki.nesdev.com/w/index.php/PPU_scrolling
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+
+    // PPUDATA read buffer
+    buffered: u8,
+    // the last value put on the PPU data bus
+    latch: u8,
+
+    // 4 KB so that four-screen mirroring also works
+    nametables: [u8; 0x1000],
+    palette: [u8; 32],
+
+    scanline: usize,
+    dot: usize,
+    lines_per_frame: usize,
+    odd_frame: bool,
+    frame: u64,
+    frame_ready: bool,
+    nmi_pending: bool,
+
+    // background pipeline
+    nt_byte: u8,
+    at_byte: u8,
+    bg_lo: u8,
+    bg_hi: u8,
+    // 16 pixels, 4 bits each: 2 bits of attribute and 2 bits of pattern
+    tile_data: u64,
+
+    // sprites on the current scanline
+    sprite_count: usize,
+    sprite_patterns: [u32; MAX_SPRITES],
+    sprite_positions: [u8; MAX_SPRITES],
+    sprite_priorities: [u8; MAX_SPRITES],
+    sprite_indexes: [u8; MAX_SPRITES],
+
+    // palette indices of the rendered picture
+    framebuffer: Vec<u8>,
+}
+
+// the framebuffer is redrawn by the next frame
+impl_state!(Ppu, ctrl, mask, status, oam_addr, oam, v, t, x, w, buffered, latch,
+            nametables, palette, scanline, dot, odd_frame, frame, frame_ready, nmi_pending,
+            nt_byte, at_byte, bg_lo, bg_hi, tile_data, sprite_count, sprite_patterns,
+            sprite_positions, sprite_priorities, sprite_indexes);
+
+impl Ppu {
+    pub fn new(region: Region) -> Self {
+        Ppu {
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_addr: 0,
+            oam: [0; 256],
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            buffered: 0,
+            latch: 0,
+            nametables: [0; 0x1000],
+            palette: [0; 32],
+            scanline: 0,
+            dot: 0,
+            lines_per_frame: match region {
+                Region::Ntsc => 262,
+                Region::Pal => 312,
+            },
+            odd_frame: false,
+            frame: 0,
+            frame_ready: false,
+            nmi_pending: false,
+            nt_byte: 0,
+            at_byte: 0,
+            bg_lo: 0,
+            bg_hi: 0,
+            tile_data: 0,
+            sprite_count: 0,
+            sprite_patterns: [0; MAX_SPRITES],
+            sprite_positions: [0; MAX_SPRITES],
+            sprite_priorities: [0; MAX_SPRITES],
+            sprite_indexes: [0; MAX_SPRITES],
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    // The reset button leaves VRAM, OAM and the palette untouched.
+    // https://wiki.nesdev.com/w/index.php/PPU_power_up_state
+    pub fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.w = false;
+        self.t = 0;
+        self.x = 0;
+        self.buffered = 0;
+        self.scanline = 0;
+        self.dot = 0;
+        self.odd_frame = false;
+        self.frame_ready = false;
+        self.nmi_pending = false;
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn scanline(&self) -> usize {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> usize {
+        self.dot
+    }
+
+    // true once per frame, when vertical blank begins
+    pub fn take_frame(&mut self) -> bool {
+        let ready = self.frame_ready;
+        self.frame_ready = false;
+        ready
+    }
+
+    pub fn take_nmi(&mut self) -> bool {
+        let nmi = self.nmi_pending;
+        self.nmi_pending = false;
+        nmi
+    }
+
+    #[inline(always)]
+    fn rendering(&self) -> bool {
+        self.mask & (MASK_BG | MASK_SPRITE) != 0
+    }
+
+    #[inline(always)]
+    fn prerender_line(&self) -> usize {
+        self.lines_per_frame - 1
+    }
+
+    fn increment(&self) -> u16 {
+        if self.ctrl & CTRL_INCREMENT != 0 { 32 } else { 1 }
+    }
+
+    // registers at $2000-$2007, mirrored up to $3fff
+    pub fn read_register(&mut self, addr: u16, cart: &mut Cartridge) -> u8 {
+        match addr & 0x7 {
+            2 => {
+                let value = (self.status & 0xe0) | (self.latch & 0x1f);
+                self.status &= !STATUS_VBLANK;
+                self.w = false;
+                self.latch = value;
+            }
+            4 => {
+                self.latch = self.oam[self.oam_addr as usize];
+            }
+            7 => {
+                let addr = self.v;
+                let mut value = if addr & 0x3fff < 0x2000 {
+                    cart.read_chr_data(addr & 0x3fff)
+                }
+                else {
+                    self.read(addr, cart)
+                };
+                if addr & 0x3fff < 0x3f00 {
+                    value = mem::replace(&mut self.buffered, value);
+                }
+                else {
+                    // palette reads are immediate but still fill the buffer
+                    // with the nametable byte underneath
+                    self.buffered = self.read(addr - 0x1000, cart);
+                    value = (value & 0x3f) | (self.latch & 0xc0);
+                }
+                self.v = self.v.wrapping_add(self.increment()) & 0x7fff;
+                self.latch = value;
+            }
+            // write-only registers return whatever is on the bus
+            _ => {}
+        }
+        self.latch
+    }
+
+    pub fn write_register(&mut self, addr: u16, value: u8, cart: &mut Cartridge) {
+        self.latch = value;
+        match addr & 0x7 {
+            0 => {
+                let was_enabled = self.ctrl & CTRL_NMI != 0;
+                self.ctrl = value;
+                self.t = (self.t & 0xf3ff) | ((value as u16 & 0x03) << 10);
+                // enabling NMI during vertical blank triggers one immediately
+                if !was_enabled && value & CTRL_NMI != 0 && self.status & STATUS_VBLANK != 0 {
+                    self.nmi_pending = true;
+                }
+            }
+            1 => self.mask = value,
+            3 => self.oam_addr = value,
+            4 => self.write_oam(value),
+            5 => {
+                if !self.w {
+                    self.t = (self.t & 0xffe0) | (value as u16 >> 3);
+                    self.x = value & 0x07;
+                }
+                else {
+                    self.t = (self.t & 0x8fff) | ((value as u16 & 0x07) << 12);
+                    self.t = (self.t & 0xfc1f) | ((value as u16 & 0xf8) << 2);
+                }
+                self.w = !self.w;
+            }
+            6 => {
+                if !self.w {
+                    self.t = (self.t & 0x80ff) | ((value as u16 & 0x3f) << 8);
+                }
+                else {
+                    self.t = (self.t & 0xff00) | value as u16;
+                    self.v = self.t;
+                }
+                self.w = !self.w;
+            }
+            7 => {
+                let addr = self.v;
+                self.write(addr, value, cart);
+                self.v = self.v.wrapping_add(self.increment()) & 0x7fff;
+            }
+            _ => {}
+        }
+    }
+
+    // used by OAM DMA at $4014
+    pub fn write_oam(&mut self, value: u8) {
+        self.oam[self.oam_addr as usize] = value;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    // https://wiki.nesdev.com/w/index.php/Mirroring#Nametable_Mirroring
+    fn nametable_index(&self, addr: u16, mirroring: Mirroring) -> usize {
+        let addr = (addr as usize - 0x2000) % 0x1000;
+        let table = addr / 0x400;
+        let offset = addr % 0x400;
+        let table = match mirroring {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical => table % 2,
+            Mirroring::SingleLower => 0,
+            Mirroring::SingleUpper => 1,
+            Mirroring::FourScreen => table,
+        };
+        table * 0x400 + offset
+    }
+
+    fn palette_index(addr: u16) -> usize {
+        let index = addr as usize % 32;
+        // $3f10/$3f14/$3f18/$3f1c mirror $3f00/$3f04/$3f08/$3f0c
+        if index >= 16 && index & 0x3 == 0 { index - 16 } else { index }
+    }
+
+    fn read(&mut self, addr: u16, cart: &mut Cartridge) -> u8 {
+        let addr = addr & 0x3fff;
+        match addr {
+            0x0000..=0x1fff => cart.read_chr(addr),
+            0x2000..=0x3eff => {
+                let index = self.nametable_index(addr, cart.mirroring());
+                self.nametables[index]
+            }
+            _ => self.palette[Ppu::palette_index(addr)],
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8, cart: &mut Cartridge) {
+        let addr = addr & 0x3fff;
+        match addr {
+            0x0000..=0x1fff => cart.write_chr(addr, value),
+            0x2000..=0x3eff => {
+                let index = self.nametable_index(addr, cart.mirroring());
+                self.nametables[index] = value;
+            }
+            _ => self.palette[Ppu::palette_index(addr)] = value & 0x3f,
+        }
+    }
+
+    fn increment_x(&mut self) {
+        if self.v & 0x001f == 31 {
+            self.v &= !0x001f;
+            self.v ^= 0x0400;
+        }
+        else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= 0x8fff;
+        let mut y = (self.v & 0x03e0) >> 5;
+        if y == 29 {
+            y = 0;
+            self.v ^= 0x0800;
+        }
+        else if y == 31 {
+            y = 0;
+        }
+        else {
+            y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (y << 5);
+    }
+
+    fn copy_x(&mut self) {
+        self.v = (self.v & 0xfbe0) | (self.t & 0x041f);
+    }
+
+    fn copy_y(&mut self) {
+        self.v = (self.v & 0x841f) | (self.t & 0x7be0);
+    }
+
+    fn fetch_nametable_byte(&mut self, cart: &mut Cartridge) {
+        let addr = 0x2000 | (self.v & 0x0fff);
+        self.nt_byte = self.read(addr, cart);
+    }
+
+    fn fetch_attribute_byte(&mut self, cart: &mut Cartridge) {
+        let v = self.v;
+        let addr = 0x23c0 | (v & 0x0c00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+        let shift = ((v >> 4) & 4) | (v & 2);
+        self.at_byte = ((self.read(addr, cart) >> shift) & 0x3) << 2;
+    }
+
+    fn background_pattern_addr(&self) -> u16 {
+        let fine_y = (self.v >> 12) & 0x7;
+        let table = if self.ctrl & CTRL_BG_TABLE != 0 { 0x1000 } else { 0 };
+        table + 16 * self.nt_byte as u16 + fine_y
+    }
+
+    fn fetch_low_tile_byte(&mut self, cart: &mut Cartridge) {
+        let addr = self.background_pattern_addr();
+        self.bg_lo = self.read(addr, cart);
+    }
+
+    fn fetch_high_tile_byte(&mut self, cart: &mut Cartridge) {
+        let addr = self.background_pattern_addr() + 8;
+        self.bg_hi = self.read(addr, cart);
+    }
+
+    fn store_tile_data(&mut self) {
+        let mut data = 0u32;
+        for i in (0..8).rev() {
+            let p1 = (self.bg_lo >> i) & 1;
+            let p2 = ((self.bg_hi >> i) & 1) << 1;
+            data = (data << 4) | (self.at_byte | p1 | p2) as u32;
+        }
+        self.tile_data |= data as u64;
+    }
+
+    fn background_pixel(&self) -> u8 {
+        if self.mask & MASK_BG == 0 {
+            return 0;
+        }
+        let data = (self.tile_data >> 32) as u32 >> ((7 - self.x as u32) * 4);
+        (data & 0xf) as u8
+    }
+
+    fn sprite_pixel(&self) -> Option<(usize, u8)> {
+        if self.mask & MASK_SPRITE == 0 {
+            return None;
+        }
+        let x = self.dot as i32 - 1;
+        for i in 0..self.sprite_count {
+            let offset = x - self.sprite_positions[i] as i32;
+            if !(0..8).contains(&offset) {
+                continue;
+            }
+            let color = (self.sprite_patterns[i] >> ((7 - offset) * 4)) & 0xf;
+            if color & 0x3 == 0 {
+                continue;
+            }
+            return Some((i, color as u8));
+        }
+        None
+    }
+
+    fn render_pixel(&mut self) {
+        let x = self.dot - 1;
+        let y = self.scanline;
+
+        let mut bg = self.background_pixel();
+        let mut sprite = self.sprite_pixel();
+        if x < 8 && self.mask & MASK_BG_LEFT == 0 {
+            bg = 0;
+        }
+        if x < 8 && self.mask & MASK_SPRITE_LEFT == 0 {
+            sprite = None;
+        }
+
+        let opaque_bg = bg & 0x3 != 0;
+        let color = match sprite {
+            None => if opaque_bg { bg } else { 0 },
+            Some((_, color)) if !opaque_bg => color | 0x10,
+            Some((i, color)) => {
+                if self.sprite_indexes[i] == 0 && x < 255 {
+                    self.status |= STATUS_SPRITE0;
+                }
+                if self.sprite_priorities[i] == 0 { color | 0x10 } else { bg }
+            }
+        };
+
+        self.put_pixel(x, y, color as u16);
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: u16) {
+        let mut value = self.palette[Ppu::palette_index(color)];
+        if self.mask & MASK_GREYSCALE != 0 {
+            value &= 0x30;
+        }
+        self.framebuffer[y * SCREEN_WIDTH + x] = value;
+    }
+
+    fn fetch_sprite_pattern(&mut self, i: usize, row: i32, cart: &mut Cartridge) -> u32 {
+        let mut tile = self.oam[i * 4 + 1] as u16;
+        let attributes = self.oam[i * 4 + 2];
+        let mut row = row as u16;
+        let addr = if self.ctrl & CTRL_SPRITE_SIZE == 0 {
+            if attributes & 0x80 != 0 {
+                row = 7 - row;
+            }
+            let table = if self.ctrl & CTRL_SPRITE_TABLE != 0 { 0x1000 } else { 0 };
+            table + 16 * tile + row
+        }
+        else {
+            if attributes & 0x80 != 0 {
+                row = 15 - row;
+            }
+            // 8x16 sprites pick their table with bit 0 of the tile index
+            let table = (tile & 1) * 0x1000;
+            tile &= 0xfe;
+            if row > 7 {
+                tile += 1;
+                row -= 8;
+            }
+            table + 16 * tile + row
+        };
+
+        let palette = (attributes & 0x3) << 2;
+        let lo = self.read(addr, cart);
+        let hi = self.read(addr + 8, cart);
+        let mut data = 0u32;
+        for i in 0..8 {
+            // bit 6 flips the sprite horizontally
+            let shift = if attributes & 0x40 != 0 { i } else { 7 - i };
+            let p1 = (lo >> shift) & 1;
+            let p2 = ((hi >> shift) & 1) << 1;
+            data = (data << 4) | (palette | p1 | p2) as u32;
+        }
+        data
+    }
+
+    fn evaluate_sprites(&mut self, cart: &mut Cartridge) {
+        let height = if self.ctrl & CTRL_SPRITE_SIZE != 0 { 16 } else { 8 };
+        let mut count = 0;
+        for i in 0..64 {
+            let y = self.oam[i * 4] as i32;
+            let row = self.scanline as i32 - y;
+            if row < 0 || row >= height {
+                continue;
+            }
+            if count < MAX_SPRITES {
+                self.sprite_patterns[count] = self.fetch_sprite_pattern(i, row, cart);
+                self.sprite_positions[count] = self.oam[i * 4 + 3];
+                self.sprite_priorities[count] = (self.oam[i * 4 + 2] >> 5) & 1;
+                self.sprite_indexes[count] = i as u8;
+            }
+            count += 1;
+        }
+        if count > MAX_SPRITES {
+            count = MAX_SPRITES;
+            self.status |= STATUS_OVERFLOW;
+        }
+        self.sprite_count = count;
+    }
+
+    fn tick(&mut self) {
+        // the pre-render line is one dot shorter on odd frames (NTSC only)
+        if self.rendering() && self.odd_frame && self.lines_per_frame == 262
+            && self.scanline == self.prerender_line() && self.dot == 339 {
+            self.dot = 0;
+            self.scanline = 0;
+            self.frame += 1;
+            self.odd_frame = !self.odd_frame;
+            return;
+        }
+
+        self.dot += 1;
+        if self.dot > 340 {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline >= self.lines_per_frame {
+                self.scanline = 0;
+                self.frame += 1;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    // advance one dot
+    pub fn step(&mut self, cart: &mut Cartridge) {
+        self.tick();
+
+        let prerender_line = self.scanline == self.prerender_line();
+        let visible_line = self.scanline < SCREEN_HEIGHT;
+        let render_line = prerender_line || visible_line;
+        let prefetch_dot = self.dot >= 321 && self.dot <= 336;
+        let visible_dot = self.dot >= 1 && self.dot <= 256;
+        let fetch_dot = prefetch_dot || visible_dot;
+
+        if self.rendering() {
+            if visible_line && visible_dot {
+                self.render_pixel();
+            }
+            if render_line && fetch_dot {
+                self.tile_data <<= 4;
+                match self.dot & 0x7 {
+                    1 => self.fetch_nametable_byte(cart),
+                    3 => self.fetch_attribute_byte(cart),
+                    5 => self.fetch_low_tile_byte(cart),
+                    7 => self.fetch_high_tile_byte(cart),
+                    0 => self.store_tile_data(),
+                    _ => {}
+                }
+            }
+            if prerender_line && self.dot >= 280 && self.dot <= 304 {
+                self.copy_y();
+            }
+            if render_line {
+                if fetch_dot && self.dot & 0x7 == 0 {
+                    self.increment_x();
+                }
+                if self.dot == 256 {
+                    self.increment_y();
+                }
+                if self.dot == 257 {
+                    self.copy_x();
+                }
+            }
+            if self.dot == 257 {
+                if visible_line {
+                    self.evaluate_sprites(cart);
+                }
+                else {
+                    self.sprite_count = 0;
+                }
+            }
+        }
+        else if visible_line && visible_dot {
+            // with rendering off the backdrop color is displayed
+            self.put_pixel(self.dot - 1, self.scanline, 0);
+        }
+
+        if self.scanline == VBLANK_LINE && self.dot == 1 {
+            self.status |= STATUS_VBLANK;
+            self.frame_ready = true;
+            if self.ctrl & CTRL_NMI != 0 {
+                self.nmi_pending = true;
+            }
+        }
+        if prerender_line && self.dot == 1 {
+            self.status &= !(STATUS_VBLANK | STATUS_SPRITE0 | STATUS_OVERFLOW);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Band-limited resampling of the APU output, in the spirit of blip_buf:
+// instead of point-sampling the ~1.79 MHz signal, every change of the
+// output level is added as a band-limited step at its exact position
+// between host samples, then the steps are integrated into samples.
+// http://www.slack.net/~ant/bl-synth/
+
+use std::f64::consts::PI;
+
+// kernel taps per step and the resolution of step positions
+const WIDTH: usize = 16;
+const PHASES: usize = 32;
+
+pub struct Resampler {
+    // host samples per clock
+    factor: f64,
+    // position of the first clock of the frame, in samples
+    offset: f64,
+    // deltas of samples not read yet
+    deltas: Vec<f32>,
+    integrator: f32,
+    kernel: Vec<[f32; WIDTH]>,
+}
+
+impl Resampler {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        Resampler {
+            factor: sample_rate / clock_rate,
+            offset: 0.0,
+            deltas: vec![0.0; WIDTH],
+            integrator: 0.0,
+            kernel: Resampler::make_kernel(),
+        }
+    }
+
+    // windowed sinc impulses for each phase, each summing to 1
+    fn make_kernel() -> Vec<[f32; WIDTH]> {
+        // cut off a bit below Nyquist
+        let cutoff = 0.9;
+        (0..PHASES + 1).map(|phase| {
+            let mut taps = [0f32; WIDTH];
+            let center = (WIDTH / 2) as f64 + phase as f64 / PHASES as f64;
+            let mut sum = 0.0;
+            for (i, tap) in taps.iter_mut().enumerate() {
+                let x = i as f64 - center;
+                let sinc = if x == 0.0 { 1.0 } else { (PI * x * cutoff).sin() / (PI * x * cutoff) };
+                // Blackman window
+                let n = (i as f64 - center + (WIDTH / 2) as f64) / WIDTH as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos();
+                let value = sinc * window.max(0.0);
+                *tap = value as f32;
+                sum += value;
+            }
+            for tap in taps.iter_mut() {
+                *tap /= sum as f32;
+            }
+            taps
+        }).collect()
+    }
+
+    // the output level changes by `delta` at `clock`, counted from
+    // the start of the current frame
+    pub fn add_delta(&mut self, clock: usize, delta: f32) {
+        let pos = self.offset + clock as f64 * self.factor;
+        let index = pos as usize;
+        let phase = ((pos - index as f64) * PHASES as f64) as usize;
+        if self.deltas.len() < index + WIDTH {
+            self.deltas.resize(index + WIDTH, 0.0);
+        }
+        for (d, tap) in self.deltas[index..index + WIDTH].iter_mut().zip(self.kernel[phase].iter()) {
+            *d += delta * tap;
+        }
+    }
+
+    // Finish a frame of `clocks` clocks and append the samples
+    // that are now complete to `out`.
+    pub fn end_frame(&mut self, clocks: usize, out: &mut Vec<f32>) {
+        let end = self.offset + clocks as f64 * self.factor;
+        let count = end as usize;
+        self.offset = end - count as f64;
+
+        if self.deltas.len() < count + WIDTH {
+            self.deltas.resize(count + WIDTH, 0.0);
+        }
+        for delta in self.deltas.drain(..count) {
+            self.integrator += delta;
+            out.push(self.integrator);
+        }
+    }
+}
+
+// first-order filters
+// https://wiki.nesdev.com/w/index.php/APU_Mixer
+pub enum Filter {
+    HighPass { alpha: f32, prev_in: f32, prev_out: f32 },
+    LowPass { alpha: f32, prev_out: f32 },
+}
+
+impl Filter {
+    pub fn high_pass(sample_rate: f64, cutoff: f64) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        Filter::HighPass { alpha: (rc / (rc + dt)) as f32, prev_in: 0.0, prev_out: 0.0 }
+    }
+
+    pub fn low_pass(sample_rate: f64, cutoff: f64) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        Filter::LowPass { alpha: (dt / (rc + dt)) as f32, prev_out: 0.0 }
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        match *self {
+            Filter::HighPass { alpha, ref mut prev_in, ref mut prev_out } => {
+                *prev_out = alpha * (*prev_out + x - *prev_in);
+                *prev_in = x;
+                *prev_out
+            }
+            Filter::LowPass { alpha, ref mut prev_out } => {
+                *prev_out += alpha * (x - *prev_out);
+                *prev_out
+            }
+        }
+    }
+}
+
+// The filter chain of the NES: two high-pass filters at 90 Hz and
+// 440 Hz, and a low-pass filter at 14 kHz.
+pub fn nes_filters(sample_rate: f64) -> Vec<Filter> {
+    vec![
+        Filter::high_pass(sample_rate, 90.0),
+        Filter::high_pass(sample_rate, 440.0),
+        Filter::low_pass(sample_rate, 14000.0),
+    ]
+}
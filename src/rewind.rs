@@ -0,0 +1,240 @@
+// Rewind: a ring of save states taken every few frames.
+//
+// Only the newest state is kept in full. Each older one is stored as
+// its XOR with the state after it, run-length encoded: two states a
+// few frames apart differ in a few hundred bytes, so most of the XOR
+// is zeros. When the memory budget is exceeded the oldest states are
+// dropped.
+
+use std::collections::VecDeque;
+use nes::Nes;
+
+pub struct Rewind {
+    // frames between two states
+    interval: usize,
+    // bytes the states may take
+    budget: usize,
+    frames: usize,
+    newest: Option<Vec<u8>>,
+    // oldest first
+    deltas: VecDeque<Vec<u8>>,
+    size: usize,
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= (byte as usize & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+// `a` XOR `b` as runs of zeros each followed by literal bytes
+fn encode(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < a.len() {
+        let start = i;
+        while i < a.len() && a[i] == b[i] {
+            i += 1;
+        }
+        let zeros = i - start;
+        let start = i;
+        while i < a.len() && a[i] != b[i] {
+            i += 1;
+        }
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, i - start);
+        out.extend(a[start..i].iter().zip(&b[start..i]).map(|(x, y)| x ^ y));
+    }
+    out
+}
+
+// apply a delta made by `encode` to `b`, giving back `a`
+fn decode(delta: &[u8], b: &mut [u8]) {
+    let mut pos = 0;
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let len = read_varint(delta, &mut pos);
+        for x in b[i..i + len].iter_mut() {
+            *x ^= delta[pos];
+            pos += 1;
+        }
+        i += len;
+    }
+}
+
+impl Rewind {
+    pub fn new(interval: usize, budget: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            budget,
+            frames: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    // Call once per frame, a state is taken every `interval` frames.
+    pub fn record(&mut self, nes: &Nes) {
+        if self.frames == 0 {
+            self.push(nes.save_state());
+        }
+        self.frames = (self.frames + 1) % self.interval;
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            // a state of another ROM or version starts over
+            if newest.len() != state.len() {
+                self.clear();
+            }
+            else {
+                let delta = encode(&newest, &state);
+                self.size += delta.len();
+                self.deltas.push_back(delta);
+            }
+        }
+        self.newest = Some(state);
+        while self.memory() > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    // take out the newest state
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.newest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.size -= delta.len();
+            let mut older = state.clone();
+            decode(&delta, &mut older);
+            self.newest = Some(older);
+        }
+        Some(state)
+    }
+
+    // Go back to the newest state and forget it, so that the next call
+    // goes further back. Returns false when there is nothing left.
+    pub fn step_back(&mut self, nes: &mut Nes) -> bool {
+        match self.pop() {
+            Some(state) => {
+                self.frames = 0;
+                nes.load_state(&state).is_ok()
+            }
+            None => false,
+        }
+    }
+
+    // Roll back about `frames` frames, as far as the buffer goes.
+    pub fn rewind_frames(&mut self, nes: &mut Nes, frames: usize) -> bool {
+        let steps = frames.div_ceil(self.interval);
+        let mut state = None;
+        for _ in 0..steps.max(1) {
+            match self.pop() {
+                Some(s) => state = Some(s),
+                None => break,
+            }
+        }
+        self.frames = 0;
+        match state {
+            Some(state) => nes.load_state(&state).is_ok(),
+            None => false,
+        }
+    }
+
+    // number of states kept
+    pub fn len(&self) -> usize {
+        self.newest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    // bytes taken by the states
+    pub fn memory(&self) -> usize {
+        self.size + self.newest.as_ref().map_or(0, |s| s.len())
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.size = 0;
+        self.frames = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_round_trip() {
+        let old: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let mut new = old.clone();
+        new[0] ^= 0xff;
+        new[500..700].iter_mut().for_each(|b| *b = b.wrapping_add(1));
+        new[999] = 0;
+        let delta = encode(&old, &new);
+        assert!(delta.len() < 250);
+        let mut decoded = new.clone();
+        decode(&delta, &mut decoded);
+        assert_eq!(decoded, old);
+    }
+
+    #[test]
+    fn varints() {
+        let mut out = Vec::new();
+        for &value in &[0, 0x7f, 0x80, 0x3fff, 0x4000, 1 << 30] {
+            write_varint(&mut out, value);
+        }
+        let mut pos = 0;
+        for &value in &[0, 0x7f, 0x80, 0x3fff, 0x4000, 1 << 30] {
+            assert_eq!(read_varint(&out, &mut pos), value);
+        }
+        assert_eq!(pos, out.len());
+    }
+
+    #[test]
+    fn states_come_back_newest_first() {
+        let mut rewind = Rewind::new(1, 1 << 20);
+        let states: Vec<Vec<u8>> = (0..5u8).map(|n| vec![n; 64]).collect();
+        for state in &states {
+            rewind.push(state.clone());
+        }
+        assert_eq!(rewind.len(), 5);
+        for state in states.iter().rev() {
+            assert_eq!(rewind.pop().as_ref(), Some(state));
+        }
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn oldest_states_go_over_budget() {
+        let mut rewind = Rewind::new(1, 100);
+        for n in 0..10u8 {
+            rewind.push(vec![n; 64]);
+        }
+        assert!(rewind.memory() <= 100);
+        assert_eq!(rewind.pop(), Some(vec![9; 64]));
+    }
+}
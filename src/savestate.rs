@@ -0,0 +1,196 @@
+// Save states: the whole machine serialized to a compact binary format.
+//
+// A state starts with a header
+//
+//     "RWST"     magic
+//     u16        format version
+//     u32        CRC-32 of the ROM, see `Ines::crc32`
+//
+// followed by the state of each chip, in the order `Nes::save_state`
+// writes them. Numbers are little-endian. A state is only loaded by
+// the same version of the format, for the same ROM.
+
+use error::Error;
+
+pub const MAGIC: &[u8; 4] = b"RWST";
+// bump when the layout of any chip changes
+pub const VERSION: u16 = 1;
+
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter { buf: Vec::new() }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        StateWriter::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, pos: 0 }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() - self.pos < len {
+            return Err(Error::new("truncated save state".to_string()));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+// Something that is part of a save state. `load` reads back exactly
+// what `save` wrote.
+pub trait State {
+    fn save(&self, w: &mut StateWriter);
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error>;
+}
+
+macro_rules! state_int {
+    ($($t:ty),+) => {
+        $(impl State for $t {
+            fn save(&self, w: &mut StateWriter) {
+                w.write_bytes(&self.to_le_bytes());
+            }
+
+            fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+                let mut bytes = [0; ::std::mem::size_of::<$t>()];
+                let len = bytes.len();
+                bytes.copy_from_slice(r.read_bytes(len)?);
+                *self = <$t>::from_le_bytes(bytes);
+                Ok(())
+            }
+        })+
+    };
+}
+
+state_int!(u8, u16, u32, u64, f32);
+
+// saved as 64 bits, whatever the host
+impl State for usize {
+    fn save(&self, w: &mut StateWriter) {
+        (*self as u64).save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        let mut value = 0u64;
+        value.load(r)?;
+        *self = value as usize;
+        Ok(())
+    }
+}
+
+impl State for bool {
+    fn save(&self, w: &mut StateWriter) {
+        (*self as u8).save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        let mut value = 0u8;
+        value.load(r)?;
+        *self = value != 0;
+        Ok(())
+    }
+}
+
+impl State for Option<u8> {
+    fn save(&self, w: &mut StateWriter) {
+        self.is_some().save(w);
+        self.unwrap_or(0).save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        let (mut some, mut value) = (false, 0u8);
+        some.load(r)?;
+        value.load(r)?;
+        *self = if some { Some(value) } else { None };
+        Ok(())
+    }
+}
+
+impl<const N: usize> State for [u8; N] {
+    fn save(&self, w: &mut StateWriter) {
+        w.write_bytes(self);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        self.copy_from_slice(r.read_bytes(N)?);
+        Ok(())
+    }
+}
+
+impl<const N: usize> State for [u32; N] {
+    fn save(&self, w: &mut StateWriter) {
+        for value in self.iter() {
+            value.save(w);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        for value in self.iter_mut() {
+            value.load(r)?;
+        }
+        Ok(())
+    }
+}
+
+// RAM and ROM sized by the cartridge, which the state has to agree with
+impl State for Vec<u8> {
+    fn save(&self, w: &mut StateWriter) {
+        (self.len() as u32).save(w);
+        w.write_bytes(self);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        let mut len = 0u32;
+        len.load(r)?;
+        if len as usize != self.len() {
+            return Err(Error::new(format!("save state has {} bytes of memory where {} are expected",
+                                          len, self.len())));
+        }
+        let len = self.len();
+        self.copy_from_slice(r.read_bytes(len)?);
+        Ok(())
+    }
+}
+
+// Implement `State` for a struct by saving the given fields in order.
+macro_rules! impl_state {
+    ($t:ty, $($field:ident),+) => {
+        impl ::savestate::State for $t {
+            fn save(&self, w: &mut ::savestate::StateWriter) {
+                $(::savestate::State::save(&self.$field, w);)+
+            }
+
+            fn load(&mut self, r: &mut ::savestate::StateReader) -> Result<(), ::error::Error> {
+                $(::savestate::State::load(&mut self.$field, r)?;)+
+                Ok(())
+            }
+        }
+    };
+}
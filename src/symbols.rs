@@ -0,0 +1,432 @@
+// Names of addresses and source lines, read from the files assemblers
+// and other emulators write:
+//
+//     game.dbg           ca65/ld65 debug info, --dbgfile
+//     game.nes.ram.nl    FCEUX name lists, RAM and registers
+//     game.nes.0.nl      ... and a file per 16 KB PRG ROM bank
+//     game.mlb           Mesen labels
+//
+// PRG ROM symbols are kept by offset in PRG ROM, so that banks sharing
+// an address each have their own.
+// https://cc65.github.io/doc/debugging.html
+// http://fceux.com/web/help/NLFilesFormat.html
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use cartridge::Cartridge;
+use disasm::Labels;
+use error::Error;
+
+const INES_HEADER_LEN: usize = 16;
+const BANK_SIZE: usize = 0x4000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RomSymbol {
+    name: String,
+    // the CPU address, when the file tells it
+    addr: Option<u16>,
+}
+
+#[derive(Default)]
+pub struct Symbols {
+    // RAM, registers and anything outside PRG ROM, by CPU address
+    addrs: HashMap<u16, String>,
+    // by offset in PRG ROM
+    prg: HashMap<usize, RomSymbol>,
+    // source file and line of the code at an offset in PRG ROM
+    lines: HashMap<usize, (usize, u32)>,
+    files: Vec<String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty() && self.prg.is_empty() && self.lines.is_empty()
+    }
+
+    pub fn add_addr(&mut self, addr: u16, name: &str) {
+        self.addrs.insert(addr, name.to_string());
+    }
+
+    pub fn add_prg(&mut self, offset: usize, addr: Option<u16>, name: &str) {
+        self.prg.insert(offset, RomSymbol { name: name.to_string(), addr });
+    }
+
+    // The name of a CPU address, `prg_offset` is where it is in PRG
+    // ROM, see `Cartridge::prg_offset`.
+    pub fn label(&self, addr: u16, prg_offset: Option<usize>) -> Option<&str> {
+        match prg_offset {
+            Some(offset) => self.prg.get(&offset).map(|s| s.name.as_str()),
+            None => self.addrs.get(&addr).map(|s| s.as_str()),
+        }
+    }
+
+    pub fn prg_label(&self, offset: usize) -> Option<&str> {
+        self.prg.get(&offset).map(|s| s.name.as_str())
+    }
+
+    // names of RAM and registers
+    pub fn addr_labels(&self) -> &HashMap<u16, String> {
+        &self.addrs
+    }
+
+    // "file.s:12" for the code at an offset in PRG ROM
+    pub fn source_line(&self, offset: usize) -> Option<String> {
+        self.lines.get(&offset).map(|&(file, line)| format!("{}:{}", self.files[file], line))
+    }
+
+    // Where a symbol is: the 16 KB bank for PRG ROM symbols, and the
+    // address. None if unknown or the CPU address is not known. A name
+    // in several banks is found in the lowest one.
+    pub fn find(&self, name: &str) -> Option<(Option<usize>, u16)> {
+        let rom = self.prg.iter()
+                          .filter(|&(_, s)| s.name == name)
+                          .filter_map(|(&offset, s)| s.addr.map(|addr| (offset, addr)))
+                          .min_by_key(|&(offset, _)| offset)
+                          .map(|(offset, addr)| (Some(offset / BANK_SIZE), addr));
+        rom.or_else(|| {
+            self.addrs.iter()
+                      .filter(|&(_, n)| n == name)
+                      .map(|(&addr, _)| addr)
+                      .min()
+                      .map(|addr| (None, addr))
+        })
+    }
+
+    // labels as seen by the CPU through the mapper
+    pub fn labels<'a>(&'a self, cartridge: &'a Cartridge) -> CartridgeLabels<'a> {
+        CartridgeLabels { symbols: self, cartridge }
+    }
+
+    // Load every symbol file found next to a ROM, see above.
+    pub fn for_rom<P: AsRef<Path>>(rom: P) -> Result<Self, Error> {
+        let rom = rom.as_ref();
+        let mut symbols = Symbols::new();
+        for extension in &["dbg", "mlb"] {
+            let path = rom.with_extension(extension);
+            if path.exists() {
+                symbols.load(&path)?;
+            }
+        }
+        // FCEUX appends to the whole file name
+        let name = rom.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let dir = match rom.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let file = entry.file_name().to_string_lossy().into_owned();
+                if file.starts_with(&format!("{}.", name)) && file.ends_with(".nl") {
+                    symbols.load(entry.path())?;
+                }
+            }
+        }
+        Ok(symbols)
+    }
+
+    // load a file, its format told by its extension
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| Error::new(format!("{}: {}", path.display(), e)))?;
+        let file = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let result = match path.extension().and_then(|e| e.to_str()) {
+            Some("dbg") => self.load_dbg(&text),
+            Some("mlb") => self.load_mlb(&text),
+            Some("nl") => {
+                // game.nes.ram.nl or game.nes.<bank in hex>.nl
+                let bank = file.trim_end_matches(".nl").rsplit('.').next().unwrap_or("");
+                if bank == "ram" {
+                    self.load_nl(&text, None)
+                }
+                else {
+                    usize::from_str_radix(bank, 16)
+                        .map_err(|_| Error::new("name list is neither for RAM nor a bank".to_string()))
+                        .and_then(|bank| self.load_nl(&text, Some(bank)))
+                }
+            }
+            _ => Err(Error::new("unknown kind of symbol file".to_string())),
+        };
+        result.map_err(|e| Error::new(format!("{}: {}", path.display(), e)))
+    }
+
+    // FCEUX name list: `$c000#name#comment`, `$0300/10#array#` names
+    // an array. `bank` is None for RAM.
+    pub fn load_nl(&mut self, text: &str, bank: Option<usize>) -> Result<(), Error> {
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let rest = match line.strip_prefix('$') {
+                Some(rest) => rest,
+                None => continue,
+            };
+            let mut fields = rest.split('#');
+            let addr = fields.next().unwrap_or("");
+            let addr = addr.split('/').next().unwrap_or("");
+            let addr = u16::from_str_radix(addr, 16)
+                           .map_err(|_| Error::new(format!("line {}: bad address", n + 1)))?;
+            let name = fields.next().unwrap_or("").trim();
+            if name.is_empty() {
+                continue;
+            }
+            match bank {
+                Some(bank) if addr >= 0x8000 => {
+                    let offset = bank * BANK_SIZE + (addr as usize & (BANK_SIZE - 1));
+                    self.add_prg(offset, Some(addr), name);
+                }
+                _ => self.add_addr(addr, name),
+            }
+        }
+        Ok(())
+    }
+
+    // Mesen labels: `P:1234:name:comment`, P for PRG ROM offsets, R for
+    // internal RAM, S and W for save and work RAM at $6000, G for
+    // registers. Mesen 2 spells them NesPrgRom, NesInternalRam...
+    pub fn load_mlb(&mut self, text: &str) -> Result<(), Error> {
+        for (n, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.trim().splitn(4, ':').collect();
+            if fields.len() < 3 || fields[2].is_empty() {
+                continue;
+            }
+            let start = fields[1].split('-').next().unwrap_or("");
+            let value = usize::from_str_radix(start, 16)
+                            .map_err(|_| Error::new(format!("line {}: bad address", n + 1)))?;
+            let name = fields[2];
+            match fields[0] {
+                "P" | "NesPrgRom" => self.add_prg(value, None, name),
+                "R" | "NesInternalRam" => self.add_addr(value as u16 & 0x7ff, name),
+                "S" | "W" | "NesSaveRam" | "NesWorkRam" => self.add_addr(0x6000 + (value as u16 & 0x1fff), name),
+                "G" | "NesMemory" => self.add_addr(value as u16, name),
+                // CHR, labels of other systems
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // ca65 debug info written by ld65 --dbgfile, lines of
+    // `kind<tab>key=value,key=value`
+    pub fn load_dbg(&mut self, text: &str) -> Result<(), Error> {
+        let mut files = HashMap::new();
+        let mut segs = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut syms = Vec::new();
+        let mut lines = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let mut parts = line.splitn(2, |c: char| c.is_whitespace());
+            let kind = parts.next().unwrap_or("");
+            let fields = dbg_fields(parts.next().unwrap_or(""));
+            let get = |key: &str| fields.get(key).map(|s| s.as_str());
+            let num = |key: &str| get(key).and_then(dbg_number);
+            let bad = || Error::new(format!("line {}: bad {}", n + 1, kind));
+            match kind {
+                "file" => {
+                    files.insert(num("id").ok_or_else(bad)?, get("name").ok_or_else(bad)?.to_string());
+                }
+                // start is the run address, ooffs where it is in the ROM file
+                "seg" => {
+                    let rom = get("type") == Some("ro");
+                    let ooffs = if rom { num("ooffs") } else { None };
+                    segs.insert(num("id").ok_or_else(bad)?, (num("start").ok_or_else(bad)?, ooffs));
+                }
+                "span" => {
+                    spans.insert(num("id").ok_or_else(bad)?, (num("seg").ok_or_else(bad)?, num("start").ok_or_else(bad)?));
+                }
+                "sym" if get("type") == Some("lab") => {
+                    if let (Some(name), Some(val)) = (get("name"), num("val")) {
+                        syms.push((name.to_string(), val, num("seg")));
+                    }
+                }
+                // type 2 lines are inside macros
+                "line" if num("type") != Some(2) => {
+                    if let (Some(file), Some(line), Some(span)) = (num("file"), num("line"), get("span")) {
+                        let c_source = num("type") == Some(1);
+                        for span in span.split('+').filter_map(dbg_number) {
+                            lines.push((span, file, line as u32, c_source));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // where a CPU address in a segment is in PRG ROM
+        let prg_offset = |seg: usize, addr: usize| -> Option<usize> {
+            match segs.get(&seg) {
+                Some(&(start, Some(ooffs))) if ooffs >= INES_HEADER_LEN && addr >= start =>
+                    Some(ooffs - INES_HEADER_LEN + addr - start),
+                _ => None,
+            }
+        };
+        for (name, val, seg) in syms {
+            match seg.and_then(|seg| prg_offset(seg, val)) {
+                Some(offset) => self.add_prg(offset, Some(val as u16), &name),
+                None => self.add_addr(val as u16, &name),
+            }
+        }
+
+        let mut file_index = HashMap::new();
+        for (span, file, line, c_source) in lines {
+            let offset = match spans.get(&span) {
+                Some(&(seg, start)) => segs.get(&seg).and_then(|&(seg_start, _)| prg_offset(seg, seg_start + start)),
+                None => None,
+            };
+            let (offset, name) = match (offset, files.get(&file)) {
+                (Some(offset), Some(name)) => (offset, name),
+                _ => continue,
+            };
+            // C lines win over the assembly generated for them
+            if self.lines.contains_key(&offset) && !c_source {
+                continue;
+            }
+            let files = &mut self.files;
+            let index = *file_index.entry(file).or_insert_with(|| {
+                files.push(name.clone());
+                files.len() - 1
+            });
+            self.lines.insert(offset, (index, line));
+        }
+        Ok(())
+    }
+}
+
+// `key=value,key="quoted, value"`
+fn dbg_fields(text: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let eq = match rest.find('=') {
+            Some(eq) => eq,
+            None => break,
+        };
+        let key = rest[..eq].to_string();
+        rest = &rest[eq + 1..];
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            rest = quoted.get(end + 1..).unwrap_or("");
+            quoted[..end].to_string()
+        }
+        else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let value = rest[..end].to_string();
+            rest = &rest[end..];
+            value
+        };
+        rest = rest.trim_start_matches(',');
+        fields.insert(key, value);
+    }
+    fields
+}
+
+// decimal, or hex with 0x
+fn dbg_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+pub struct CartridgeLabels<'a> {
+    symbols: &'a Symbols,
+    cartridge: &'a Cartridge,
+}
+
+impl<'a> Labels for CartridgeLabels<'a> {
+    fn label(&self, addr: u16) -> Option<&str> {
+        self.symbols.label(addr, self.cartridge.prg_offset(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_lists() {
+        let mut symbols = Symbols::new();
+        symbols.load_nl("$0010#temp#scratch\n$0300/10#buffer#\n$2000#PPUCTRL#\n", None).unwrap();
+        symbols.load_nl("$8000#reset#\n$C123#loop#inner\n# comment\n", Some(1)).unwrap();
+        assert_eq!(symbols.label(0x0010, None), Some("temp"));
+        assert_eq!(symbols.label(0x0300, None), Some("buffer"));
+        assert_eq!(symbols.label(0x2000, None), Some("PPUCTRL"));
+        assert_eq!(symbols.prg_label(0x4000), Some("reset"));
+        assert_eq!(symbols.prg_label(0x4123), Some("loop"));
+        assert_eq!(symbols.find("loop"), Some((Some(1), 0xc123)));
+        assert!(symbols.load_nl("$zz#bad#\n", None).is_err());
+    }
+
+    #[test]
+    fn mesen_labels() {
+        let mut symbols = Symbols::new();
+        let text = "P:0010:main:entry\nR:0812:temp\nS:0004-0007:save\nG:2002:PPUSTATUS\n\
+                    NesPrgRom:0020:nmi\nC:0000:tiles\nP:0030:\n";
+        symbols.load_mlb(text).unwrap();
+        assert_eq!(symbols.prg_label(0x10), Some("main"));
+        assert_eq!(symbols.prg_label(0x20), Some("nmi"));
+        assert_eq!(symbols.prg_label(0x30), None);
+        assert_eq!(symbols.label(0x0012, None), Some("temp"));
+        assert_eq!(symbols.label(0x6004, None), Some("save"));
+        assert_eq!(symbols.label(0x2002, None), Some("PPUSTATUS"));
+        // Mesen does not tell where PRG ROM labels are seen by the CPU
+        assert_eq!(symbols.find("main"), None);
+    }
+
+    #[test]
+    fn fields_with_quoted_commas() {
+        let fields = dbg_fields("id=0,name=\"a, b.s\",size=0x10");
+        assert_eq!(fields.get("id").map(|s| s.as_str()), Some("0"));
+        assert_eq!(fields.get("name").map(|s| s.as_str()), Some("a, b.s"));
+        assert_eq!(fields.get("size").map(|s| s.as_str()), Some("0x10"));
+        assert_eq!(dbg_number("0x10"), Some(16));
+        assert_eq!(dbg_number("16"), Some(16));
+        assert_eq!(dbg_number("x"), None);
+    }
+
+    #[test]
+    fn ca65_debug_info() {
+        let text = "version\tmajor=2,minor=0\n\
+                    file\tid=0,name=\"main, game.s\",size=100,mtime=0x5e000000,mod=0\n\
+                    seg\tid=0,name=\"CODE\",start=0x00C000,size=0x0010,addrsize=absolute,type=ro,oname=\"game.nes\",ooffs=16\n\
+                    seg\tid=1,name=\"ZEROPAGE\",start=0x000000,size=0x0002,addrsize=zeropage,type=rw\n\
+                    span\tid=0,seg=0,start=0,size=3\n\
+                    span\tid=1,seg=0,start=3,size=2\n\
+                    line\tid=0,file=0,line=12,span=0\n\
+                    line\tid=1,file=0,line=13,type=2,span=1\n\
+                    sym\tid=0,name=\"reset\",addrsize=absolute,scope=0,def=0,val=0xC000,seg=0,type=lab\n\
+                    sym\tid=1,name=\"loop\",addrsize=absolute,scope=0,def=0,val=0xC003,seg=0,type=lab\n\
+                    sym\tid=2,name=\"temp\",addrsize=zeropage,scope=0,def=0,val=0x01,seg=1,type=lab\n\
+                    sym\tid=3,name=\"SIZE\",addrsize=zeropage,scope=0,def=0,val=0x10,type=equ\n";
+        let mut symbols = Symbols::new();
+        symbols.load_dbg(text).unwrap();
+        assert_eq!(symbols.prg_label(0), Some("reset"));
+        assert_eq!(symbols.prg_label(3), Some("loop"));
+        assert_eq!(symbols.find("loop"), Some((Some(0), 0xc003)));
+        assert_eq!(symbols.label(0x0001, None), Some("temp"));
+        assert_eq!(symbols.find("SIZE"), None);
+        assert_eq!(symbols.source_line(0).as_deref(), Some("main, game.s:12"));
+        // inside a macro
+        assert_eq!(symbols.source_line(3), None);
+        assert!(symbols.load_dbg("file\tname=\"x.s\"\n").is_err());
+    }
+
+    #[test]
+    fn found_in_the_lowest_bank() {
+        let mut symbols = Symbols::new();
+        for bank in (0..8).rev() {
+            symbols.add_prg(bank * BANK_SIZE + 0x10, Some(0x8010), "bankswitch");
+        }
+        symbols.add_addr(0x0300, "twice");
+        symbols.add_addr(0x0200, "twice");
+        assert_eq!(symbols.find("bankswitch"), Some((Some(0), 0x8010)));
+        assert_eq!(symbols.find("twice"), Some((None, 0x0200)));
+        assert_eq!(symbols.find("nothing"), None);
+    }
+}
@@ -0,0 +1,146 @@
+// Klaus Dormann's functional tests, run on 64K of RAM until the CPU
+// traps in an infinite loop. The address of the trap tells whether a
+// test passed or where it failed.
+// https://github.com/Klaus2m5/6502_65C02_functional_tests
+//
+// The binaries are not part of the repository, so the tests are ignored
+// unless asked for. Put them in tests/klaus, or in the directory named by
+// $KLAUS_TESTS, and run
+//
+//     cargo test --test klaus -- --ignored
+//
+// with
+//
+//     6502_functional_test.bin    from bin_files
+//     6502_interrupt_test.bin     from the ca65 port, default configuration
+//
+// The success traps of those builds are known. Binaries assembled
+// otherwise need their listing next to them, e.g. 6502_interrupt_test.lst,
+// to tell where it is.
+// https://github.com/amb5l/6502_65C02_functional_tests
+
+extern crate redwhite;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use redwhite::cpu::{Cpu, Variant};
+use redwhite::mem::Access;
+
+// the tests are loaded at $0000 and started at $0400
+const START: u16 = 0x0400;
+
+// the functional test runs about 30 million
+const MAX_INSTRUCTIONS: usize = 100_000_000;
+
+// The feedback register of the interrupt test, wired to IRQ and NMI as
+// an open collector: a bit set asserts the line.
+const I_PORT: u16 = 0xbffc;
+const I_FILTER: u8 = 0x7f;
+const IRQ_BIT: u8 = 0x01;
+const NMI_BIT: u8 = 0x02;
+
+struct Ram {
+    bytes: Vec<u8>,
+    // the feedback register, for the interrupt test
+    port: Option<u8>,
+}
+
+impl Access for Ram {
+    fn read(&mut self, addr: u16) -> u8 {
+        match self.port {
+            Some(port) if addr == I_PORT => port,
+            _ => self.bytes[addr as usize],
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match self.port {
+            Some(_) if addr == I_PORT => self.port = Some(value & I_FILTER),
+            _ => self.bytes[addr as usize] = value,
+        }
+    }
+}
+
+fn binary_dir() -> PathBuf {
+    match env::var_os("KLAUS_TESTS") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("klaus"),
+    }
+}
+
+// The success trap, from the line of the listing that says so, e.g.
+//
+//     3469 : 4c6934          >        jmp *           ;test passed, no errors
+fn success_trap(name: &str, known: u16) -> u16 {
+    let path = binary_dir().join(format!("{}.lst", name));
+    let listing = match fs::read(&path) {
+        Ok(listing) => listing,
+        Err(_) => return known,
+    };
+    String::from_utf8_lossy(&listing)
+        .lines()
+        .find(|line| line.contains("test passed, no errors"))
+        .and_then(|line| line.get(..4))
+        .and_then(|addr| u16::from_str_radix(addr, 16).ok())
+        .unwrap_or_else(|| panic!("no success trap in {}", path.display()))
+}
+
+// Run until the PC stays the same after a step. Returns the trap address
+// and the number of instructions run.
+fn run(cpu: &mut Cpu<Ram>) -> (u16, usize) {
+    let mut nmi = false;
+    for count in 0..MAX_INSTRUCTIONS {
+        let pc = cpu.registers().pc;
+        cpu.step();
+        if cpu.registers().pc == pc {
+            return (pc, count);
+        }
+        // the lines follow the register for the next instruction, NMI
+        // on the rising edge
+        if let Some(port) = cpu.mem().port {
+            cpu.set_irq(port & IRQ_BIT != 0);
+            if port & NMI_BIT != 0 && !nmi {
+                cpu.trigger_nmi();
+            }
+            nmi = port & NMI_BIT != 0;
+        }
+    }
+    panic!("no trap after {} instructions, pc={:04x}", MAX_INSTRUCTIONS, cpu.registers().pc);
+}
+
+fn run_test(name: &str, variant: Variant, known_success: u16, port: bool) {
+    let path = binary_dir().join(format!("{}.bin", name));
+    let binary = fs::read(&path).unwrap_or_else(|e| {
+        panic!("{}: {}, see tests/klaus.rs for where to get it", path.display(), e)
+    });
+    let success = success_trap(name, known_success);
+
+    let mut bytes = vec![0; 0x10000];
+    let len = binary.len().min(bytes.len());
+    bytes[..len].copy_from_slice(&binary[..len]);
+    let mut cpu = Cpu::new(Ram { bytes, port: if port { Some(0) } else { None } });
+    cpu.set_variant(variant);
+    let mut regs = cpu.registers();
+    regs.pc = START;
+    cpu.set_registers(regs);
+
+    let (trap, count) = run(&mut cpu);
+    assert!(trap == success,
+            "{} failed: trapped at ${:04x} after {} instructions, {:?}",
+            name, trap, count, cpu.registers());
+    println!("{} passed: trapped at ${:04x} after {} instructions", name, trap, count);
+}
+
+// with decimal mode, which the 2A03 does not have
+#[test]
+#[ignore = "needs the test binaries, see above"]
+fn functional_test() {
+    run_test("6502_functional_test", Variant::Nmos6502, 0x3469, false);
+}
+
+#[test]
+#[ignore = "needs the test binaries, see above"]
+fn interrupt_test() {
+    run_test("6502_interrupt_test", Variant::Nmos6502, 0x06e8, true);
+}